@@ -0,0 +1,297 @@
+//! Caching of the text layouts and the icons of the blocks.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::rc::Rc;
+
+use pangocairo::cairo;
+use pangocairo::pango::FontDescription;
+
+use crate::i3bar_protocol::{Block, BlockRender, MinWidth};
+use crate::text::{self, ComputedText, Multiline};
+
+/// The space between the icon of a block and its text.
+const ICON_GAP: f64 = 4.0;
+
+/// SVG icons are rasterized at this multiple of their size, so that they stay sharp on outputs
+/// scaled up to this factor.
+const SVG_OVERSAMPLE: f64 = 2.0;
+
+/// Keeps the laid out text of the blocks, so that only the changed blocks are recomputed.
+#[derive(Default)]
+pub struct BlocksCache {
+    computed: Vec<ComputedBlock>,
+    multiline: Option<Multiline>,
+    /// Whether the text of the urgent blocks starts with an exclamation mark, see
+    /// [`UrgentMarker::Glyph`](crate::layout::UrgentMarker::Glyph).
+    urgent_glyph: bool,
+    /// The loaded icons by path and size, `None` if loading failed. Only the icons of the current
+    /// blocks are kept, so an icon file which changes is reloaded once no block uses it.
+    icons: HashMap<(Rc<str>, u32), Option<cairo::ImageSurface>>,
+}
+
+/// A block along with its laid out text.
+pub struct ComputedBlock {
+    pub block: Block,
+    pub full: ComputedText,
+    pub short: Option<ComputedText>,
+    pub min_width: Option<f64>,
+    /// The icon, drawn at the start of the block, and the height it is drawn at.
+    pub icon: Option<(cairo::ImageSurface, f64)>,
+    /// The filled fraction of the block, from 0 to 1, if it is drawn as a meter.
+    pub progress: Option<f64>,
+}
+
+impl BlocksCache {
+    /// Stack the lines of the blocks with several lines, if they fit.
+    pub fn with_multiline(multiline: Option<Multiline>) -> Self {
+        Self {
+            computed: Vec::new(),
+            multiline,
+            urgent_glyph: false,
+            icons: HashMap::new(),
+        }
+    }
+
+    /// Start the text of the urgent blocks with an exclamation mark. The blocks themselves are
+    /// not changed.
+    pub fn with_urgent_glyph(mut self, urgent_glyph: bool) -> Self {
+        self.urgent_glyph = urgent_glyph;
+        self
+    }
+
+    /// Replace the blocks with the new ones, reusing the layouts of the unchanged blocks.
+    pub fn process_new_blocks(&mut self, font: &FontDescription, blocks: Vec<Block>) {
+        // The icons are as tall as a line of text
+        let icon_size = text::line_height(font).round() as u32;
+        let mut icons = HashMap::new();
+        for path in blocks.iter().filter_map(|b| b.icon.as_ref()) {
+            let key = (path.clone(), icon_size);
+            if !icons.contains_key(&key) {
+                let icon = self
+                    .icons
+                    .remove(&key)
+                    .unwrap_or_else(|| load_icon(path, icon_size));
+                icons.insert(key, icon);
+            }
+        }
+        self.icons = icons;
+        let icons = &self.icons;
+        let icon_of = |block: &Block| {
+            let key = (block.icon.clone()?, icon_size);
+            Some((icons.get(&key)?.clone()?, icon_size as f64))
+        };
+
+        if blocks.len() != self.computed.len() {
+            self.computed = blocks
+                .into_iter()
+                .map(|b| {
+                    let icon = icon_of(&b);
+                    ComputedBlock::new(b, font, self.multiline, self.urgent_glyph, icon)
+                })
+                .collect();
+            return;
+        }
+
+        for (block, computed) in blocks.into_iter().zip(self.computed.iter_mut()) {
+            let icon = icon_of(&block);
+            computed.update(block, font, self.multiline, self.urgent_glyph, icon);
+        }
+    }
+
+    pub fn get_computed(&self) -> &[ComputedBlock] {
+        &self.computed
+    }
+}
+
+impl ComputedBlock {
+    fn new(
+        block: Block,
+        font: &FontDescription,
+        multiline: Option<Multiline>,
+        urgent_glyph: bool,
+        icon: Option<(cairo::ImageSurface, f64)>,
+    ) -> Self {
+        let icon_width = comp_icon_width(&block, icon.as_ref());
+        let mw = comp_min_width(&block, font).map(|mw| mw + icon_width);
+        let glyph = urgent_glyph && block.urgent;
+        Self {
+            full: comp_full(&block, mw, icon_width, font, multiline, glyph),
+            short: comp_short(&block, mw, icon_width, font, multiline, glyph),
+            min_width: mw,
+            icon,
+            progress: comp_progress(&block),
+            block,
+        }
+    }
+
+    fn update(
+        &mut self,
+        block: Block,
+        font: &FontDescription,
+        multiline: Option<Multiline>,
+        urgent_glyph: bool,
+        icon: Option<(cairo::ImageSurface, f64)>,
+    ) {
+        if block.min_width != self.block.min_width
+            || block.markup != self.block.markup
+            || (urgent_glyph && block.urgent != self.block.urgent)
+            || block.align != self.block.align
+            || block.full_text.is_empty() != self.block.full_text.is_empty()
+            || !same_icon(icon.as_ref(), self.icon.as_ref())
+        {
+            *self = ComputedBlock::new(block, font, multiline, urgent_glyph, icon);
+        } else {
+            let icon_width = comp_icon_width(&block, icon.as_ref());
+            let glyph = urgent_glyph && block.urgent;
+            if block.full_text != self.block.full_text {
+                self.full = comp_full(&block, self.min_width, icon_width, font, multiline, glyph);
+            }
+            if block.short_text != self.block.short_text {
+                self.short = comp_short(&block, self.min_width, icon_width, font, multiline, glyph);
+            }
+            self.progress = comp_progress(&block);
+            self.block = block;
+        }
+    }
+}
+
+fn same_icon(
+    a: Option<&(cairo::ImageSurface, f64)>,
+    b: Option<&(cairo::ImageSurface, f64)>,
+) -> bool {
+    match (a, b) {
+        (Some((a, _)), Some((b, _))) => a.to_raw_none() == b.to_raw_none(),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// Load the PNG or SVG icon at `path`, for a height of `size` pixels.
+fn load_icon(path: &str, size: u32) -> Option<cairo::ImageSurface> {
+    let loaded = if path.ends_with(".svg") {
+        load_svg(path, (size as f64 * SVG_OVERSAMPLE).ceil() as u32)
+    } else {
+        File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|mut file| {
+                cairo::ImageSurface::create_from_png(&mut file).map_err(|e| e.to_string())
+            })
+    };
+    loaded
+        .map_err(|e| warn!("failed to load the icon {path}: {e}"))
+        .ok()
+}
+
+/// Rasterize the SVG file at `path` with a height of `height` pixels.
+#[cfg(feature = "svg")]
+pub fn load_svg(path: &str, height: u32) -> Result<cairo::ImageSurface, String> {
+    let handle = rsvg::Loader::new()
+        .read_path(path)
+        .map_err(|e| e.to_string())?;
+    let renderer = rsvg::CairoRenderer::new(&handle);
+    let height = height as f64;
+    let width = match renderer.intrinsic_size_in_pixels() {
+        Some((w, h)) if w > 0.0 && h > 0.0 => (height * w / h).ceil(),
+        _ => height,
+    };
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32)
+        .map_err(|e| e.to_string())?;
+    let context = cairo::Context::new(&surface).map_err(|e| e.to_string())?;
+    renderer
+        .render_document(&context, &cairo::Rectangle::new(0.0, 0.0, width, height))
+        .map_err(|e| e.to_string())?;
+    drop(context);
+    Ok(surface)
+}
+
+#[cfg(not(feature = "svg"))]
+pub fn load_svg(_path: &str, _height: u32) -> Result<cairo::ImageSurface, String> {
+    Err("SVG icons require the svg feature".into())
+}
+
+fn comp_progress(block: &Block) -> Option<f64> {
+    match block.render {
+        BlockRender::Progress => Some(block.percent.unwrap_or(0.0).clamp(0.0, 100.0) / 100.0),
+        BlockRender::Text => None,
+    }
+}
+
+/// The space taken by the icon of `block`, including the gap before its text.
+fn comp_icon_width(block: &Block, icon: Option<&(cairo::ImageSurface, f64)>) -> f64 {
+    let Some((surface, height)) = icon else {
+        return 0.0;
+    };
+    let width = surface.width() as f64 * height / surface.height() as f64;
+    if block.full_text.is_empty() {
+        width
+    } else {
+        width + ICON_GAP
+    }
+}
+
+fn comp_min_width(block: &Block, font: &FontDescription) -> Option<f64> {
+    let markup = block.markup.as_deref() == Some("pango");
+    match &block.min_width {
+        Some(MinWidth::Pixels(p)) => Some(*p as f64),
+        Some(MinWidth::Text(t)) => Some(text::width_of(t, markup, font)),
+        None => None,
+    }
+}
+
+/// `text`, which starts with an exclamation mark if `glyph`.
+fn with_glyph(text: &str, glyph: bool) -> Cow<'_, str> {
+    if glyph {
+        Cow::Owned(format!("! {text}"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn comp_full(
+    block: &Block,
+    min_width: Option<f64>,
+    icon_width: f64,
+    font: &FontDescription,
+    multiline: Option<Multiline>,
+    glyph: bool,
+) -> ComputedText {
+    let markup = block.markup.as_deref() == Some("pango");
+    ComputedText::new_multiline(
+        &with_glyph(&block.full_text, glyph),
+        text::Attributes {
+            font,
+            padding_left: icon_width,
+            padding_right: 0.0,
+            min_width,
+            align: block.align,
+            markup,
+        },
+        multiline,
+    )
+}
+
+fn comp_short(
+    block: &Block,
+    min_width: Option<f64>,
+    icon_width: f64,
+    font: &FontDescription,
+    multiline: Option<Multiline>,
+    glyph: bool,
+) -> Option<ComputedText> {
+    let markup = block.markup.as_deref() == Some("pango");
+    block.short_text.as_ref().map(|short_text| {
+        text::ComputedText::new_multiline(
+            &with_glyph(short_text, glyph),
+            text::Attributes {
+                font,
+                padding_left: icon_width,
+                padding_right: 0.0,
+                min_width,
+                align: block.align,
+                markup,
+            },
+            multiline,
+        )
+    })
+}
@@ -0,0 +1,70 @@
+//! Mapping of click positions to elements.
+
+/// Horizontal ranges occupied by clickable elements.
+#[derive(Debug, Default)]
+pub struct ButtonManager<T = usize>(Vec<(f64, f64, T)>);
+
+impl<T> ButtonManager<T> {
+    pub fn push(&mut self, x_offset: f64, width: f64, elem: T) {
+        self.0.push((x_offset, width, elem));
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// The element under `x`, if any.
+    pub fn click(&self, x: f64) -> Option<&T> {
+        self.find(x).map(|(_, _, e)| e)
+    }
+
+    /// The element under `x` with its offset and width, if any.
+    pub fn find(&self, x: f64) -> Option<(f64, f64, &T)> {
+        self.0
+            .iter()
+            .find(|(x_off, w, _)| x >= *x_off && x <= *x_off + *w)
+            .map(|(x_off, w, e)| (*x_off, *w, e))
+    }
+
+    /// The index of the element under `x` in the order they were pushed, if any.
+    pub fn index_at(&self, x: f64) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|(x_off, w, _)| x >= *x_off && x <= *x_off + *w)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The offset and width of the `i`-th element, in the order they were pushed.
+    pub fn get(&self, i: usize) -> Option<(f64, f64)> {
+        self.0.get(i).map(|(x_off, w, _)| (*x_off, *w))
+    }
+
+    /// The offset and width of `elem`, if it is present.
+    pub fn position_of(&self, elem: &T) -> Option<(f64, f64)>
+    where
+        T: PartialEq,
+    {
+        self.0
+            .iter()
+            .find(|(_, _, e)| e == elem)
+            .map(|(x_off, w, _)| (*x_off, *w))
+    }
+
+    /// Whether `x` lies between the leftmost and the rightmost elements.
+    pub fn is_between(&self, x: f64) -> bool {
+        let mut left = false;
+        let mut right = false;
+        for &(x_off, w, _) in &self.0 {
+            left |= x_off <= x;
+            right |= x_off + w >= x;
+        }
+        left && right
+    }
+}
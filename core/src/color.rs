@@ -0,0 +1,174 @@
+//! RGBA colors, parsed from `#rrggbb` or `#rrggbbaa`.
+
+use pangocairo::cairo::Context;
+use serde::{de, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    red: f64,
+    green: f64,
+    blue: f64,
+    alpha: f64,
+}
+
+impl Color {
+    /// Use this color as the source of the cairo context.
+    pub fn apply(self, cr: &Context) {
+        cr.set_source_rgba(self.red, self.green, self.blue, self.alpha);
+    }
+
+    pub fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self {
+            red: r as f64 / 255.0,
+            green: g as f64 / 255.0,
+            blue: b as f64 / 255.0,
+            alpha: a as f64 / 255.0,
+        }
+    }
+
+    pub fn to_rgba8(self) -> [u8; 4] {
+        let to_u8 = |x: f64| (x * 255.0).round() as u8;
+        [
+            to_u8(self.red),
+            to_u8(self.green),
+            to_u8(self.blue),
+            to_u8(self.alpha),
+        ]
+    }
+
+    pub fn from_rgba_hex(hex: u32) -> Self {
+        let r = (hex >> 24) as u8;
+        let g = (hex >> 16) as u8;
+        let b = (hex >> 8) as u8;
+        let a = hex as u8;
+        Self::from_rgba(r, g, b, a)
+    }
+
+    /// The color `t` of the way from `self` to `other`, with `t` from 0 to 1.
+    pub fn mix(self, other: Self, t: f64) -> Self {
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        Self {
+            red: lerp(self.red, other.red),
+            green: lerp(self.green, other.green),
+            blue: lerp(self.blue, other.blue),
+            alpha: lerp(self.alpha, other.alpha),
+        }
+    }
+
+    /// The relative luminance as defined by WCAG, ignoring the alpha.
+    pub fn luminance(self) -> f64 {
+        let linear = |c: f64| {
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.red) + 0.7152 * linear(self.green) + 0.0722 * linear(self.blue)
+    }
+
+    /// The WCAG contrast ratio between the two colors, from 1 to 21.
+    pub fn contrast_ratio(self, other: Self) -> f64 {
+        let (l1, l2) = (self.luminance(), other.luminance());
+        (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05)
+    }
+
+    /// This color if it is readable on `bg`, otherwise black or white, whichever contrasts more.
+    pub fn readable_on(self, bg: Self) -> Self {
+        const MIN_CONTRAST: f64 = 4.5;
+        if self.contrast_ratio(bg) >= MIN_CONTRAST {
+            return self;
+        }
+        let black = Self::from_rgba_hex(0x000000ff);
+        let white = Self::from_rgba_hex(0xffffffff);
+        if black.contrast_ratio(bg) > white.contrast_ratio(bg) {
+            black
+        } else {
+            white
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ();
+
+    fn from_str(color: &str) -> Result<Self, Self::Err> {
+        let rgb = color.get(1..7).ok_or(())?;
+        let rgb = u32::from_str_radix(rgb, 16).map_err(|_| ())?;
+        let r = (rgb >> 16) as u8;
+        let g = (rgb >> 8) as u8;
+        let b = rgb as u8;
+
+        let a = match color.get(7..9) {
+            Some(a) => u8::from_str_radix(a, 16).map_err(|_| ())?,
+            None => 255,
+        };
+
+        Ok(Self::from_rgba(r, g, b, a))
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let [r, g, b, a] = self.to_rgba8();
+        serializer.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct ColorVisitor;
+
+        impl de::Visitor<'_> for ColorVisitor {
+            type Value = Color;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("RBG or RGBA color (in hex)")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                s.parse()
+                    .map_err(|_| E::custom(format!("'{s}' is not a valid RGB/RGBA color")))
+            }
+        }
+
+        deserializer.deserialize_str(ColorVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readable_on() {
+        let white = Color::from_rgba_hex(0xffffffff);
+        let black = Color::from_rgba_hex(0x000000ff);
+        let yellow = Color::from_rgba_hex(0xfabd2fff);
+        let dark = Color::from_rgba_hex(0x282828ff);
+        assert!((white.contrast_ratio(black) - 21.0).abs() < 1e-9);
+        assert_eq!(white.readable_on(yellow), black);
+        assert_eq!(white.readable_on(dark), white);
+        assert_eq!(dark.readable_on(yellow), dark);
+    }
+
+    #[test]
+    fn mix() {
+        let black = Color::from_rgba_hex(0x000000ff);
+        let white = Color::from_rgba_hex(0xffffff00);
+        assert_eq!(black.mix(white, 0.0), black);
+        assert_eq!(black.mix(white, 1.0), white);
+        assert_eq!(black.mix(white, 0.5).to_rgba8(), [128, 128, 128, 128]);
+    }
+}
@@ -0,0 +1,437 @@
+//! The [i3bar protocol](https://i3wm.org/docs/i3bar-protocol.html), with fallbacks to plain
+//! text and lemonbar.
+
+use crate::color::Color;
+use crate::lemonbar;
+use crate::plain_text;
+use crate::pointer_btn::PointerBtn;
+use crate::text::Align;
+use crate::utils::{de_first_json, last_line};
+use serde::{de, Deserialize, Serialize, Serializer};
+use std::io::{self, Error, ErrorKind};
+use std::rc::Rc;
+
+/// A block, as sent by the status command.
+///
+/// The strings are reference counted, so that the blocks and the positions of their buttons can be
+/// cloned on every update and every frame without copying the text.
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct Block {
+    pub full_text: Rc<str>,
+    #[serde(default)]
+    pub short_text: Option<Rc<str>>,
+    #[serde(default)]
+    pub color: Option<Color>,
+    #[serde(default)]
+    pub background: Option<Color>,
+    #[serde(default)]
+    pub min_width: Option<MinWidth>,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub urgent: bool,
+    #[serde(default)]
+    pub name: Option<Rc<str>>,
+    #[serde(default)]
+    pub instance: Option<Rc<str>>,
+    #[serde(default = "def_sep")]
+    pub separator: bool,
+    #[serde(default = "def_sep_width")]
+    pub separator_block_width: u8,
+    #[serde(default)]
+    pub markup: Option<Rc<str>>,
+    /// The path to a PNG (or, with the `svg` feature, SVG) image shown before the text.
+    #[serde(default)]
+    pub icon: Option<Rc<str>>,
+    /// Shown in a popup while the pointer rests on the block.
+    #[serde(default)]
+    pub tooltip: Option<Rc<str>>,
+    #[serde(default)]
+    pub render: BlockRender,
+    /// How full the meter is, from 0 to 100, when `render` is `progress`.
+    #[serde(default)]
+    pub percent: Option<f64>,
+}
+
+/// How a block is drawn.
+#[derive(Deserialize, Serialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockRender {
+    /// Just the text on the background. Unknown modes fall back to it.
+    #[default]
+    #[serde(other)]
+    Text,
+    /// A meter: the background is filled from the left up to `percent`, behind the text.
+    Progress,
+}
+
+fn def_sep() -> bool {
+    true
+}
+
+fn def_sep_width() -> u8 {
+    9
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MinWidth {
+    Text(String),
+    Pixels(u64),
+}
+
+/// A click event, sent to the status command.
+#[derive(Serialize, Default)]
+pub struct Event<'a> {
+    pub name: Option<&'a str>,
+    pub instance: Option<&'a str>,
+    pub button: PointerBtn,
+    /// `"drop"` when data is dropped onto the block, `"hover"` when the pointer rests on it,
+    /// omitted for clicks.
+    #[serde(rename = "event", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<&'a str>,
+    /// The MIME type and the content of the dropped data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<&'a str>,
+    // Not available on wayland
+    pub modifiers: Vec<()>,
+    /// The pointer in the compositor space, in logical pixels.
+    pub x: i32,
+    pub y: i32,
+    /// The pointer relative to the top left corner of the block.
+    pub relative_x: i32,
+    pub relative_y: i32,
+    /// The pointer relative to the top left corner of the output.
+    pub output_x: i32,
+    pub output_y: i32,
+    /// The size of the block.
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct JsonHeader {
+    version: u8,
+    #[serde(default)]
+    stop_signal: i32,
+    #[serde(default)]
+    cont_signal: i32,
+    #[serde(default)]
+    click_events: bool,
+}
+
+/// The parser of the status command output. Start with [`Protocol::Unknown`] to detect the
+/// protocol automatically.
+#[derive(Debug)]
+pub enum Protocol {
+    Unknown,
+    PlainText {
+        pending_line: Option<String>,
+    },
+    JsonNotStarted {
+        header: JsonHeader,
+    },
+    Json {
+        header: JsonHeader,
+        pending_blocks: Option<Vec<Block>>,
+        /// The blocks of the array being received, deserialized one at a time as they arrive.
+        partial_blocks: Option<Vec<Block>>,
+    },
+    Lemonbar {
+        pending_line: Option<String>,
+        actions: lemonbar::Actions,
+    },
+}
+
+impl Protocol {
+    /// Extract new data from `bytes`, return unused bytes.
+    pub fn process_new_bytes<'a>(&mut self, bytes: &'a [u8]) -> io::Result<&'a [u8]> {
+        match self {
+            Self::Unknown => match de_first_json::<JsonHeader>(bytes) {
+                Ok((Some(header), rem)) if header.version == 1 => {
+                    debug!("i3bar protocol detected: {header:?}");
+                    *self = Self::JsonNotStarted { header };
+                    self.process_new_bytes(rem)
+                }
+                Ok((Some(header), _)) => Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Protocol version {} is not supported", header.version),
+                )),
+                _ => {
+                    debug!("plain text protocol detected");
+                    *self = Self::PlainText { pending_line: None };
+                    self.process_new_bytes(bytes)
+                }
+            },
+            Self::PlainText { pending_line } | Self::Lemonbar { pending_line, .. } => {
+                match last_line(bytes) {
+                    Some((new_line, rem)) => {
+                        *pending_line = Some(String::from_utf8_lossy(new_line).into());
+                        Ok(rem)
+                    }
+                    None => Ok(bytes),
+                }
+            }
+            Self::JsonNotStarted { header } => match bytes.trim_ascii_start() {
+                [] => Ok(&[]),
+                [b'[', rem @ ..] => {
+                    *self = Self::Json {
+                        header: *header,
+                        pending_blocks: None,
+                        partial_blocks: None,
+                    };
+                    self.process_new_bytes(rem)
+                }
+                [other, ..] => Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid json: expected '[', got '{}'", *other as char),
+                )),
+            },
+            Self::Json {
+                pending_blocks,
+                partial_blocks,
+                ..
+            } => {
+                let mut bytes = bytes;
+                loop {
+                    bytes = skip_separators(bytes);
+                    match (&mut *partial_blocks, bytes) {
+                        (_, []) => return Ok(bytes),
+                        (None, [b'[', rem @ ..]) => {
+                            *partial_blocks = Some(Vec::new());
+                            bytes = rem;
+                        }
+                        (None, [other, ..]) => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!("invalid json: expected '[', got '{}'", *other as char),
+                            ))
+                        }
+                        (Some(_), [b']', rem @ ..]) => {
+                            let blocks = partial_blocks.take().unwrap();
+                            trace!("received {} blocks", blocks.len());
+                            *pending_blocks = Some(blocks);
+                            bytes = rem;
+                        }
+                        (Some(blocks), _) => match de_first_json(bytes) {
+                            Ok((Some(block), rem)) => {
+                                blocks.push(block);
+                                bytes = rem;
+                            }
+                            // The block is not complete yet
+                            Ok((None, rem)) => return Ok(rem),
+                            Err(e) => {
+                                return Err(Error::new(
+                                    ErrorKind::InvalidData,
+                                    format!("invalid json: {e}"),
+                                ))
+                            }
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the new blocks, if any.
+    ///
+    /// In plain text mode, each line is split into multiple blocks by `plain_text_separator`, if
+    /// it is given. Such blocks are named sequentially, starting from `"0"`.
+    pub fn get_blocks(&mut self, plain_text_separator: Option<&str>) -> Option<Vec<Block>> {
+        match self {
+            Self::Unknown | Self::JsonNotStarted { .. } => None,
+            Self::PlainText { pending_line, .. } => {
+                let line = pending_line.take()?;
+                Some(match plain_text_separator {
+                    Some(sep) if !sep.is_empty() => line
+                        .split(sep)
+                        .enumerate()
+                        .map(|(i, text)| plain_text_block(text, Some(i.to_string().into())))
+                        .collect(),
+                    _ => vec![plain_text_block(&line, None)],
+                })
+            }
+            Self::Json { pending_blocks, .. } => pending_blocks.take(),
+            Self::Lemonbar {
+                pending_line,
+                actions,
+            } => {
+                let (blocks, new_actions) = lemonbar::parse(&pending_line.take()?);
+                *actions = new_actions;
+                Some(blocks)
+            }
+        }
+    }
+
+    /// Get the command bound to a clickable area. Only available in lemonbar mode.
+    pub fn lemonbar_action(&self, name: &str, button: PointerBtn) -> Option<&str> {
+        match self {
+            Self::Lemonbar { actions, .. } => actions
+                .get(name)?
+                .iter()
+                .rev()
+                .find(|(btn, _)| *btn == button)
+                .map(|(_, cmd)| cmd.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn supports_clicks(&self) -> bool {
+        match self {
+            Self::JsonNotStarted { header } | Self::Json { header, .. } => header.click_events,
+            _ => false,
+        }
+    }
+
+    /// The signals which pause and resume the status command, as requested in its header. `0`
+    /// stands for the defaults, `SIGSTOP` and `SIGCONT`.
+    pub fn stop_cont_signals(&self) -> (i32, i32) {
+        match self {
+            Self::JsonNotStarted { header } | Self::Json { header, .. } => {
+                (header.stop_signal, header.cont_signal)
+            }
+            _ => (0, 0),
+        }
+    }
+}
+
+/// Skip the whitespace and the commas between the arrays and between the blocks.
+fn skip_separators(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|&b| b != b',' && !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// Create a block from a piece of plain text, translating the color escapes into pango markup.
+fn plain_text_block(text: &str, name: Option<Rc<str>>) -> Block {
+    match plain_text::to_markup(text) {
+        Some(markup) => Block {
+            full_text: markup.into(),
+            markup: Some("pango".into()),
+            name,
+            ..Default::default()
+        },
+        None => Block {
+            full_text: text.into(),
+            name,
+            ..Default::default()
+        },
+    }
+}
+
+impl Serialize for MinWidth {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Text(text) => serializer.serialize_str(text),
+            Self::Pixels(pixels) => serializer.serialize_u64(*pixels),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MinWidth {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MinWidthVisitor;
+
+        impl de::Visitor<'_> for MinWidthVisitor {
+            type Value = MinWidth;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("positive integer or string")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(MinWidth::Text(v.to_owned()))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(MinWidth::Text(v))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(MinWidth::Pixels(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(MinWidth::Pixels(
+                    v.try_into().map_err(|_| E::custom("invalid min_width"))?,
+                ))
+            }
+        }
+
+        deserializer.deserialize_any(MinWidthVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(blocks: Option<Vec<Block>>) -> Option<Vec<String>> {
+        blocks.map(|blocks| {
+            blocks
+                .into_iter()
+                .map(|b| b.full_text.to_string())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn json_blocks_in_chunks() {
+        let input = br#"[
+[{"full_text":"a"},{"full_text":"b"}],
+[],
+[{"full_text":"c"}, {"full_text":"d"}],"#;
+        // Whatever the chunks, at most one incomplete block is left unused
+        for chunk_len in [1, 7, input.len()] {
+            let mut protocol = Protocol::Unknown;
+            protocol.process_new_bytes(b"{\"version\":1}\n").unwrap();
+            let mut buf = Vec::new();
+            for chunk in input.chunks(chunk_len) {
+                buf.extend_from_slice(chunk);
+                let rem = protocol.process_new_bytes(&buf).unwrap();
+                buf = rem.to_vec();
+                assert!(buf.len() < r#"{"full_text":"c"}"#.len());
+            }
+            assert!(buf.is_empty());
+            assert_eq!(
+                texts(protocol.get_blocks(None)),
+                Some(vec!["c".into(), "d".into()])
+            );
+        }
+
+        let mut protocol = Protocol::Unknown;
+        let rem = protocol
+            .process_new_bytes(br#"{"version":1} [ [{"full_text":"a"}], [{"full_text":"b"#)
+            .unwrap();
+        assert_eq!(rem, br#"{"full_text":"b"#);
+        assert_eq!(texts(protocol.get_blocks(None)), Some(vec!["a".into()]));
+        assert_eq!(texts(protocol.get_blocks(None)), None);
+
+        let mut protocol = Protocol::Unknown;
+        assert!(protocol
+            .process_new_bytes(br#"{"version":1} [ [{"full_text":"a"} invalid"#)
+            .is_err());
+    }
+}
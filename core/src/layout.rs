@@ -0,0 +1,461 @@
+//! Layout of the blocks on the bar.
+
+use std::rc::Rc;
+
+use pangocairo::cairo;
+use serde::Deserialize;
+
+use crate::blocks_cache::ComputedBlock;
+use crate::button_manager::ButtonManager;
+use crate::color::Color;
+use crate::i3bar_protocol::Block;
+use crate::text::{rounded_rectangle, Align, ComputedText, RenderOptions};
+
+/// How urgent tags and blocks are marked, in addition to their colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrgentMarker {
+    /// A stripe along the bottom edge.
+    Stripe,
+    /// A thick outline.
+    Outline,
+    /// An exclamation mark before the text, added when the text is laid out.
+    Glyph,
+}
+
+/// The width of the stripes and outlines of [`UrgentMarker`].
+const URGENT_MARKER_WIDTH: f64 = 2.0;
+
+/// Draw `marker` over the element at `x` of width `width`.
+fn draw_urgent_marker(
+    context: &cairo::Context,
+    marker: UrgentMarker,
+    color: Color,
+    x: f64,
+    width: f64,
+    full_height: f64,
+) {
+    color.apply(context);
+    match marker {
+        UrgentMarker::Stripe => {
+            context.rectangle(
+                x,
+                full_height - URGENT_MARKER_WIDTH,
+                width,
+                URGENT_MARKER_WIDTH,
+            );
+            context.fill().unwrap();
+        }
+        UrgentMarker::Outline => {
+            let inset = URGENT_MARKER_WIDTH * 0.5;
+            context.set_line_width(URGENT_MARKER_WIDTH);
+            context.rectangle(
+                x + inset,
+                inset,
+                width - URGENT_MARKER_WIDTH,
+                full_height - URGENT_MARKER_WIDTH,
+            );
+            context.stroke().unwrap();
+        }
+        UrgentMarker::Glyph => (),
+    }
+}
+
+/// Draw `icon` scaled to `height` at `x`, centered vertically.
+fn draw_icon(
+    context: &cairo::Context,
+    icon: &cairo::ImageSurface,
+    height: f64,
+    x: f64,
+    full_height: f64,
+) {
+    let scale = height / icon.height() as f64;
+    context.save().unwrap();
+    context.translate(x, (full_height - height) * 0.5);
+    context.scale(scale, scale);
+    context.set_source_surface(icon, 0.0, 0.0).unwrap();
+    context.source().set_filter(cairo::Filter::Good);
+    context.paint().unwrap();
+    context.restore().unwrap();
+}
+
+/// The colors of a tag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorPair {
+    pub bg: Color,
+    pub fg: Color,
+}
+
+/// A tag with its label laid out.
+#[derive(Debug)]
+pub struct ComputedTag {
+    /// Identifies the tag in the [`ButtonManager`] filled by [`render_tags`].
+    pub id: u32,
+    pub colors: ColorPair,
+    pub label: ComputedText,
+    pub urgent: bool,
+}
+
+/// Appearance of the tags.
+#[derive(Debug, Clone, Copy)]
+pub struct TagsStyle {
+    /// The radius of the rounded corners of the tags.
+    pub tags_r: f64,
+    /// The gap between the tags, unless they have the same colors.
+    pub tags_margin: f64,
+    pub urgent_marker: Option<UrgentMarker>,
+}
+
+/// Appearance of the blocks.
+#[derive(Debug, Clone, Copy)]
+pub struct BlocksStyle {
+    /// The default text color.
+    pub color: Color,
+    /// The color of the separators.
+    pub separator: Color,
+    /// The width of the separators, `0` to disable them.
+    pub separator_width: f64,
+    /// The radius of the rounded corners of the blocks.
+    pub blocks_r: f64,
+    /// How much the blocks overlap, see `blocks_overlap` in README.
+    pub blocks_overlap: f64,
+    /// Extra space between logical blocks, the separators are drawn in the middle of it.
+    pub blocks_margin: f64,
+    /// The background drawn behind each logical block.
+    pub group_bg: Option<Color>,
+    /// The space between the edges of a logical block and its first and last blocks.
+    pub group_padding: f64,
+    /// Replace the default text color with black or white if it is not readable on the
+    /// background of a block.
+    pub auto_contrast: bool,
+    /// Replace any text color which is not readable on its background with black or white.
+    pub high_contrast: bool,
+    /// The background of the bar, behind the blocks without a background.
+    pub background: Color,
+    /// The filled part of the blocks drawn as meters.
+    pub progress_bg: Color,
+    pub urgent_marker: Option<UrgentMarker>,
+}
+
+impl BlocksStyle {
+    /// The color of the text of `block` on `bg`.
+    fn fg(&self, block: &Block, bg: Option<Color>) -> Color {
+        let fg = block.color.unwrap_or(self.color);
+        match bg {
+            _ if self.high_contrast => fg.readable_on(bg.unwrap_or(self.background)),
+            Some(bg) if self.auto_contrast && block.color.is_none() => fg.readable_on(bg),
+            _ => fg,
+        }
+    }
+}
+
+/// The width [`render_tags`] takes to render `tags`.
+pub fn tags_width(tags: &[ComputedTag], style: &TagsStyle) -> f64 {
+    let mut width = 0.0;
+    for (i, tag) in tags.iter().enumerate() {
+        if i != 0 && tags[i - 1].colors != tag.colors {
+            width += style.tags_margin;
+        }
+        width += tag.label.width;
+    }
+    width
+}
+
+/// Render the tags from left to right, starting at `offset_left`. Adjacent tags of the same colors
+/// are joined. `buttons` are filled with the positions of the tags. Returns the offset right after
+/// the last tag.
+pub fn render_tags(
+    context: &cairo::Context,
+    tags: &[ComputedTag],
+    style: &TagsStyle,
+    buttons: &mut ButtonManager<u32>,
+    mut offset_left: f64,
+    full_height: f64,
+) -> f64 {
+    buttons.clear();
+    for (i, tag) in tags.iter().enumerate() {
+        let left_joined = i != 0 && tags[i - 1].colors == tag.colors;
+        let right_joined = i + 1 != tags.len() && tags[i + 1].colors == tag.colors;
+        if i != 0 && !left_joined {
+            offset_left += style.tags_margin;
+        }
+        tag.label.render(
+            context,
+            RenderOptions {
+                x_offset: offset_left,
+                bar_height: full_height,
+                fg_color: tag.colors.fg,
+                bg_color: Some(tag.colors.bg),
+                r_left: if left_joined { 0.0 } else { style.tags_r },
+                r_right: if right_joined { 0.0 } else { style.tags_r },
+                overlap: 0.0,
+                progress: None,
+            },
+        );
+        if let (true, Some(marker)) = (tag.urgent, style.urgent_marker) {
+            draw_urgent_marker(
+                context,
+                marker,
+                tag.colors.fg,
+                offset_left,
+                tag.label.width,
+                full_height,
+            );
+        }
+        buttons.push(offset_left, tag.label.width, tag.id);
+        offset_left += tag.label.width;
+    }
+    offset_left
+}
+
+/// The end of the logical block starting at `start`: the following blocks of the same name which are
+/// not separated from it.
+fn series_end(blocks: &[ComputedBlock], start: usize) -> usize {
+    let mut end = start + 1;
+    let series_name = &blocks[start].block.name;
+    while end < blocks.len()
+        && blocks[end - 1].block.separator_block_width == 0
+        && &blocks[end].block.name == series_name
+    {
+        end += 1;
+    }
+    end
+}
+
+/// The logical blocks as `(start, end, width)`, where `width` is the width of all the blocks up to
+/// `end` with their full text.
+fn series_widths<'a>(
+    style: &'a BlocksStyle,
+    blocks: &'a [ComputedBlock],
+) -> impl Iterator<Item = (usize, usize, f64)> + 'a {
+    let mut width = 0.0;
+    let mut s_start = 0;
+    std::iter::from_fn(move || {
+        if s_start >= blocks.len() {
+            return None;
+        }
+        let s_end = series_end(blocks, s_start);
+        if s_start != 0 {
+            let prev = &blocks[s_start - 1].block;
+            width += prev.separator_block_width as f64 + style.blocks_margin;
+        }
+        width += style.group_padding * 2.0;
+        width += blocks[s_start..s_end]
+            .iter()
+            .map(|comp| comp.full.width)
+            .sum::<f64>();
+        let series = (s_start, s_end, width);
+        s_start = s_end;
+        Some(series)
+    })
+}
+
+/// The width of the blocks with their full text.
+pub fn blocks_width(style: &BlocksStyle, blocks: &[ComputedBlock]) -> f64 {
+    series_widths(style, blocks)
+        .last()
+        .map_or(0.0, |(_, _, width)| width)
+}
+
+/// Split the blocks into two rows if they do not fit in `first_row_width` with their full text.
+///
+/// Returns the index of the first block of the second row. The first row gets as many logical
+/// blocks as fit, but at least one.
+pub fn split_blocks(
+    style: &BlocksStyle,
+    blocks: &[ComputedBlock],
+    first_row_width: f64,
+) -> Option<usize> {
+    let (s_start, s_end, _) =
+        series_widths(style, blocks).find(|&(_, _, w)| w > first_row_width)?;
+    let split = if s_start == 0 { s_end } else { s_start };
+    (split < blocks.len()).then_some(split)
+}
+
+/// Render the blocks between `offset_left` and `offset_right`, aligned according to `align`.
+///
+/// If the blocks do not fit, the series with the largest difference between the full and the
+/// short text are switched to the short text first. `buttons` are filled with the positions of the
+/// rendered blocks, identified by their names and instances.
+///
+/// Returns the blocks which are not shown in full: those rendered with their short text, and those
+/// which are cut off at the edges.
+#[allow(clippy::too_many_arguments)]
+pub fn render_blocks(
+    context: &cairo::Context,
+    style: &BlocksStyle,
+    blocks: &[ComputedBlock],
+    buttons: &mut ButtonManager<(Option<Rc<str>>, Option<Rc<str>>)>,
+    offset_left: f64,
+    offset_right: f64,
+    align: Align,
+    full_height: f64,
+) -> Vec<(Option<Rc<str>>, Option<Rc<str>>)> {
+    let available_width = offset_right - offset_left;
+    context.rectangle(offset_left, 0.0, available_width, full_height);
+    context.clip();
+
+    struct LogialBlock<'a> {
+        blocks: Vec<&'a ComputedBlock>,
+        delta: f64,
+        switched_to_short: bool,
+        separator: bool,
+        separator_block_width: u8,
+    }
+
+    let mut blocks_computed = Vec::new();
+    let mut blocks_width = 0.0;
+    let mut s_start = 0;
+    while s_start < blocks.len() {
+        let s_end = series_end(blocks, s_start);
+
+        let mut series = LogialBlock {
+            blocks: Vec::with_capacity(s_end - s_start),
+            delta: 0.0,
+            switched_to_short: false,
+            separator: blocks[s_end - 1].block.separator,
+            separator_block_width: blocks[s_end - 1].block.separator_block_width,
+        };
+
+        for comp in &blocks[s_start..s_end] {
+            blocks_width += comp.full.width;
+            if let Some(short) = &comp.short {
+                series.delta += comp.full.width - short.width;
+            }
+            series.blocks.push(comp);
+        }
+        if s_end != blocks.len() {
+            blocks_width += series.separator_block_width as f64 + style.blocks_margin;
+        }
+        blocks_width += style.group_padding * 2.0;
+        blocks_computed.push(series);
+        s_start = s_end;
+    }
+
+    // Progressively switch to short mode
+    if blocks_width > available_width {
+        let mut deltas: Vec<_> = blocks_computed
+            .iter()
+            .map(|b| b.delta)
+            .enumerate()
+            .filter(|(_, delta)| *delta > 0.0)
+            .collect();
+        // Sort in descending order
+        deltas.sort_unstable_by(|(_, d1), (_, d2)| d2.total_cmp(d1));
+        for (to_switch, delta) in deltas {
+            blocks_computed[to_switch].switched_to_short = true;
+            blocks_width -= delta;
+            if blocks_width <= available_width {
+                break;
+            }
+        }
+    }
+
+    // Remove all the empty blocks
+    for s in &mut blocks_computed {
+        s.blocks.retain(|text| {
+            (s.switched_to_short
+                && text
+                    .short
+                    .as_ref()
+                    .map_or(text.full.width > 0.0, |s| s.width > 0.0))
+                || (!s.switched_to_short && text.full.width > 0.0)
+        });
+        if s.blocks.is_empty() {
+            blocks_width -= style.group_padding * 2.0;
+        }
+    }
+
+    // Render blocks
+    buttons.clear();
+    let mut truncated = Vec::new();
+    let mut x = match align {
+        Align::Left => offset_left,
+        Align::Right => offset_right - blocks_width,
+        Align::Center => offset_left + (available_width - blocks_width) * 0.5,
+    };
+    let mut j = 0;
+    for series in blocks_computed {
+        let s_len = series.blocks.len();
+        let rendered_width = |computed: &ComputedBlock| {
+            if series.switched_to_short {
+                computed.short.as_ref().unwrap_or(&computed.full).width
+            } else {
+                computed.full.width
+            }
+        };
+        if s_len > 0 {
+            if let Some(group_bg) = style.group_bg {
+                let width: f64 = series.blocks.iter().map(|c| rendered_width(c)).sum();
+                group_bg.apply(context);
+                rounded_rectangle(
+                    context,
+                    x,
+                    0.0,
+                    width + style.group_padding * 2.0,
+                    full_height,
+                    style.blocks_r,
+                    style.blocks_r,
+                );
+                context.fill().unwrap();
+            }
+            x += style.group_padding;
+        }
+        for (i, computed) in series.blocks.into_iter().enumerate() {
+            let block = &computed.block;
+            let to_render = if series.switched_to_short {
+                computed.short.as_ref().unwrap_or(&computed.full)
+            } else {
+                &computed.full
+            };
+            j += 1;
+            let fg_color = style.fg(block, block.background.or(style.group_bg));
+            to_render.render(
+                context,
+                RenderOptions {
+                    x_offset: x,
+                    bar_height: full_height,
+                    fg_color,
+                    bg_color: block.background,
+                    r_left: if i == 0 { style.blocks_r } else { 0.0 },
+                    r_right: if i + 1 == s_len { style.blocks_r } else { 0.0 },
+                    overlap: style.blocks_overlap,
+                    progress: computed.progress.map(|p| (p, style.progress_bg)),
+                },
+            );
+            if let Some((icon, height)) = &computed.icon {
+                draw_icon(context, icon, *height, x, full_height);
+            }
+            if let (true, Some(marker)) = (block.urgent, style.urgent_marker) {
+                draw_urgent_marker(context, marker, fg_color, x, to_render.width, full_height);
+            }
+            buttons.push(
+                x,
+                to_render.width,
+                (block.name.clone(), block.instance.clone()),
+            );
+            let short = series.switched_to_short && computed.short.is_some();
+            if short || x < offset_left || x + to_render.width > offset_right {
+                truncated.push((block.name.clone(), block.instance.clone()));
+            }
+            x += to_render.width;
+        }
+        if s_len > 0 {
+            x += style.group_padding;
+        }
+        if j != blocks.len() {
+            let w = series.separator_block_width as f64 + style.blocks_margin;
+            if series.separator && series.separator_block_width > 0 && style.separator_width > 0.0 {
+                style.separator.apply(context);
+                context.set_line_width(style.separator_width);
+                context.move_to(x + w * 0.5, full_height * 0.1);
+                context.line_to(x + w * 0.5, full_height * 0.9);
+                context.stroke().unwrap();
+            }
+            x += w;
+        }
+    }
+
+    context.reset_clip();
+    truncated
+}
@@ -0,0 +1,229 @@
+//! Parser for lemonbar's input format.
+//!
+//! Supported directives: `l`, `c`, `r` (alignment), `F` and `B` (colors), `R` (swap colors) and
+//! `A` (clickable areas). Other directives are ignored.
+//!
+//! Alignment sections are rendered one after another in the blocks area: left, center, right.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::i3bar_protocol::Block;
+use crate::plain_text::parse_color;
+use crate::pointer_btn::PointerBtn;
+
+/// The gap between alignment sections, in pixels.
+const SECTIONS_GAP: u8 = 9;
+
+/// Commands bound to clickable areas, indexed by the name of the block.
+pub type Actions = HashMap<String, Vec<(PointerBtn, String)>>;
+
+#[derive(Default)]
+struct Parser {
+    sections: [Vec<Block>; 3],
+    section: usize,
+    fg: Option<Color>,
+    bg: Option<Color>,
+    areas: Vec<(PointerBtn, String)>,
+    area_name: Option<Rc<str>>,
+    actions: Actions,
+    text: String,
+}
+
+impl Parser {
+    /// Emit the accumulated text as a block, using the current state.
+    fn flush(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+        self.sections[self.section].push(Block {
+            full_text: std::mem::take(&mut self.text).into(),
+            color: self.fg,
+            background: self.bg,
+            name: self.area_name.clone(),
+            separator: false,
+            separator_block_width: 0,
+            ..Default::default()
+        });
+    }
+
+    /// The set of active clickable areas has changed.
+    fn areas_changed(&mut self) {
+        if self.areas.is_empty() {
+            self.area_name = None;
+        } else {
+            let name = format!("lemonbar-{}", self.actions.len());
+            self.actions.insert(name.clone(), self.areas.clone());
+            self.area_name = Some(name.into());
+        }
+    }
+
+    /// Apply a single directive. Returns `None` if it is malformed.
+    fn directive(&mut self, d: &str) -> Option<()> {
+        let mut chars = d.chars();
+        match chars.next()? {
+            'l' => self.section = 0,
+            'c' => self.section = 1,
+            'r' => self.section = 2,
+            'F' => self.fg = parse_color(chars.as_str(), true)?,
+            'B' => self.bg = parse_color(chars.as_str(), true)?,
+            'R' => std::mem::swap(&mut self.fg, &mut self.bg),
+            'A' => {
+                let rest = chars.as_str();
+                if rest.is_empty() {
+                    self.areas.pop();
+                } else {
+                    let (btn, rest) = rest.split_once(':')?;
+                    let cmd = rest.strip_suffix(':')?;
+                    let btn = match btn {
+                        "" | "1" => PointerBtn::Left,
+                        "2" => PointerBtn::Middle,
+                        "3" => PointerBtn::Right,
+                        "4" => PointerBtn::WheelUp,
+                        "5" => PointerBtn::WheelDown,
+                        "8" => PointerBtn::Back,
+                        "9" => PointerBtn::Forward,
+                        _ => PointerBtn::Unknown,
+                    };
+                    self.areas.push((btn, cmd.replace("\\:", ":")));
+                }
+                self.areas_changed();
+            }
+            _ => (),
+        }
+        Some(())
+    }
+}
+
+/// Split the contents of `%{...}` into separate directives.
+///
+/// Directives are separated by spaces, except for the commands of clickable areas, which may
+/// contain spaces and escaped colons.
+fn split_directives(s: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let is_area = rest.strip_prefix('A').is_some_and(|r| {
+            r.trim_start_matches(|c: char| c.is_ascii_digit())
+                .starts_with(':')
+        });
+        let end = if is_area {
+            // Find the closing (unescaped) colon
+            let start = rest.find(':').unwrap() + 1;
+            let mut end = None;
+            let mut prev = ' ';
+            for (i, c) in rest[start..].char_indices() {
+                if c == ':' && prev != '\\' {
+                    end = Some(start + i + 1);
+                    break;
+                }
+                prev = c;
+            }
+            end.unwrap_or(rest.len())
+        } else {
+            rest.find(' ').unwrap_or(rest.len())
+        };
+        result.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    result
+}
+
+/// Parse a line of lemonbar input into blocks and clickable area commands.
+pub fn parse(line: &str) -> (Vec<Block>, Actions) {
+    let mut p = Parser::default();
+
+    let mut rest = line;
+    while let Some(i) = rest.find("%{") {
+        p.text.push_str(&rest[..i]);
+        let tail = &rest[i + 2..];
+        let Some(end) = find_closing_brace(tail) else {
+            p.text.push_str(&rest[i..]);
+            rest = "";
+            break;
+        };
+        p.flush();
+        for d in split_directives(&tail[..end]) {
+            if p.directive(d).is_none() {
+                break;
+            }
+        }
+        rest = &tail[end + 1..];
+    }
+    p.text.push_str(rest);
+    p.flush();
+
+    let mut blocks = Vec::new();
+    for section in p.sections.into_iter().filter(|s| !s.is_empty()) {
+        if let Some(last) = blocks.last_mut() {
+            last.separator_block_width = SECTIONS_GAP;
+        }
+        blocks.extend(section);
+    }
+
+    (blocks, p.actions)
+}
+
+/// Find the `}` which closes the directives, skipping the ones inside the commands of clickable
+/// areas.
+fn find_closing_brace(s: &str) -> Option<usize> {
+    let mut in_area = false;
+    let mut in_cmd = false;
+    let mut prev = ' ';
+    for (i, c) in s.char_indices() {
+        if in_cmd {
+            if c == ':' && prev != '\\' {
+                in_cmd = false;
+            }
+        } else {
+            match c {
+                '}' => return Some(i),
+                ' ' => in_area = false,
+                'A' if prev == ' ' => in_area = true,
+                ':' if in_area => {
+                    in_area = false;
+                    in_cmd = true;
+                }
+                _ => (),
+            }
+        }
+        prev = c;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_and_colors() {
+        let (blocks, actions) = parse("%{r}right%{l}left %{F#ff0000}red%{F-}");
+        assert!(actions.is_empty());
+        let texts: Vec<_> = blocks.iter().map(|b| &*b.full_text).collect();
+        assert_eq!(texts, ["left ", "red", "right"]);
+        assert_eq!(blocks[0].color, None);
+        assert_eq!(blocks[1].color, "#ff0000".parse::<Color>().ok());
+        assert_eq!(blocks[1].separator_block_width, SECTIONS_GAP);
+        assert_eq!(blocks[2].separator_block_width, 0);
+    }
+
+    #[test]
+    fn clickable_areas() {
+        let (blocks, actions) = parse("%{A:echo a\\:b}:}%{A3:foo:}x%{A}y%{A}z");
+        let texts: Vec<_> = blocks.iter().map(|b| &*b.full_text).collect();
+        assert_eq!(texts, ["x", "y", "z"]);
+        let x = &actions[blocks[0].name.as_deref().unwrap()];
+        assert_eq!(
+            x,
+            &[
+                (PointerBtn::Left, "echo a:b}".to_owned()),
+                (PointerBtn::Right, "foo".to_owned())
+            ]
+        );
+        let y = &actions[blocks[1].name.as_deref().unwrap()];
+        assert_eq!(y, &[(PointerBtn::Left, "echo a:b}".to_owned())]);
+        assert_eq!(blocks[2].name, None);
+    }
+}
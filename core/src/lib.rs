@@ -0,0 +1,27 @@
+//! The core of i3bar-river: the i3bar protocol and the rendering of the blocks.
+//!
+//! This crate does not depend on Wayland, so it can be reused by other bars and widgets. The
+//! typical flow is:
+//!
+//! 1. Feed the output of a status command to [`i3bar_protocol::Protocol::process_new_bytes`] and
+//!    get the blocks with [`i3bar_protocol::Protocol::get_blocks`].
+//! 2. Lay out their text with [`blocks_cache::BlocksCache::process_new_blocks`].
+//! 3. Draw them on a cairo context with [`layout::render_blocks`], which also records where each
+//!    block ended up, so that clicks can be mapped back to blocks with
+//!    [`button_manager::ButtonManager::click`].
+//! 4. Send the clicks to the status command as [`i3bar_protocol::Event`]s.
+
+#[macro_use]
+extern crate log;
+
+pub mod blocks_cache;
+pub mod button_manager;
+pub mod color;
+pub mod i3bar_protocol;
+pub mod layout;
+pub mod lemonbar;
+pub mod plain_text;
+pub mod pointer_btn;
+pub mod tags;
+pub mod text;
+mod utils;
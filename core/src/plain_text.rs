@@ -0,0 +1,164 @@
+//! Inline color escapes for the plain text protocol.
+//!
+//! Both dzen2 (`^fg(#rrggbb)`, `^bg(#rrggbb)`, `^fg()`) and lemonbar (`%{F#rrggbb}`,
+//! `%{B#aarrggbb}`, `%{F-}`) styles are supported. The text is translated into pango markup.
+
+use std::fmt::Write;
+
+use crate::color::Color;
+
+enum Escape {
+    Fg(Option<Color>),
+    Bg(Option<Color>),
+}
+
+/// Translate color escapes into pango markup. Returns `None` if `text` has no escapes.
+pub fn to_markup(text: &str) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut fg = None;
+    let mut bg = None;
+    let mut has_escapes = false;
+    let mut span_open = false;
+
+    let mut rest = text;
+    while let Some(i) = rest.find(['^', '%']) {
+        let (before, tail) = rest.split_at(i);
+        escape_markup(&mut out, before);
+        match parse_escape(tail) {
+            Some((escape, len)) => {
+                has_escapes = true;
+                match escape {
+                    Escape::Fg(color) => fg = color,
+                    Escape::Bg(color) => bg = color,
+                }
+                if span_open {
+                    out.push_str("</span>");
+                }
+                span_open = open_span(&mut out, fg, bg);
+                rest = &tail[len..];
+            }
+            None => {
+                out.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    escape_markup(&mut out, rest);
+
+    if span_open {
+        out.push_str("</span>");
+    }
+
+    has_escapes.then_some(out)
+}
+
+/// Parse an escape at the start of `s`. Returns the escape and its length in bytes.
+fn parse_escape(s: &str) -> Option<(Escape, usize)> {
+    let (arg, prefix_len, is_fg, argb) = if let Some(rest) = s.strip_prefix("^fg(") {
+        (&rest[..rest.find(')')?], 4, true, false)
+    } else if let Some(rest) = s.strip_prefix("^bg(") {
+        (&rest[..rest.find(')')?], 4, false, false)
+    } else if let Some(rest) = s.strip_prefix("%{F") {
+        (&rest[..rest.find('}')?], 3, true, true)
+    } else if let Some(rest) = s.strip_prefix("%{B") {
+        (&rest[..rest.find('}')?], 3, false, true)
+    } else {
+        return None;
+    };
+
+    let color = parse_color(arg, argb)?;
+    let len = prefix_len + arg.len() + 1;
+    Some((
+        if is_fg {
+            Escape::Fg(color)
+        } else {
+            Escape::Bg(color)
+        },
+        len,
+    ))
+}
+
+/// Parse a color argument of an escape. An empty argument or `-` resets the color and results in
+/// `Some(None)`. Returns `None` if the color is invalid.
+///
+/// If `argb` is set, eight-digit colors are in lemonbar's `#aarrggbb` format.
+pub fn parse_color(arg: &str, argb: bool) -> Option<Option<Color>> {
+    match arg {
+        "" | "-" => Some(None),
+        _ if argb && arg.len() == 9 && arg.is_ascii() => format!("#{}{}", &arg[3..], &arg[1..3])
+            .parse()
+            .ok()
+            .map(Some),
+        _ => arg.parse().ok().map(Some),
+    }
+}
+
+/// Open a `<span>` with the given colors. Returns `false` if no span is needed.
+fn open_span(out: &mut String, fg: Option<Color>, bg: Option<Color>) -> bool {
+    if fg.is_none() && bg.is_none() {
+        return false;
+    }
+    out.push_str("<span");
+    for (color, attr, alpha_attr) in [(fg, "foreground", "fgalpha"), (bg, "background", "bgalpha")]
+    {
+        if let Some(color) = color {
+            let [r, g, b, a] = color.to_rgba8();
+            let _ = write!(out, " {attr}=\"#{r:02x}{g:02x}{b:02x}\"");
+            if a != 255 {
+                // pango does not accept zero alpha
+                let alpha = (a as u32 * 257).max(1);
+                let _ = write!(out, " {alpha_attr}=\"{alpha}\"");
+            }
+        }
+    }
+    out.push('>');
+    true
+}
+
+fn escape_markup(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\'' => out.push_str("&#39;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_escapes() {
+        assert_eq!(to_markup("100% <b>"), None);
+    }
+
+    #[test]
+    fn dzen_escapes() {
+        assert_eq!(
+            to_markup("^fg(#ff0000)a & b^fg() c").as_deref(),
+            Some("<span foreground=\"#ff0000\">a &amp; b</span> c")
+        );
+    }
+
+    #[test]
+    fn lemonbar_escapes() {
+        assert_eq!(
+            to_markup("%{F#80ff0000}a%{B#00ff00}b%{F-}c%{B-}").as_deref(),
+            Some(concat!(
+                "<span foreground=\"#ff0000\" fgalpha=\"32896\">a</span>",
+                "<span foreground=\"#ff0000\" fgalpha=\"32896\" background=\"#00ff00\">b</span>",
+                "<span background=\"#00ff00\">c</span>",
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_escape_is_kept() {
+        assert_eq!(to_markup("^fg(nope)x^fg(#fff").as_deref(), None);
+    }
+}
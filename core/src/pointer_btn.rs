@@ -1,3 +1,5 @@
+//! Pointer buttons, as reported in click events.
+
 use serde::{Serialize, Serializer};
 
 // From linux/input-event-codes.h
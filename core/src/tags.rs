@@ -0,0 +1,20 @@
+//! Workspaces or tags, as reported by the window manager.
+
+/// A tag (or workspace) on an output.
+#[derive(Debug)]
+pub struct Tag {
+    /// An identifier, passed back to the window manager when the tag is clicked.
+    pub id: u32,
+    /// The label of the tag.
+    pub name: String,
+    /// The tag is focused on this output.
+    pub is_focused: bool,
+    /// The tag has windows or is visible.
+    pub is_active: bool,
+    /// A window on this tag demands attention.
+    pub is_urgent: bool,
+    /// Show the tag even if it is inactive and `hide_inactive_tags` is set.
+    pub always_shown: bool,
+    /// The number of windows on the tag, if known.
+    pub windows: Option<u32>,
+}
@@ -1,7 +1,9 @@
+//! Laying out and rendering text with pango.
+
 use crate::color::Color;
 use pango::FontDescription;
 use pangocairo::{cairo, pango};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::f64::consts::{FRAC_PI_2, PI, TAU};
 
 thread_local! {
@@ -22,6 +24,8 @@ pub struct RenderOptions {
     pub r_left: f64,
     pub r_right: f64,
     pub overlap: f64,
+    /// The fraction of the background filled from the left with the color, for meters.
+    pub progress: Option<(f64, Color)>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -34,7 +38,7 @@ pub struct Attributes<'a> {
     pub markup: bool,
 }
 
-#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Align {
     Right,
@@ -43,28 +47,60 @@ pub enum Align {
     Center,
 }
 
+/// How text with several lines is laid out, see [`ComputedText::new_multiline`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Multiline {
+    /// The height the lines must fit in, otherwise they are joined with "⏎".
+    pub max_height: f64,
+    /// Extra space between the lines, may be negative.
+    pub line_spacing: f64,
+}
+
 #[derive(Clone, Debug, PartialEq)]
+/// A laid out piece of text, ready to be rendered.
 pub struct ComputedText {
     pub width: f64,
     layout: pango::Layout,
-    height: f64,
+    pub height: f64,
     padding_left: f64,
 }
 
 impl ComputedText {
-    pub fn new(text: &str, mut attr: Attributes) -> Self {
-        let text = text.replace('\n', "\u{23CE}");
+    /// Lay out `text` on a single line, line breaks are shown as "⏎".
+    pub fn new(text: &str, attr: Attributes) -> Self {
+        Self::new_multiline(text, attr, None)
+    }
 
+    /// Like [`ComputedText::new`], but the lines of `text` are stacked if they fit in
+    /// `multiline.max_height`. They are aligned according to `attr.align`.
+    pub fn new_multiline(text: &str, mut attr: Attributes, multiline: Option<Multiline>) -> Self {
         let layout = PANGO_CTX.with(pango::Layout::new);
         layout.set_font_description(Some(attr.font));
-        if attr.markup {
-            layout.set_markup(&text);
-        } else {
-            layout.set_text(&text);
+        let set_text = |text: &str| {
+            if attr.markup {
+                layout.set_markup(text);
+            } else {
+                layout.set_text(text);
+            }
+        };
+
+        let mut stacked = false;
+        if let Some(multiline) = multiline.filter(|_| text.contains('\n')) {
+            layout.set_spacing((multiline.line_spacing * f64::from(pango::SCALE)) as i32);
+            layout.set_alignment(match attr.align {
+                Align::Right => pango::Alignment::Right,
+                Align::Left => pango::Alignment::Left,
+                Align::Center => pango::Alignment::Center,
+            });
+            set_text(text);
+            stacked = f64::from(layout.pixel_size().1) <= multiline.max_height;
+        }
+        if !stacked {
+            set_text(&text.replace('\n', "\u{23CE}"));
         }
 
         let (text_width, text_height) = layout.pixel_size();
-        let mut width = f64::from(text_width) + attr.padding_right + attr.padding_right;
+        let mut width = f64::from(text_width) + attr.padding_left + attr.padding_right;
         let height = f64::from(text_height);
 
         if let Some(min_width) = attr.min_width {
@@ -110,6 +146,26 @@ impl ComputedText {
             context.fill().unwrap();
         }
 
+        // Draw the filled part of a meter, with the corners of the background
+        if let Some((fraction, color)) = options.progress {
+            let width = self.width + options.overlap + 0.5;
+            context.save().unwrap();
+            rounded_rectangle(
+                context,
+                0.0,
+                0.0,
+                width,
+                options.bar_height,
+                options.r_left,
+                options.r_right,
+            );
+            context.clip();
+            color.apply(context);
+            context.rectangle(0.0, 0.0, width * fraction, options.bar_height);
+            context.fill().unwrap();
+            context.restore().unwrap();
+        }
+
         options.fg_color.apply(context);
         context.translate(
             self.padding_left + options.overlap,
@@ -120,7 +176,7 @@ impl ComputedText {
     }
 }
 
-fn rounded_rectangle(
+pub(crate) fn rounded_rectangle(
     context: &cairo::Context,
     x: f64,
     y: f64,
@@ -141,6 +197,23 @@ fn rounded_rectangle(
     }
 }
 
+/// The height of a line of text in pixels.
+pub fn line_height(font: &FontDescription) -> f64 {
+    ComputedText::new(
+        "",
+        Attributes {
+            font,
+            padding_left: 0.0,
+            padding_right: 0.0,
+            min_width: None,
+            align: Default::default(),
+            markup: false,
+        },
+    )
+    .height
+}
+
+/// The width of the text in pixels.
 pub fn width_of(text: &str, markup: bool, font: &FontDescription) -> f64 {
     ComputedText::new(
         text,
@@ -0,0 +1,256 @@
+//! Golden-image tests for the rendering of tags and blocks.
+//!
+//! The reference images live in `tests/golden`. A missing image is a failure. When `UPDATE_GOLDEN`
+//! is set, the images are written instead of compared, so review them before committing them.
+//! Rendering depends on the installed fonts, so small differences are tolerated.
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use i3bar_river_core::blocks_cache::BlocksCache;
+use i3bar_river_core::button_manager::ButtonManager;
+use i3bar_river_core::color::Color;
+use i3bar_river_core::i3bar_protocol::Block;
+use i3bar_river_core::layout::{
+    render_blocks, render_tags, split_blocks, BlocksStyle, ColorPair, ComputedTag, TagsStyle,
+};
+use i3bar_river_core::text::{Align, Attributes, ComputedText};
+use pangocairo::cairo;
+use pangocairo::pango::FontDescription;
+
+const HEIGHT: u32 = 24;
+/// Channels which differ by less than this are considered equal.
+const CHANNEL_TOLERANCE: u8 = 32;
+/// The fraction of pixels allowed to differ.
+const PIXEL_TOLERANCE: f64 = 0.01;
+
+fn font() -> FontDescription {
+    FontDescription::from_string("monospace 10")
+}
+
+fn color(hex: u32) -> Color {
+    Color::from_rgba_hex(hex)
+}
+
+fn blocks_style() -> BlocksStyle {
+    BlocksStyle {
+        color: color(0xffffffff),
+        separator: color(0x9a8a62ff),
+        separator_width: 2.0,
+        blocks_r: 0.0,
+        blocks_overlap: 0.0,
+        blocks_margin: 0.0,
+        group_bg: None,
+        group_padding: 0.0,
+        auto_contrast: false,
+        high_contrast: false,
+        background: color(0x282828ff),
+        urgent_marker: None,
+    }
+}
+
+fn tag(id: u32, bg: u32, fg: u32) -> ComputedTag {
+    ComputedTag {
+        id,
+        colors: ColorPair {
+            bg: color(bg),
+            fg: color(fg),
+        },
+        label: ComputedText::new(
+            &id.to_string(),
+            Attributes {
+                font: &font(),
+                padding_left: 25.0,
+                padding_right: 25.0,
+                min_width: None,
+                align: Default::default(),
+                markup: false,
+            },
+        ),
+        urgent: false,
+    }
+}
+
+fn block(full_text: &str) -> Block {
+    Block {
+        full_text: full_text.into(),
+        separator: true,
+        separator_block_width: 9,
+        ..Default::default()
+    }
+}
+
+/// Render the blocks aligned to the right. Returns the buttons of the blocks.
+fn draw_blocks(
+    context: &cairo::Context,
+    style: &BlocksStyle,
+    blocks: Vec<Block>,
+    width: u32,
+) -> ButtonManager<(Option<Rc<str>>, Option<Rc<str>>)> {
+    let mut cache = BlocksCache::default();
+    cache.process_new_blocks(&font(), blocks);
+    let mut buttons = ButtonManager::default();
+    render_blocks(
+        context,
+        style,
+        cache.get_computed(),
+        &mut buttons,
+        0.0,
+        width as f64,
+        Align::Right,
+        HEIGHT as f64,
+    );
+    buttons
+}
+
+fn check_golden(name: &str, width: u32, scale: f64, draw: impl FnOnce(&cairo::Context)) {
+    let pix_width = (width as f64 * scale).ceil() as i32;
+    let pix_height = (HEIGHT as f64 * scale).ceil() as i32;
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, pix_width, pix_height)
+        .expect("cairo surface");
+    {
+        let context = cairo::Context::new(&surface).expect("cairo context");
+        context.scale(scale, scale);
+        color(0x282828ff).apply(&context);
+        context.paint().unwrap();
+        draw(&context);
+    }
+    surface.flush();
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        surface
+            .write_to_png(&mut File::create(&path).unwrap())
+            .unwrap();
+        eprintln!("wrote {}", path.display());
+        return;
+    }
+    assert!(
+        path.exists(),
+        "{name}: {} is missing, run the tests with UPDATE_GOLDEN=1 to write it",
+        path.display()
+    );
+
+    let expected = cairo::ImageSurface::create_from_png(&mut File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        (expected.width(), expected.height()),
+        (pix_width, pix_height),
+        "{name}: size mismatch"
+    );
+
+    let actual_data = data(&surface);
+    let expected_data = data(&expected);
+    let mut differing = 0;
+    for y in 0..pix_height as usize {
+        let actual_row = &actual_data[y * surface.stride() as usize..][..pix_width as usize * 4];
+        let expected_row =
+            &expected_data[y * expected.stride() as usize..][..pix_width as usize * 4];
+        for (a, e) in actual_row.chunks(4).zip(expected_row.chunks(4)) {
+            if a.iter()
+                .zip(e)
+                .any(|(a, e)| a.abs_diff(*e) >= CHANNEL_TOLERANCE)
+            {
+                differing += 1;
+            }
+        }
+    }
+    let total = pix_width as usize * pix_height as usize;
+    assert!(
+        differing as f64 <= total as f64 * PIXEL_TOLERANCE,
+        "{name}: {differing} of {total} pixels differ from {}",
+        path.display()
+    );
+}
+
+fn data(surface: &cairo::ImageSurface) -> Vec<u8> {
+    let mut data = Vec::new();
+    surface.with_data(|d| data = d.to_vec()).unwrap();
+    data
+}
+
+#[test]
+fn tags_in_every_state() {
+    check_golden("tags", 500, 1.0, |context| {
+        let tags = [
+            // focused
+            tag(1, 0x689d68ff, 0x1d2021ff),
+            // active, joined together
+            tag(2, 0x282828ff, 0xd79921ff),
+            tag(3, 0x282828ff, 0xd79921ff),
+            // urgent
+            tag(4, 0xcc241dff, 0x282828ff),
+            // inactive
+            tag(5, 0x3c3836ff, 0xa89984ff),
+        ];
+        let style = TagsStyle {
+            tags_r: 6.0,
+            tags_margin: 4.0,
+            urgent_marker: None,
+        };
+        let mut buttons = ButtonManager::default();
+        let end = render_tags(context, &tags, &style, &mut buttons, 0.0, HEIGHT as f64);
+        assert_eq!(buttons.click(1.0), Some(&1));
+        assert_eq!(buttons.click(end - 1.0), Some(&5));
+    });
+}
+
+#[test]
+fn markup_blocks() {
+    check_golden("markup", 400, 1.0, |context| {
+        let mut markup = block("<b>bold</b> <span foreground='#ff0000'>red</span>");
+        markup.markup = Some("pango".into());
+        let plain = block("<b>not bold</b>");
+        draw_blocks(context, &blocks_style(), vec![markup, plain], 400);
+    });
+}
+
+#[test]
+fn short_text_overflow() {
+    check_golden("short_text", 200, 1.0, |context| {
+        let mut first = block("a very long full text");
+        first.short_text = Some("short".into());
+        first.name = Some("first".into());
+        let mut second = block("another long text");
+        second.short_text = Some("s".into());
+        second.name = Some("second".into());
+        let buttons = draw_blocks(context, &blocks_style(), vec![first, second], 200);
+        assert_eq!(buttons.click(199.0), Some(&(Some("second".into()), None)));
+    });
+}
+
+#[test]
+fn blocks_split_into_rows() {
+    let mut cache = BlocksCache::default();
+    cache.process_new_blocks(&font(), vec![block("one"), block("two"), block("three")]);
+    let blocks = cache.get_computed();
+    let width: f64 = blocks.iter().map(|b| b.full.width).sum::<f64>() + 2.0 * 9.0;
+    assert_eq!(split_blocks(&blocks_style(), blocks, width), None);
+    assert_eq!(split_blocks(&blocks_style(), blocks, width - 1.0), Some(2));
+    assert_eq!(split_blocks(&blocks_style(), blocks, 1.0), Some(1));
+}
+
+fn separators_and_radii(context: &cairo::Context) {
+    let style = BlocksStyle {
+        blocks_r: 8.0,
+        ..blocks_style()
+    };
+    let mut blocks = vec![block("one"), block("two"), block("three")];
+    blocks[0].background = Some(color(0x458588ff));
+    blocks[1].background = Some(color(0xb16286ff));
+    blocks[1].separator = false;
+    blocks[2].background = Some(color(0x98971aff));
+    draw_blocks(context, &style, blocks, 300);
+}
+
+#[test]
+fn separators_and_radii_scale_1() {
+    check_golden("separators", 300, 1.0, separators_and_radii);
+}
+
+#[test]
+fn separators_and_radii_fractional_scale() {
+    check_golden("separators_scale_1.5", 300, 1.5, separators_and_radii);
+}
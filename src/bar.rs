@@ -1,48 +1,133 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
 use pangocairo::cairo;
+use serde_json::json;
 
+use wayrs_client::proxy::Proxy;
 use wayrs_client::{Connection, EventCtx};
 use wayrs_utils::shm_alloc::BufferSpec;
 
+use i3bar_river_core::layout::{
+    blocks_width, render_blocks, render_tags, split_blocks, tags_width, ColorPair, ComputedTag,
+    UrgentMarker,
+};
+
 use crate::blocks_cache::ComputedBlock;
 use crate::button_manager::ButtonManager;
-use crate::color::Color;
-use crate::config::{Config, Position};
+use crate::clipboard::block_text;
+use crate::config::{BarContent, BlocksOverflow, Colors, Config, Position, TagsPosition};
 use crate::i3bar_protocol;
 use crate::output::Output;
 use crate::pointer_btn::PointerBtn;
+use crate::profiler::FrameTimes;
 use crate::protocol::*;
+use crate::scripting::{ScriptAction, Target};
 use crate::shared_state::SharedState;
 use crate::state::State;
-use crate::text::{self, ComputedText, RenderOptions};
+use crate::text::{self, Align, ComputedText, RenderOptions};
+use crate::tooltip;
+use crate::tray;
+use crate::utils::{spawn_command, spawn_command_with_env};
 use crate::wm_info_provider::Tag;
 
+/// How much the blocks are covered with the background when they are stale.
+const STALE_DIM: f64 = 0.6;
+
+/// The width of the outline of the tag a tag is being dragged onto.
+const DRAG_OUTLINE_WIDTH: f64 = 2.0;
+
+/// The height of the bar collapsed by `auto_hide`, which the pointer can still enter.
+const AUTO_HIDE_STRIP: u32 = 1;
+
+/// The space around the text of the `tooltip` of a block.
+const TOOLTIP_PADDING: f64 = 4.0;
+
+/// The Linux input event codes of the keys of `keyboard_navigation`. They are matched by position,
+/// whatever the keyboard layout.
+mod keycode {
+    pub const ESC: u32 = 1;
+    pub const NUM_1: u32 = 2;
+    pub const NUM_9: u32 = 10;
+    pub const ENTER: u32 = 28;
+    pub const KP_ENTER: u32 = 96;
+    pub const UP: u32 = 103;
+    pub const LEFT: u32 = 105;
+    pub const RIGHT: u32 = 106;
+    pub const DOWN: u32 = 108;
+}
+
+/// A fade of the colors of a tag, see `animations` in README.
+struct TagTransition {
+    id: u32,
+    from: ColorPair,
+    to: ColorPair,
+    start: Instant,
+}
+
 pub struct Bar {
     pub output: Output,
+    /// The output whose tags, layout name, mode and focus are shown, see `mirror_of` in README.
+    pub mirrored: Option<Output>,
     hidden: bool,
+    /// Unmapped because there is nothing to show, see `hide_when_empty` in README.
+    auto_hidden: bool,
     mapped: bool,
+    /// Not drawn while the output is powered off, see `pause_when_off` in README.
+    pub powered_off: bool,
     throttle: Option<WlCallback>,
     throttled: bool,
+    full_damage: bool,
+    /// The horizontal range which has changed since the last frame, unless `full_damage` is set.
+    partial_damage: Option<(f64, f64)>,
     width: u32,
     height: u32,
+    /// The number of rows of blocks, see `blocks_overflow` in README.
+    rows: u32,
+    /// Shrunk to a strip, see `auto_hide` in README.
+    collapsed: bool,
+    /// When the pointer left the bar, `None` while it is over the bar.
+    left_at: Option<Instant>,
     scale120: Option<u32>,
     pub surface: WlSurface,
     layer_surface: ZwlrLayerSurfaceV1,
     viewport: WpViewport,
     fractional_scale: Option<WpFractionalScaleV1>,
-    blocks_btns: ButtonManager<(Option<String>, Option<String>)>,
+    blur: Option<OrgKdeKwinBlur>,
+    blocks_btns: ButtonManager<(Option<Rc<str>>, Option<Rc<str>>)>,
+    wrapped_blocks_btns: ButtonManager<(Option<Rc<str>>, Option<Rc<str>>)>,
+    hovered_block: Option<(Option<Rc<str>>, Option<Rc<str>>)>,
+    /// Whether the bar has the keyboard focus, see `keyboard_navigation` in README.
+    keyboard_focused: bool,
+    /// The index of the block selected with the keyboard, among the blocks of both rows.
+    selected_block: Option<usize>,
+    /// The blocks shown with their short text or cut off, see `show_tooltips` in README.
+    truncated_blocks: Vec<(Option<Rc<str>>, Option<Rc<str>>)>,
     tags: Vec<Tag>,
     layout_name: Option<String>,
     mode_name: Option<String>,
+    /// Whether the output has the keyboard focus.
+    pub focused: bool,
     tags_btns: ButtonManager<u32>,
-    tags_computed: Vec<(u32, ColorPair, ComputedText)>,
+    hovered_tag: Option<u32>,
+    /// The tag pressed with the left button, and the other tag it is being dragged onto.
+    dragged_tag: Option<u32>,
+    drag_target: Option<u32>,
+    mode_btn: ButtonManager<()>,
+    layout_btn: ButtonManager<()>,
+    tray_btns: ButtonManager<u32>,
+    tags_computed: Vec<ComputedTag>,
+    tag_transitions: Vec<TagTransition>,
+    /// The colors the tags were last drawn with.
+    shown_tag_colors: Vec<(u32, ColorPair)>,
     layout_name_computed: Option<ComputedText>,
     mode_computed: Option<ComputedText>,
-}
-
-#[derive(Debug, PartialEq)]
-pub struct ColorPair {
-    bg: Color,
-    fg: Color,
+    divider_computed: Option<ComputedText>,
+    output_name_computed: Option<ComputedText>,
 }
 
 impl Bar {
@@ -61,47 +146,98 @@ impl Bar {
             c"i3bar-river".into(),
             layer_surface_cb,
         );
+        set_keyboard_interactivity(conn, layer_surface, &state.shared_state.config);
+
+        // The whole surface is blurred, applied on the first commit
+        let blur = state.blur_manager.map(|mgr| {
+            let blur = mgr.create(conn, surface);
+            blur.set_region(conn, None);
+            blur.commit(conn);
+            blur
+        });
+
+        let height = state.shared_state.config.output_height(&output.name);
 
         Self {
             output,
+            mirrored: None,
             hidden: true,
+            auto_hidden: false,
+            powered_off: false,
             mapped: false,
             throttle: None,
             throttled: false,
+            full_damage: true,
+            partial_damage: None,
             width: 0,
-            height: state.shared_state.config.height,
+            height,
+            rows: 1,
+            collapsed: false,
+            left_at: Some(Instant::now()),
             scale120: None,
             surface,
             viewport: state.viewporter.get_viewport(conn, surface),
             fractional_scale,
+            blur,
             layer_surface,
             blocks_btns: Default::default(),
+            wrapped_blocks_btns: Default::default(),
+            hovered_block: None,
+            keyboard_focused: false,
+            selected_block: None,
+            truncated_blocks: Vec::new(),
             tags: Vec::new(),
             layout_name: None,
             mode_name: None,
+            focused: true,
             tags_btns: Default::default(),
+            hovered_tag: None,
+            dragged_tag: None,
+            drag_target: None,
+            mode_btn: Default::default(),
+            layout_btn: Default::default(),
+            tray_btns: Default::default(),
             tags_computed: Vec::new(),
+            tag_transitions: Vec::new(),
+            shown_tag_colors: Vec::new(),
             layout_name_computed: None,
             mode_computed: None,
+            divider_computed: None,
+            output_name_computed: None,
         }
     }
 
-    pub fn destroy(self, conn: &mut Connection<State>) {
+    /// Destroy the surfaces, the output is left to the caller.
+    pub fn destroy(self, conn: &mut Connection<State>) -> Output {
         self.layer_surface.destroy(conn);
         self.viewport.destroy(conn);
         if let Some(fs) = self.fractional_scale {
             fs.destroy(conn);
         }
+        if let Some(blur) = self.blur {
+            blur.release(conn);
+        }
         self.surface.destroy(conn);
-        self.output.destroy(conn);
+        self.output
+    }
+
+    /// The output the window manager is asked about: the mirrored one, if any.
+    pub fn wm_output(&self) -> &Output {
+        self.mirrored.as_ref().unwrap_or(&self.output)
     }
 
     pub fn set_tags(&mut self, tags: Vec<Tag>) {
         self.tags = tags;
+        self.hovered_tag = None;
         self.tags_btns.clear();
         self.tags_computed.clear();
     }
 
+    /// Recompute the tags, whose colors come from the config.
+    pub fn reset_tag_colors(&mut self) {
+        self.tags_computed.clear();
+    }
+
     pub fn set_layout_name(&mut self, layout_name: Option<String>) {
         self.layout_name = layout_name;
         self.layout_name_computed = None;
@@ -112,6 +248,76 @@ impl Bar {
         self.mode_computed = None;
     }
 
+    /// The state of this bar, as reported by the `get-state` IPC command.
+    pub fn ipc_state(&self) -> serde_json::Value {
+        let tags: Vec<_> = self
+            .tags
+            .iter()
+            .map(|tag| {
+                json!({
+                    "id": tag.id,
+                    "name": tag.name,
+                    "focused": tag.is_focused,
+                    "active": tag.is_active,
+                    "urgent": tag.is_urgent,
+                    "windows": tag.windows,
+                })
+            })
+            .collect();
+        json!({
+            "output": self.output.name,
+            "focused": self.focused,
+            "visible": !self.hidden && !self.auto_hidden,
+            "tags": tags,
+            "layout_name": self.layout_name,
+            "mode": self.mode_name,
+        })
+    }
+
+    /// Whether `(x, y)` is over the tags rather than the blocks.
+    pub fn is_over_tags(&self, x: f64, y: f64) -> bool {
+        !self.in_second_row(y)
+            && (self.tags_btns.click(x).is_some() || self.tags_btns.is_between(x))
+    }
+
+    /// Whether `y` is in the second row, which only has blocks.
+    fn in_second_row(&self, y: f64) -> bool {
+        self.rows > 1 && y >= (self.height / self.rows) as f64
+    }
+
+    /// The buttons of the blocks in the row at `y`.
+    fn blocks_btns_at(&self, y: f64) -> &ButtonManager<(Option<Rc<str>>, Option<Rc<str>>)> {
+        if self.in_second_row(y) {
+            &self.wrapped_blocks_btns
+        } else {
+            &self.blocks_btns
+        }
+    }
+
+    /// The index of the block at `(x, y)` among the blocks of both rows.
+    fn block_index_at(&self, x: f64, y: f64) -> Option<usize> {
+        if self.in_second_row(y) {
+            Some(self.blocks_btns.len() + self.wrapped_blocks_btns.index_at(x)?)
+        } else {
+            self.blocks_btns.index_at(x)
+        }
+    }
+
+    /// The name and the instance of the block at `(x, y)`.
+    pub fn block_at(&self, x: f64, y: f64) -> Option<&(Option<Rc<str>>, Option<Rc<str>>)> {
+        if self.hidden || self.is_over_tags(x, y) {
+            return None;
+        }
+        self.blocks_btns_at(y).click(x)
+    }
+
+    /// The horizontal position of a block in either row.
+    fn block_position(&self, block: &(Option<Rc<str>>, Option<Rc<str>>)) -> Option<(f64, f64)> {
+        self.blocks_btns
+            .position_of(block)
+            .or_else(|| self.wrapped_blocks_btns.position_of(block))
+    }
+
     pub fn click(
         &mut self,
         conn: &mut Connection<State>,
@@ -119,29 +325,591 @@ impl Bar {
         button: PointerBtn,
         seat: WlSeat,
         x: f64,
-        _y: f64,
+        y: f64,
     ) -> anyhow::Result<()> {
-        if let Some(tag_id) = self.tags_btns.click(x) {
-            ss.wm_info_provider
-                .click_on_tag(conn, &self.output, seat, Some(*tag_id), button);
+        if self.hidden {
+            return Ok(());
+        }
+
+        let tag_id = if self.in_second_row(y) {
+            None
+        } else if let Some(tag_id) = self.tags_btns.click(x) {
+            Some(Some(*tag_id))
         } else if self.tags_btns.is_between(x) {
+            Some(None)
+        } else {
+            None
+        };
+        if button == PointerBtn::Left {
+            self.dragged_tag = tag_id.flatten();
+        }
+
+        if let Some(&id) = self.tray_btns.click(x).filter(|_| !self.in_second_row(y)) {
+            if let Some(tray) = &mut ss.tray {
+                tray.click(id, button)?;
+            }
+            return Ok(());
+        }
+
+        if self.mode_btn.click(x).is_some() && !self.in_second_row(y) {
             ss.wm_info_provider
-                .click_on_tag(conn, &self.output, seat, None, button);
-        } else if let Some((name, instance)) = self.blocks_btns.click(x) {
+                .click_on_mode(conn, self.wm_output(), seat, button);
+        } else if self.layout_btn.click(x).is_some() && !self.in_second_row(y) {
+            if let Some(cmd) = ss.config.click.layout.get(button) {
+                spawn_command_with_env(
+                    cmd,
+                    &[
+                        ("OUTPUT", self.wm_output().name.as_str()),
+                        ("LAYOUT", self.layout_name.as_deref().unwrap_or_default()),
+                    ],
+                )?;
+            }
+        } else if let Some(tag_id) = tag_id {
+            let target = Target::Tag {
+                id: tag_id,
+                output: &self.wm_output().name,
+            };
+            if run_script(conn, ss, seat, target, button)? {
+                if let Some(cmd) = ss.config.click.tags.get(button) {
+                    let tag = tag_id.map(|id| id.to_string()).unwrap_or_default();
+                    spawn_command_with_env(
+                        cmd,
+                        &[
+                            ("OUTPUT", self.wm_output().name.as_str()),
+                            ("TAG", tag.as_str()),
+                        ],
+                    )?;
+                } else {
+                    ss.wm_info_provider
+                        .click_on_tag(conn, self.wm_output(), seat, tag_id, button);
+                }
+            }
+        } else if let Some((block_x, block_width, (name, instance))) =
+            self.blocks_btns_at(y).find(x)
+        {
+            let target = Target::Block {
+                name: name.as_deref(),
+                instance: instance.as_deref(),
+            };
+            if !run_script(conn, ss, seat, target, button)? {
+                return Ok(());
+            }
+            let cmd = name
+                .as_deref()
+                .and_then(|name| ss.config.click.blocks.get(name))
+                .and_then(|commands| commands.get(button));
+            if let Some(cmd) = cmd {
+                spawn_command_with_env(
+                    cmd,
+                    &[
+                        ("BLOCK_NAME", name.as_deref().unwrap_or_default()),
+                        ("BLOCK_INSTANCE", instance.as_deref().unwrap_or_default()),
+                    ],
+                )?;
+                return Ok(());
+            }
+            if button == PointerBtn::Middle {
+                if let Some(clipboard) = &mut ss.clipboard {
+                    // The clicked block, unless the blocks changed since they were drawn
+                    let blocks = ss
+                        .blocks_cache
+                        .get_scaled(ss.config.output_font_scale(&self.output.name));
+                    let block = self
+                        .block_index_at(x, y)
+                        .and_then(|i| blocks.get(i))
+                        .map(|computed| &computed.block)
+                        .filter(|block| block.name == *name && block.instance == *instance);
+                    if let Some(block) = block {
+                        clipboard.copy(conn, seat, block_text(block));
+                    }
+                    return Ok(());
+                }
+            }
+            let block_height = ss.config.output_height(&self.output.name) as f64;
+            let block_y = if self.in_second_row(y) {
+                block_height
+            } else {
+                0.0
+            };
+            let (output_x, output_y) = self.output_position(&ss.config, x, y);
+            let event = i3bar_protocol::Event {
+                name: name.as_deref(),
+                instance: instance.as_deref(),
+                button,
+                x: self.output.geometry.x + output_x,
+                y: self.output.geometry.y + output_y,
+                relative_x: (x - block_x).round() as i32,
+                relative_y: (y - block_y).round() as i32,
+                output_x,
+                output_y,
+                width: block_width.round() as i32,
+                height: block_height as i32,
+                ..Default::default()
+            };
+            if let Some(path) = &ss.config.click_events_file {
+                if let Err(e) = mirror_click_event(path, &event) {
+                    debug!("failed to write the click event to {}: {e}", path.display());
+                }
+            }
+            if ss.plugins.click(&event)? {
+                return Ok(());
+            }
+            if let Some(cmd) = &mut ss.status_cmd {
+                cmd.send_click_event(&event)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The point `(x, y)` of the bar relative to the top left corner of the output, in logical
+    /// pixels.
+    fn output_position(&self, config: &Config, x: f64, y: f64) -> (i32, i32) {
+        let scale = self.scale();
+        let [margin_top, _, margin_bottom, margin_left] = config.output_margins(&self.output.name);
+        let top = match config.output_position(&self.output.name) {
+            Position::Top => margin_top as f64,
+            Position::Bottom => {
+                self.output.geometry.logical_height(scale)
+                    - self.height as f64
+                    - margin_bottom as f64
+            }
+        };
+        (
+            (margin_left as f64 + x).round() as i32,
+            (top + y).round() as i32,
+        )
+    }
+
+    /// Forward `data`, dropped at `(x, y)`, to the drop command or the status command.
+    pub fn drop_data(
+        &self,
+        ss: &mut SharedState,
+        x: f64,
+        y: f64,
+        mime_type: &str,
+        data: &str,
+    ) -> anyhow::Result<()> {
+        let Some((name, instance)) = self.block_at(x, y) else {
+            return Ok(());
+        };
+        debug!("{mime_type} dropped onto {name:?}");
+        if let Some(cmd) = &ss.config.drop_command {
+            spawn_command_with_env(
+                cmd,
+                &[
+                    ("BLOCK_NAME", name.as_deref().unwrap_or_default()),
+                    ("BLOCK_INSTANCE", instance.as_deref().unwrap_or_default()),
+                    ("DROP_MIME_TYPE", mime_type),
+                    ("DROP_DATA", data),
+                ],
+            )?;
+            return Ok(());
+        }
+        let event = i3bar_protocol::Event {
+            name: name.as_deref(),
+            instance: instance.as_deref(),
+            kind: Some("drop"),
+            mime_type: Some(mime_type),
+            data: Some(data),
+            ..Default::default()
+        };
+        if let Some(path) = &ss.config.click_events_file {
+            if let Err(e) = mirror_click_event(path, &event) {
+                debug!("failed to write the drop event to {}: {e}", path.display());
+            }
+        }
+        if let Some(cmd) = &mut ss.status_cmd {
+            cmd.send_event(&event)?;
+        }
+        Ok(())
+    }
+
+    /// Highlight the element under the pointer at `pos`, or nothing if the pointer left the bar.
+    pub fn hover(
+        &mut self,
+        conn: &mut Connection<State>,
+        ss: &mut SharedState,
+        pos: Option<(f64, f64)>,
+    ) {
+        if self.hidden {
+            return;
+        }
+
+        self.left_at = match pos {
+            Some(_) => None,
+            None => Some(self.left_at.unwrap_or_else(Instant::now)),
+        };
+        if self.collapsed && pos.is_some() {
+            self.expand(conn, ss);
+            return;
+        }
+
+        let mut changed = false;
+
+        if let Some(from) = self.dragged_tag {
+            let target = pos
+                .and_then(|(x, y)| self.tag_at(x, y))
+                .filter(|&to| to != from);
+            changed |= self.set_drag_target(target);
+            if pos.is_none() {
+                // The release will not be seen
+                self.dragged_tag = None;
+            }
+        }
+
+        let colors = ss.config.output_colors(&self.output.name);
+        if colors.tag_hover_bg.is_some() || colors.tag_hover_fg.is_some() {
+            let hovered_tag = pos.and_then(|(x, y)| self.tag_at(x, y));
+            if hovered_tag != self.hovered_tag {
+                for tag in [self.hovered_tag, hovered_tag].into_iter().flatten() {
+                    if let Some((x, width)) = self.tags_btns.position_of(&tag) {
+                        self.add_damage(x, width);
+                    }
+                }
+                self.hovered_tag = hovered_tag;
+                self.tags_computed.clear();
+                changed = true;
+            }
+        }
+
+        // Only named blocks receive clicks
+        if colors.block_hover_bg.is_some() {
+            let hovered_block = pos
+                .and_then(|(x, y)| self.blocks_btns_at(y).click(x))
+                .filter(|(name, _)| name.is_some())
+                .cloned();
+            if hovered_block != self.hovered_block {
+                for block in [self.hovered_block.take(), hovered_block.clone()]
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Some((x, width)) = self.block_position(&block) {
+                        self.add_damage(x, width);
+                    }
+                }
+                self.hovered_block = hovered_block;
+                changed = true;
+            }
+        }
+
+        if let Some(tooltip) = &mut ss.tooltip {
+            let blocks = ss.blocks_cache.get_computed();
+            let hovered = pos
+                .and_then(|(x, y)| self.blocks_btns_at(y).click(x))
+                .filter(|block| {
+                    self.truncated_blocks.contains(block)
+                        || (ss.config.hover_events && block.0.is_some())
+                        || find_block(blocks, block).is_some_and(|c| c.block.tooltip.is_some())
+                });
+            tooltip.hover(conn, self.surface, hovered);
+        }
+
+        if changed {
+            self.redraw(conn, ss);
+        }
+    }
+
+    /// Show the tooltip of `block`, or its full text if it is truncated, in a popup next to it,
+    /// and send the hover event.
+    pub fn show_tooltip(
+        &self,
+        conn: &mut Connection<State>,
+        ss: &mut SharedState,
+        block: &(Option<Rc<str>>, Option<Rc<str>>),
+    ) -> anyhow::Result<()> {
+        if ss.config.hover_events && block.0.is_some() {
+            let event = i3bar_protocol::Event {
+                name: block.0.as_deref(),
+                instance: block.1.as_deref(),
+                kind: Some("hover"),
+                ..Default::default()
+            };
             if let Some(cmd) = &mut ss.status_cmd {
-                cmd.send_click_event(&i3bar_protocol::Event {
-                    name: name.as_deref(),
-                    instance: instance.as_deref(),
-                    button,
-                    ..Default::default()
-                })?;
+                cmd.send_event(&event)?;
+            }
+        }
+
+        let Some(tooltip) = &mut ss.tooltip else {
+            return Ok(());
+        };
+        let font_scale = ss.config.output_font_scale(&self.output.name);
+        let Some(computed) = find_block(ss.blocks_cache.get_scaled(font_scale), block) else {
+            return Ok(());
+        };
+        let mut height = ss.config.output_height(&self.output.name) as f64;
+        let text = match &computed.block.tooltip {
+            Some(text) => {
+                let text = ComputedText::new_multiline(
+                    text,
+                    text::Attributes {
+                        font: &ss.config.blocks_font().scaled(font_scale),
+                        padding_left: TOOLTIP_PADDING,
+                        padding_right: TOOLTIP_PADDING,
+                        min_width: None,
+                        align: Align::Left,
+                        markup: computed.block.markup.as_deref() == Some("pango"),
+                    },
+                    Some(text::Multiline {
+                        max_height: f64::INFINITY,
+                        line_spacing: ss.config.line_spacing,
+                    }),
+                );
+                height = height.max(text.height + TOOLTIP_PADDING * 2.0);
+                text
             }
+            None if self.truncated_blocks.contains(block) => computed.full.clone(),
+            None => return Ok(()),
+        };
+        let bar_height = ss.config.output_height(&self.output.name) as f64;
+        let anchor = match self.blocks_btns.position_of(block) {
+            Some((x, width)) => (x, 0.0, width, bar_height),
+            None => match self.wrapped_blocks_btns.position_of(block) {
+                Some((x, width)) => (x, bar_height, width, bar_height),
+                None => return Ok(()),
+            },
+        };
+        let colors = ss.config.output_colors(&self.output.name);
+        let bg = computed.block.background.unwrap_or(colors.background);
+        let fg = computed.block.color.unwrap_or(colors.color);
+        tooltip.show(
+            conn,
+            (self.surface, self.layer_surface),
+            anchor,
+            ss.config.output_position(&self.output.name) == Position::Top,
+            tooltip::Content {
+                text,
+                height,
+                fg: ss.config.readable(fg, bg),
+                bg,
+                scale: self.output.scale,
+            },
+        );
+        Ok(())
+    }
+
+    /// Finish dragging a tag with the left button: ask the window manager to move things from the
+    /// dragged tag to the one under the pointer.
+    pub fn release(
+        &mut self,
+        conn: &mut Connection<State>,
+        ss: &mut SharedState,
+        button: PointerBtn,
+        seat: WlSeat,
+        x: f64,
+        y: f64,
+    ) {
+        if button != PointerBtn::Left {
+            return;
+        }
+        let Some(from) = self.dragged_tag.take() else {
+            return;
+        };
+        if self.set_drag_target(None) {
+            self.redraw(conn, ss);
+        }
+        if let Some(to) = self.tag_at(x, y).filter(|&to| to != from) {
+            debug!("{}: tag {from} dragged onto tag {to}", self.output.name);
+            ss.wm_info_provider
+                .drag_tag(conn, self.wm_output(), seat, from, to);
+        }
+    }
+
+    /// Switch to the next or the previous tag like scrolling over the tags, see `[swipe]` in README.
+    pub fn swipe(
+        &self,
+        conn: &mut Connection<State>,
+        ss: &mut SharedState,
+        seat: WlSeat,
+        btn: PointerBtn,
+    ) {
+        if self.hidden {
+            return;
+        }
+        debug!("{}: swiped, {btn:?}", self.output.name);
+        ss.wm_info_provider
+            .click_on_tag(conn, self.wm_output(), seat, None, btn);
+    }
+
+    /// The bar has gained or lost the keyboard focus. The first block is selected on focus.
+    pub fn set_keyboard_focus(
+        &mut self,
+        conn: &mut Connection<State>,
+        ss: &mut SharedState,
+        focused: bool,
+    ) {
+        self.keyboard_focused = focused;
+        if focused && self.selected_block.is_none() && self.blocks_count() > 0 {
+            self.selected_block = Some(0);
+        }
+        self.full_damage = true;
+        self.redraw(conn, ss);
+    }
+
+    /// Handle a key pressed while the bar has the keyboard focus, see `keyboard_navigation` in
+    /// README. `key` is a Linux input event code.
+    pub fn key(
+        &mut self,
+        conn: &mut Connection<State>,
+        ss: &mut SharedState,
+        seat: WlSeat,
+        key: u32,
+    ) -> anyhow::Result<()> {
+        if self.hidden {
+            return Ok(());
+        }
+        match key {
+            keycode::LEFT | keycode::RIGHT => {
+                let btn = if key == keycode::LEFT {
+                    PointerBtn::WheelUp
+                } else {
+                    PointerBtn::WheelDown
+                };
+                ss.wm_info_provider
+                    .click_on_tag(conn, self.wm_output(), seat, None, btn);
+            }
+            keycode::NUM_1..=keycode::NUM_9 => {
+                if let Some((x, width)) = self.tags_btns.get((key - keycode::NUM_1) as usize) {
+                    self.click(conn, ss, PointerBtn::Left, seat, x + width * 0.5, 0.0)?;
+                }
+            }
+            keycode::UP | keycode::DOWN => {
+                let count = self.blocks_count();
+                if count == 0 {
+                    return Ok(());
+                }
+                self.selected_block = Some(match (self.selected_block, key) {
+                    (None, _) => 0,
+                    (Some(i), keycode::UP) => (i + count - 1) % count,
+                    (Some(i), _) => (i + 1) % count,
+                });
+                self.full_damage = true;
+                self.redraw(conn, ss);
+            }
+            keycode::ESC => {
+                self.selected_block = None;
+                self.full_damage = true;
+                self.redraw(conn, ss);
+            }
+            keycode::ENTER | keycode::KP_ENTER => {
+                if let Some((x, y, width)) = self.selected_block_position() {
+                    let row_height = ss.config.output_height(&self.output.name) as f64;
+                    self.click(
+                        conn,
+                        ss,
+                        PointerBtn::Left,
+                        seat,
+                        x + width * 0.5,
+                        y + row_height * 0.5,
+                    )?;
+                }
+            }
+            _ => (),
         }
         Ok(())
     }
 
+    /// The number of blocks in both rows.
+    fn blocks_count(&self) -> usize {
+        self.blocks_btns.len() + self.wrapped_blocks_btns.len()
+    }
+
+    /// The offset, the vertical offset and the width of the block selected with the keyboard.
+    fn selected_block_position(&self) -> Option<(f64, f64, f64)> {
+        let i = self.selected_block?;
+        match self.blocks_btns.get(i) {
+            Some((x, width)) => Some((x, 0.0, width)),
+            None => {
+                let row_height = (self.height / self.rows) as f64;
+                let (x, width) = self.wrapped_blocks_btns.get(i - self.blocks_btns.len())?;
+                Some((x, row_height, width))
+            }
+        }
+    }
+
+    /// The id of the tag at (`x`, `y`).
+    fn tag_at(&self, x: f64, y: f64) -> Option<u32> {
+        if self.in_second_row(y) {
+            return None;
+        }
+        self.tags_btns.click(x).copied()
+    }
+
+    /// Returns whether the target has changed, damaging the old and the new one.
+    fn set_drag_target(&mut self, target: Option<u32>) -> bool {
+        if target == self.drag_target {
+            return false;
+        }
+        for tag in [self.drag_target, target].into_iter().flatten() {
+            if let Some((x, width)) = self.tags_btns.position_of(&tag) {
+                self.add_damage(x, width);
+            }
+        }
+        self.drag_target = target;
+        true
+    }
+
+    fn add_damage(&mut self, x: f64, width: f64) {
+        self.partial_damage = Some(match self.partial_damage {
+            Some((x0, width0)) => {
+                let left = x0.min(x);
+                (left, (x0 + width0).max(x + width) - left)
+            }
+            None => (x, width),
+        });
+    }
+
+    /// Redraw the whole bar.
     pub fn frame(&mut self, conn: &mut Connection<State>, ss: &mut SharedState) {
-        if !self.mapped {
+        if self.hidden {
+            return;
+        }
+
+        if ss.config.hide_when_empty && !self.hidden {
+            let empty = self.is_empty(ss);
+            if empty {
+                if !self.auto_hidden {
+                    debug!("{}: nothing to show, hiding", self.output.name);
+                    self.surface.attach(conn, None, 0, 0);
+                    self.surface.commit(conn);
+                }
+                self.auto_hidden = true;
+                self.mapped = false;
+                return;
+            }
+            if self.auto_hidden {
+                // Drawn once configured
+                debug!("{}: showing again", self.output.name);
+                self.show(conn, ss);
+                return;
+            }
+        }
+
+        self.full_damage = true;
+        self.redraw(conn, ss);
+    }
+
+    /// Whether there are no blocks, no mode and at most one non-urgent tag to show.
+    fn is_empty(&self, ss: &SharedState) -> bool {
+        let content = ss.config.output_content(&self.output.name);
+        if content != BarContent::TagsOnly && !ss.blocks_cache.get_computed().is_empty() {
+            return false;
+        }
+        if !ss.config.output_show_tags(&self.output.name) || content == BarContent::BlocksOnly {
+            return true;
+        }
+        let mut shown = self.tags.iter().filter(|tag| {
+            tag.is_urgent
+                || tag.is_focused
+                || tag.is_active
+                || !ss.config.hide_inactive_tags
+                || tag.always_shown
+        });
+        self.mode_name.is_none() && shown.clone().count() <= 1 && !shown.any(|tag| tag.is_urgent)
+    }
+
+    /// Draw the bar, damaging only the changed parts unless `full_damage` is set.
+    fn redraw(&mut self, conn: &mut Connection<State>, ss: &mut SharedState) {
+        if !self.mapped || self.collapsed || self.powered_off {
             return;
         }
 
@@ -150,22 +918,107 @@ impl Bar {
             return;
         }
 
-        let (pix_width, pix_height, scale_f) = match self.scale120 {
-            Some(scale120) => (
-                // rounding halfway away from zero
-                (self.width * scale120 + 60) / 120,
-                (self.height * scale120 + 60) / 120,
-                scale120 as f64 / 120.0,
-            ),
-            None => (
-                self.width * self.output.scale,
-                self.height * self.output.scale,
-                self.output.scale as f64,
-            ),
-        };
+        // Drawn with the colors of the output, see `palette` in README
+        let colors = ss.config.output_colors(&self.output.name);
+        self.draw(conn, ss, &colors);
+    }
+
+    /// Draw the bar with `colors`.
+    fn draw(&mut self, conn: &mut Connection<State>, ss: &mut SharedState, colors: &Colors) {
+        let start = Instant::now();
+        let tags_recomputed = self.tags_computed.is_empty();
+        self.compute_layout(&ss.config, colors);
+        let animating = self.animate_tags(&ss.config, tags_recomputed, start);
+        let layout_done = Instant::now();
 
         let width_f = self.width as f64;
-        let height_f = self.height as f64;
+
+        let content = ss.config.output_content(&self.output.name);
+        let show_tags = content != BarContent::BlocksOnly;
+        let tags: &[ComputedTag] = if show_tags { &self.tags_computed } else { &[] };
+        let tags_style = ss.config.tags_style();
+        let layout_name = self
+            .layout_name_computed
+            .as_ref()
+            .filter(|_| show_tags && ss.config.show_layout_name);
+        let mode = self
+            .mode_computed
+            .as_ref()
+            .filter(|_| show_tags && ss.config.show_mode);
+        let tags_area_width = tags_width(tags, &tags_style)
+            + layout_name.map_or(0.0, |text| text.width)
+            + mode.map_or(0.0, |text| text.width);
+        // The output name is always at the far left
+        let output_name = self
+            .output_name_computed
+            .as_ref()
+            .filter(|_| ss.config.show_output_name);
+        let output_name_width = output_name.map_or(0.0, |text| text.width);
+        // The tray is always at the far right
+        let tray = ss
+            .tray
+            .as_ref()
+            .filter(|_| ss.config.output_tray(&self.output.name));
+        let row_height = ss.config.output_height(&self.output.name);
+        let tray_start = width_f - tray.map_or(0.0, |tray| tray.width(row_height as f64));
+        // The divider is on the side of the tags which faces the blocks, if both are shown
+        let divider = &ss.config.divider;
+        let divider_width =
+            if divider.enable && content == BarContent::Full && tags_area_width > 0.0 {
+                match &self.divider_computed {
+                    Some(text) => text.width,
+                    None => divider.width + divider.gap * 2.0,
+                }
+            } else {
+                0.0
+            };
+        let blocks_style = ss.config.blocks_style(colors);
+        let blocks = ss
+            .blocks_cache
+            .get_scaled(ss.config.output_font_scale(&self.output.name));
+        let tags_start = match ss.config.tags_position {
+            TagsPosition::Left => output_name_width,
+            // Centered, unless the blocks on the left need the room
+            TagsPosition::Center => {
+                let blocks_width = match content {
+                    BarContent::TagsOnly => 0.0,
+                    _ => blocks_width(&blocks_style, blocks),
+                };
+                let centered = ((tray_start - tags_area_width) * 0.5).round();
+                let after_blocks = (output_name_width + blocks_width + divider_width).ceil();
+                centered.max(after_blocks.min(tray_start - tags_area_width))
+            }
+            TagsPosition::Right => tray_start - tags_area_width,
+        };
+        let divider_start = match ss.config.tags_position {
+            TagsPosition::Left => tags_start + tags_area_width,
+            TagsPosition::Center | TagsPosition::Right => tags_start - divider_width,
+        };
+
+        // The blocks take the remaining space, and the whole second row if they do not fit
+        let (blocks_left, blocks_right) = match ss.config.tags_position {
+            _ if !show_tags => (output_name_width, tray_start),
+            TagsPosition::Left => (divider_start + divider_width, tray_start),
+            TagsPosition::Center | TagsPosition::Right => (output_name_width, divider_start),
+        };
+        let split = match ss.config.blocks_overflow {
+            BlocksOverflow::Grow if content != BarContent::TagsOnly => {
+                split_blocks(&blocks_style, blocks, blocks_right - blocks_left)
+            }
+            _ => None,
+        };
+        let rows = if split.is_some() { 2 } else { 1 };
+        if rows != self.rows {
+            debug!("{}: showing the blocks in {rows} rows", self.output.name);
+            self.rows = rows;
+            self.height = row_height * rows;
+            self.full_damage = true;
+            self.set_size(conn, &ss.config);
+        }
+
+        let (pix_width, pix_height, scale_f) = self.buffer_size();
+
+        let height_f = row_height as f64;
 
         let (buffer, canvas) = ss
             .shm
@@ -203,139 +1056,262 @@ impl Bar {
             cairo_ctx.save().unwrap();
             cairo_ctx.set_operator(cairo::Operator::Source);
         }
-        ss.config.background.apply(&cairo_ctx);
+        colors.background.apply(&cairo_ctx);
         cairo_ctx.paint().unwrap();
         if ss.config.blend {
             cairo_ctx.restore().unwrap();
         }
 
-        // Compute tags
-        if ss.config.show_tags && self.tags_computed.is_empty() {
-            for tag in &self.tags {
-                let (bg, fg) = if tag.is_urgent {
-                    (ss.config.tag_urgent_bg, ss.config.tag_urgent_fg)
-                } else if tag.is_focused {
-                    (ss.config.tag_focused_bg, ss.config.tag_focused_fg)
-                } else if tag.is_active {
-                    (ss.config.tag_bg, ss.config.tag_fg)
-                } else if !ss.config.hide_inactive_tags {
-                    (ss.config.tag_inactive_bg, ss.config.tag_inactive_fg)
-                } else {
-                    continue;
-                };
-                let comp = compute_tag_label(&tag.name, &ss.config);
-                self.tags_computed
-                    .push((tag.id, ColorPair { bg, fg }, comp));
-            }
+        // Display the output name
+        if let Some(text) = output_name {
+            text.render(
+                &cairo_ctx,
+                RenderOptions {
+                    x_offset: 0.0,
+                    bar_height: height_f,
+                    fg_color: ss
+                        .config
+                        .readable(colors.tag_inactive_fg, colors.background),
+                    bg_color: None,
+                    r_left: 0.0,
+                    r_right: 0.0,
+                    overlap: 0.0,
+                    progress: None,
+                },
+            );
         }
 
         // Display tags
-        let mut offset_left = 0.0;
-        self.tags_btns.clear();
-        for (i, (id, color, computed)) in self.tags_computed.iter().enumerate() {
-            let left_joined = i != 0 && self.tags_computed[i - 1].1 == *color;
-            let right_joined =
-                i + 1 != self.tags_computed.len() && self.tags_computed[i + 1].1 == *color;
-            if i != 0 && !left_joined {
-                offset_left += ss.config.tags_margin;
-            }
-            computed.render(
+        let mut offset_left = render_tags(
+            &cairo_ctx,
+            tags,
+            &tags_style,
+            &mut self.tags_btns,
+            tags_start,
+            height_f,
+        );
+
+        // Outline the tag a tag is being dragged onto
+        if let Some(target) = self.drag_target {
+            let tag = tags.iter().find(|tag| tag.id == target);
+            if let (Some(tag), Some((x, width))) = (tag, self.tags_btns.position_of(&target)) {
+                let inset = DRAG_OUTLINE_WIDTH * 0.5;
+                tag.colors.fg.apply(&cairo_ctx);
+                cairo_ctx.set_line_width(DRAG_OUTLINE_WIDTH);
+                cairo_ctx.rectangle(
+                    x + inset,
+                    inset,
+                    width - DRAG_OUTLINE_WIDTH,
+                    height_f - DRAG_OUTLINE_WIDTH,
+                );
+                cairo_ctx.stroke().unwrap();
+            }
+        }
+
+        // Display layout name
+        self.layout_btn.clear();
+        if let Some(text) = layout_name {
+            text.render(
                 &cairo_ctx,
                 RenderOptions {
                     x_offset: offset_left,
                     bar_height: height_f,
-                    fg_color: color.fg,
-                    bg_color: Some(color.bg),
-                    r_left: if left_joined { 0.0 } else { ss.config.tags_r },
-                    r_right: if right_joined { 0.0 } else { ss.config.tags_r },
+                    fg_color: ss
+                        .config
+                        .readable(colors.tag_inactive_fg, colors.background),
+                    bg_color: None,
+                    r_left: 0.0,
+                    r_right: 0.0,
                     overlap: 0.0,
+                    progress: None,
                 },
             );
-            self.tags_btns.push(offset_left, computed.width, *id);
-            offset_left += computed.width;
+            self.layout_btn.push(offset_left, text.width, ());
+            offset_left += text.width;
         }
 
-        // Display layout name
-        if ss.config.show_layout_name {
-            if let Some(layout_name) = &self.layout_name {
-                let text = self.layout_name_computed.get_or_insert_with(|| {
-                    ComputedText::new(
-                        layout_name,
-                        text::Attributes {
-                            font: &ss.config.font,
-                            padding_left: 25.0,
-                            padding_right: 25.0,
-                            min_width: None,
-                            align: Default::default(),
-                            markup: false,
-                        },
-                    )
-                });
-                text.render(
+        // Display mode
+        self.mode_btn.clear();
+        if let Some(text) = mode {
+            text.render(
+                &cairo_ctx,
+                RenderOptions {
+                    x_offset: offset_left,
+                    bar_height: height_f,
+                    fg_color: ss
+                        .config
+                        .readable(colors.tag_urgent_fg, colors.tag_urgent_bg),
+                    bg_color: Some(colors.tag_urgent_bg),
+                    r_left: ss.config.tags_r,
+                    r_right: ss.config.tags_r,
+                    overlap: 0.0,
+                    progress: None,
+                },
+            );
+            self.mode_btn.push(offset_left, text.width, ());
+        }
+
+        // Display the divider
+        if divider_width > 0.0 {
+            let color = divider.color.unwrap_or(colors.separator);
+            match &self.divider_computed {
+                Some(text) => text.render(
                     &cairo_ctx,
                     RenderOptions {
-                        x_offset: offset_left,
+                        x_offset: divider_start,
                         bar_height: height_f,
-                        fg_color: ss.config.tag_inactive_fg,
+                        fg_color: color,
                         bg_color: None,
                         r_left: 0.0,
                         r_right: 0.0,
                         overlap: 0.0,
+                        progress: None,
                     },
-                );
-                offset_left += text.width;
+                ),
+                None if divider.width > 0.0 => {
+                    let x = divider_start + divider_width * 0.5;
+                    color.apply(&cairo_ctx);
+                    cairo_ctx.set_line_width(divider.width);
+                    cairo_ctx.move_to(x, height_f * 0.1);
+                    cairo_ctx.line_to(x, height_f * 0.9);
+                    cairo_ctx.stroke().unwrap();
+                }
+                None => (),
             }
         }
 
-        // Display mode
-        if ss.config.show_mode {
-            if let Some(mode) = &self.mode_name {
-                let text = self.mode_computed.get_or_insert_with(|| {
-                    ComputedText::new(
-                        mode,
-                        text::Attributes {
-                            font: &ss.config.font,
-                            padding_left: 10.0,
-                            padding_right: 10.0,
-                            min_width: None,
-                            align: Default::default(),
-                            markup: false,
-                        },
-                    )
-                });
-                text.render(
+        // Display the tray
+        self.tray_btns.clear();
+        if let Some(tray) = tray {
+            tray.render(
+                &cairo_ctx,
+                tray::RenderOptions {
+                    x_offset: tray_start,
+                    bar_height: height_f,
+                    padding: ss.config.tray_padding,
+                    scale: scale_f,
+                    font: &ss.config.font,
+                    fg_color: ss.config.readable(colors.color, colors.background),
+                },
+                &mut self.tray_btns,
+            );
+        }
+
+        // Display the blocks
+        self.wrapped_blocks_btns.clear();
+        if content == BarContent::TagsOnly {
+            self.blocks_btns.clear();
+            self.truncated_blocks.clear();
+        } else {
+            let blocks_align = match ss.config.tags_position {
+                TagsPosition::Left => Align::Right,
+                TagsPosition::Center | TagsPosition::Right => Align::Left,
+            };
+            let (first_row, second_row) = blocks.split_at(split.unwrap_or(blocks.len()));
+            self.truncated_blocks = render_blocks(
+                &cairo_ctx,
+                &blocks_style,
+                first_row,
+                &mut self.blocks_btns,
+                blocks_left,
+                blocks_right,
+                blocks_align,
+                height_f,
+            );
+            if !second_row.is_empty() {
+                cairo_ctx.save().unwrap();
+                cairo_ctx.translate(0.0, height_f);
+                self.truncated_blocks.extend(render_blocks(
                     &cairo_ctx,
-                    RenderOptions {
-                        x_offset: offset_left,
-                        bar_height: height_f,
-                        fg_color: ss.config.tag_urgent_fg,
-                        bg_color: Some(ss.config.tag_urgent_bg),
-                        r_left: ss.config.tags_r,
-                        r_right: ss.config.tags_r,
-                        overlap: 0.0,
-                    },
+                    &blocks_style,
+                    second_row,
+                    &mut self.wrapped_blocks_btns,
+                    0.0,
+                    width_f,
+                    blocks_align,
+                    height_f,
+                ));
+                cairo_ctx.restore().unwrap();
+            }
+
+            // Dim the blocks which may be out of date
+            if ss.blocks_stale {
+                cairo_ctx.save().unwrap();
+                cairo_ctx.set_operator(cairo::Operator::Over);
+                cairo_ctx.rectangle(blocks_left, 0.0, blocks_right - blocks_left, height_f);
+                if !second_row.is_empty() {
+                    cairo_ctx.rectangle(0.0, height_f, width_f, height_f);
+                }
+                cairo_ctx.clip();
+                colors.background.apply(&cairo_ctx);
+                cairo_ctx.paint_with_alpha(STALE_DIM).unwrap();
+                cairo_ctx.restore().unwrap();
+            }
+
+            // Tint the block under the pointer
+            if let (Some(color), Some(block)) = (colors.block_hover_bg, &self.hovered_block) {
+                let position = match self.blocks_btns.position_of(block) {
+                    Some((x, width)) => Some((x, 0.0, width)),
+                    None => self
+                        .wrapped_blocks_btns
+                        .position_of(block)
+                        .map(|(x, width)| (x, height_f, width)),
+                };
+                if let Some((x, y, width)) = position {
+                    cairo_ctx.save().unwrap();
+                    cairo_ctx.set_operator(cairo::Operator::Over);
+                    color.apply(&cairo_ctx);
+                    cairo_ctx.rectangle(x, y, width, height_f);
+                    cairo_ctx.fill().unwrap();
+                    cairo_ctx.restore().unwrap();
+                }
+            }
+
+            // Outline the block selected with the keyboard
+            if self.selected_block >= Some(self.blocks_count()) {
+                self.selected_block = self.blocks_count().checked_sub(1);
+            }
+            if let Some((x, y, width)) = self
+                .selected_block_position()
+                .filter(|_| self.keyboard_focused)
+            {
+                let inset = DRAG_OUTLINE_WIDTH * 0.5;
+                colors.color.apply(&cairo_ctx);
+                cairo_ctx.set_line_width(DRAG_OUTLINE_WIDTH);
+                cairo_ctx.rectangle(
+                    x + inset,
+                    y + inset,
+                    width - DRAG_OUTLINE_WIDTH,
+                    height_f - DRAG_OUTLINE_WIDTH,
                 );
-                offset_left += text.width;
+                cairo_ctx.stroke().unwrap();
             }
         }
 
-        // Display the blocks
-        render_blocks(
-            &cairo_ctx,
-            &ss.config,
-            ss.blocks_cache.get_computed(),
-            &mut self.blocks_btns,
-            offset_left,
-            width_f,
-            height_f,
-        );
+        if ss.faded {
+            cairo_ctx.set_operator(cairo::Operator::DestIn);
+            cairo_ctx.set_source_rgba(0.0, 0.0, 0.0, ss.config.idle.opacity);
+            cairo_ctx.paint().unwrap();
+        }
+
+        drop(cairo_ctx);
+        let paint_done = Instant::now();
 
         self.viewport
             .set_destination(conn, self.width as i32, self.height as i32);
 
         self.surface
             .attach(conn, Some(buffer.into_wl_buffer()), 0, 0);
-        self.surface.damage(conn, 0, 0, i32::MAX, i32::MAX);
+        match self.partial_damage.take() {
+            Some((x, width)) if !self.full_damage => {
+                let left = x.floor() as i32;
+                let right = (x + width).ceil() as i32;
+                self.surface
+                    .damage(conn, left, 0, right - left, self.height as i32);
+            }
+            _ => self.surface.damage(conn, 0, 0, i32::MAX, i32::MAX),
+        }
+        self.full_damage = false;
 
         self.throttle = Some(self.surface.frame_with_cb(conn, |ctx| {
             if let Some(bar) = ctx
@@ -347,200 +1323,477 @@ impl Bar {
                 bar.throttle = None;
                 if bar.throttled {
                     bar.throttled = false;
-                    bar.frame(ctx.conn, &mut ctx.state.shared_state);
+                    bar.redraw(ctx.conn, &mut ctx.state.shared_state);
                 }
             }
         }));
+        if animating {
+            // Drawn again once this frame is shown
+            self.throttled = true;
+            self.full_damage = true;
+        }
 
         self.surface.commit(conn);
+
+        if let Some(profiler) = &mut ss.profiler {
+            profiler.record(
+                &self.output.name,
+                FrameTimes {
+                    layout: layout_done - start,
+                    paint: paint_done - layout_done,
+                    commit: paint_done.elapsed(),
+                },
+            );
+        }
     }
 
-    pub fn show(&mut self, conn: &mut Connection<State>, shared_state: &SharedState) {
-        assert!(!self.mapped);
+    /// The fractional scale preferred by the compositor, or the integer scale of the output.
+    pub fn scale(&self) -> f64 {
+        match self.scale120 {
+            Some(scale120) => scale120 as f64 / 120.0,
+            None => self.output.scale as f64,
+        }
+    }
 
-        self.hidden = false;
+    /// The size of the buffer in pixels, and the scale.
+    fn buffer_size(&self) -> (u32, u32, f64) {
+        match self.scale120 {
+            Some(scale120) => (
+                // rounding halfway away from zero
+                (self.width * scale120 + 60) / 120,
+                (self.height * scale120 + 60) / 120,
+                scale120 as f64 / 120.0,
+            ),
+            None => (
+                self.width * self.output.scale,
+                self.height * self.output.scale,
+                self.output.scale as f64,
+            ),
+        }
+    }
 
-        let config = &shared_state.config;
+    /// Attach a transparent buffer, keeping the surface mapped and its exclusive zone.
+    fn clear(&mut self, conn: &mut Connection<State>, ss: &mut SharedState) {
+        let (pix_width, pix_height, _) = self.buffer_size();
+        let (buffer, canvas) = ss
+            .shm
+            .alloc_buffer(
+                conn,
+                BufferSpec {
+                    width: pix_width,
+                    height: pix_height,
+                    stride: pix_width * 4,
+                    format: wl_shm::Format::Argb8888,
+                },
+            )
+            .unwrap();
+        canvas.fill(0);
+        self.viewport
+            .set_destination(conn, self.width as i32, self.height as i32);
+        self.surface
+            .attach(conn, Some(buffer.into_wl_buffer()), 0, 0);
+        self.surface.damage(conn, 0, 0, i32::MAX, i32::MAX);
+        self.surface.commit(conn);
+    }
 
-        self.layer_surface.set_size(conn, 0, config.height);
-        self.layer_surface.set_anchor(conn, config.position.into());
-        self.layer_surface.set_margin(
-            conn,
-            config.margin_top,
-            config.margin_right,
-            config.margin_bottom,
-            config.margin_left,
-        );
-        self.layer_surface.set_exclusive_zone(
-            conn,
-            (shared_state.config.height) as i32
-                + if config.position == Position::Top {
-                    shared_state.config.margin_bottom
+    /// Lay out the text which has changed since the last frame.
+    fn compute_layout(&mut self, config: &Config, colors: &Colors) {
+        let font_scale = config.output_font_scale(&self.output.name);
+
+        // Compute tags
+        if config.output_show_tags(&self.output.name) && self.tags_computed.is_empty() {
+            for tag in &self.tags {
+                let (bg, fg) = if tag.is_urgent {
+                    (colors.tag_urgent_bg, colors.tag_urgent_fg)
+                } else if tag.is_focused {
+                    (colors.tag_focused_bg, colors.tag_focused_fg)
+                } else if tag.is_active {
+                    (colors.tag_bg, colors.tag_fg)
+                } else if !config.hide_inactive_tags || tag.always_shown {
+                    (colors.tag_inactive_bg, colors.tag_inactive_fg)
                 } else {
-                    shared_state.config.margin_top
+                    continue;
+                };
+                let (bg, fg) = if self.hovered_tag == Some(tag.id) {
+                    (
+                        colors.tag_hover_bg.unwrap_or(bg),
+                        colors.tag_hover_fg.unwrap_or(match colors.tag_hover_bg {
+                            Some(bg) if config.auto_contrast => fg.readable_on(bg),
+                            _ => fg,
+                        }),
+                    )
+                } else {
+                    (bg, fg)
+                };
+                let fg = config.readable(fg, bg);
+                let mut label = match tag.windows {
+                    Some(windows) if config.show_window_count && windows > 0 => {
+                        format!("{}{}", tag.name, superscript(windows))
+                    }
+                    _ => tag.name.clone(),
+                };
+                if tag.is_urgent && config.urgent_marker == Some(UrgentMarker::Glyph) {
+                    label.insert_str(0, "! ");
+                }
+                self.tags_computed.push(ComputedTag {
+                    id: tag.id,
+                    colors: ColorPair { bg, fg },
+                    label: compute_tag_label(&label, tag.is_focused, config, font_scale),
+                    urgent: tag.is_urgent,
+                });
+            }
+        }
+
+        if config.show_layout_name && self.layout_name_computed.is_none() {
+            let layout_name = self
+                .layout_name
+                .as_deref()
+                .and_then(|n| config.format_layout_name(n));
+            if let Some(layout_name) = &layout_name {
+                self.layout_name_computed = Some(ComputedText::new(
+                    layout_name,
+                    text::Attributes {
+                        font: &config.layout_font().scaled(font_scale),
+                        padding_left: 25.0,
+                        padding_right: 25.0,
+                        min_width: None,
+                        align: Default::default(),
+                        markup: false,
+                    },
+                ));
+            }
+        }
+
+        if config.show_mode && self.mode_computed.is_none() {
+            if let Some(mode) = &self.mode_name {
+                self.mode_computed = Some(ComputedText::new(
+                    mode,
+                    text::Attributes {
+                        font: &config.mode_font().scaled(font_scale),
+                        padding_left: 10.0,
+                        padding_right: 10.0,
+                        min_width: None,
+                        align: Default::default(),
+                        markup: false,
+                    },
+                ));
+            }
+        }
+
+        if config.show_output_name && self.output_name_computed.is_none() {
+            self.output_name_computed = Some(ComputedText::new(
+                config.output_label(&self.output.name),
+                text::Attributes {
+                    font: &config.font.scaled(font_scale),
+                    padding_left: 10.0,
+                    padding_right: 10.0,
+                    min_width: None,
+                    align: Default::default(),
+                    markup: false,
                 },
-        );
+            ));
+        }
 
-        self.surface.commit(conn);
+        if config.divider.enable && self.divider_computed.is_none() {
+            if let Some(text) = &config.divider.text {
+                self.divider_computed = Some(ComputedText::new(
+                    text,
+                    text::Attributes {
+                        font: &config.font.scaled(font_scale),
+                        padding_left: config.divider.gap,
+                        padding_right: config.divider.gap,
+                        min_width: None,
+                        align: Default::default(),
+                        markup: false,
+                    },
+                ));
+            }
+        }
     }
 
-    pub fn hide(&mut self, conn: &mut Connection<State>) {
-        self.hidden = true;
-        self.mapped = false;
-        self.surface.attach(conn, None, 0, 0);
+    /// Fade the tags whose colors have changed from the colors they were last drawn with, see
+    /// `animations` in README. `recomputed` tells whether the tags have just been laid out with
+    /// their new colors. Returns whether a fade is still in progress.
+    fn animate_tags(&mut self, config: &Config, recomputed: bool, now: Instant) -> bool {
+        if !config.animations {
+            self.tag_transitions.clear();
+            self.shown_tag_colors.clear();
+            return false;
+        }
+
+        if recomputed {
+            self.tag_transitions
+                .retain(|t| self.tags_computed.iter().any(|tag| tag.id == t.id));
+            for tag in &self.tags_computed {
+                let transition = self.tag_transitions.iter().position(|t| t.id == tag.id);
+                if let Some(i) = transition {
+                    if self.tag_transitions[i].to == tag.colors {
+                        continue;
+                    }
+                    self.tag_transitions.swap_remove(i);
+                }
+                let shown = self
+                    .shown_tag_colors
+                    .iter()
+                    .find(|(id, _)| *id == tag.id)
+                    .map(|(_, colors)| *colors);
+                if let Some(from) = shown.filter(|&from| from != tag.colors) {
+                    self.tag_transitions.push(TagTransition {
+                        id: tag.id,
+                        from,
+                        to: tag.colors,
+                        start: now,
+                    });
+                }
+            }
+        }
+
+        let duration = config.animation_duration.0.as_secs_f64();
+        for tag in &mut self.tags_computed {
+            let Some(i) = self.tag_transitions.iter().position(|t| t.id == tag.id) else {
+                continue;
+            };
+            let t = &self.tag_transitions[i];
+            let progress = now.saturating_duration_since(t.start).as_secs_f64() / duration;
+            if progress >= 1.0 || duration == 0.0 {
+                tag.colors = t.to;
+                self.tag_transitions.swap_remove(i);
+            } else {
+                let progress = config.animation_easing.apply(progress);
+                tag.colors = ColorPair {
+                    bg: t.from.bg.mix(t.to.bg, progress),
+                    fg: t.from.fg.mix(t.to.fg, progress),
+                };
+            }
+        }
+        self.shown_tag_colors = self
+            .tags_computed
+            .iter()
+            .map(|tag| (tag.id, tag.colors))
+            .collect();
+        !self.tag_transitions.is_empty()
+    }
+
+    pub fn show(&mut self, conn: &mut Connection<State>, shared_state: &mut SharedState) {
+        if self.hidden && self.mapped {
+            // Kept mapped while hidden, see `hidden_keeps_exclusive_zone` in README
+            self.hidden = false;
+            self.frame(conn, shared_state);
+            return;
+        }
+        assert!(!self.mapped);
+
+        self.hidden = false;
+        self.auto_hidden = false;
+        self.collapsed = false;
+        self.left_at = Some(Instant::now());
+        self.place(conn, &shared_state.config);
         self.surface.commit(conn);
     }
-}
 
-#[allow(clippy::too_many_arguments)]
-fn render_blocks(
-    context: &cairo::Context,
-    config: &Config,
-    blocks: &[ComputedBlock],
-    buttons: &mut ButtonManager<(Option<String>, Option<String>)>,
-    offset_left: f64,
-    full_width: f64,
-    full_height: f64,
-) {
-    context.rectangle(offset_left, 0.0, full_width - offset_left, full_height);
-    context.clip();
-
-    struct LogialBlock<'a> {
-        blocks: Vec<&'a ComputedBlock>,
-        delta: f64,
-        switched_to_short: bool,
-        separator: bool,
-        separator_block_width: u8,
-    }
-
-    let mut blocks_computed = Vec::new();
-    let mut blocks_width = 0.0;
-    let mut s_start = 0;
-    while s_start < blocks.len() {
-        let mut s_end = s_start + 1;
-        let series_name = &blocks[s_start].block.name;
-        while s_end < blocks.len()
-            && blocks[s_end - 1].block.separator_block_width == 0
-            && &blocks[s_end].block.name == series_name
-        {
-            s_end += 1;
+    /// Forget the texts computed with the old config and apply its size and position. The bar is
+    /// redrawn by the caller, or once configured if its size changed.
+    pub fn config_changed(&mut self, conn: &mut Connection<State>, config: &Config) {
+        self.tags_computed.clear();
+        self.layout_name_computed = None;
+        self.mode_computed = None;
+        self.divider_computed = None;
+        self.output_name_computed = None;
+        self.full_damage = true;
+
+        if !self.mapped || self.collapsed {
+            return;
         }
+        self.place(conn, config);
+        if self.layer_surface.version() >= 2 {
+            self.layer_surface.set_layer(conn, config.layer.into());
+        }
+        set_keyboard_interactivity(conn, self.layer_surface, config);
+        self.surface.commit(conn);
+    }
 
-        let mut series = LogialBlock {
-            blocks: Vec::with_capacity(s_end - s_start),
-            delta: 0.0,
-            switched_to_short: false,
-            separator: blocks[s_end - 1].block.separator,
-            separator_block_width: blocks[s_end - 1].block.separator_block_width,
+    /// Apply the height, the position and the margins of the bar on this output, with the
+    /// overrides of `[output.NAME]`. Applied on the next commit.
+    fn place(&mut self, conn: &mut Connection<State>, config: &Config) {
+        let [top, right, bottom, left] = config.output_margins(&self.output.name);
+        self.height = config.output_height(&self.output.name) * self.rows;
+        self.set_size(conn, config);
+        self.layer_surface
+            .set_anchor(conn, config.output_position(&self.output.name).into());
+        self.layer_surface
+            .set_margin(conn, top, right, bottom, left);
+    }
+
+    /// Request `self.height` and reserve the space for it. Applied on the next commit.
+    fn set_size(&self, conn: &mut Connection<State>, config: &Config) {
+        self.layer_surface.set_size(conn, 0, self.height);
+        let [margin_top, _, margin_bottom, _] = config.output_margins(&self.output.name);
+        // With `auto_hide`, the windows would be resized whenever the bar collapses or expands
+        let exclusive_zone = if config.auto_hide.is_some() {
+            0
+        } else {
+            self.height as i32
+                + match config.output_position(&self.output.name) {
+                    Position::Top => margin_bottom,
+                    Position::Bottom => margin_top,
+                }
         };
+        self.layer_surface.set_exclusive_zone(conn, exclusive_zone);
+    }
 
-        for comp in &blocks[s_start..s_end] {
-            blocks_width += comp.full.width;
-            if let Some(short) = &comp.short {
-                series.delta += comp.full.width - short.width;
-            }
-            series.blocks.push(comp);
+    /// Shrink to a strip if the pointer has been away for `auto_hide`.
+    pub fn check_auto_hide(&mut self, conn: &mut Connection<State>, ss: &mut SharedState) {
+        let Some(timeout) = ss.config.auto_hide else {
+            return;
+        };
+        if self.collapsed
+            || self.hidden
+            || !self.mapped
+            || !self.left_at.is_some_and(|t| t.elapsed() >= timeout.0)
+        {
+            return;
         }
-        if s_end != blocks.len() {
-            blocks_width += series.separator_block_width as f64;
+        debug!("{}: collapsing", self.output.name);
+        self.collapsed = true;
+        self.height = AUTO_HIDE_STRIP;
+        if let Some(tooltip) = &mut ss.tooltip {
+            tooltip.bar_gone(conn, self.surface);
         }
-        blocks_computed.push(series);
-        s_start = s_end;
+        self.set_size(conn, &ss.config);
+        self.surface.commit(conn);
     }
 
-    // Progressively switch to short mode
-    if offset_left + blocks_width > full_width {
-        let mut deltas: Vec<_> = blocks_computed
-            .iter()
-            .map(|b| b.delta)
-            .enumerate()
-            .filter(|(_, delta)| *delta > 0.0)
-            .collect();
-        // Sort in descending order
-        deltas.sort_unstable_by(|(_, d1), (_, d2)| d2.total_cmp(d1));
-        for (to_switch, delta) in deltas {
-            blocks_computed[to_switch].switched_to_short = true;
-            blocks_width -= delta;
-            if offset_left + blocks_width <= full_width {
-                break;
-            }
-        }
-    }
-
-    // Remove all the empty blocks
-    for s in &mut blocks_computed {
-        s.blocks.retain(|text| {
-            (s.switched_to_short
-                && text
-                    .short
-                    .as_ref()
-                    .map_or(text.full.width > 0.0, |s| s.width > 0.0))
-                || (!s.switched_to_short && text.full.width > 0.0)
-        });
+    /// Restore the full size of the collapsed bar, it is redrawn once configured.
+    fn expand(&mut self, conn: &mut Connection<State>, ss: &mut SharedState) {
+        debug!("{}: expanding", self.output.name);
+        self.collapsed = false;
+        self.height = ss.config.output_height(&self.output.name) * self.rows;
+        self.set_size(conn, &ss.config);
+        self.surface.commit(conn);
     }
 
-    // Render blocks
-    buttons.clear();
-    let mut j = 0;
-    for series in blocks_computed {
-        let s_len = series.blocks.len();
-        for (i, computed) in series.blocks.into_iter().enumerate() {
-            let block = &computed.block;
-            let to_render = if series.switched_to_short {
-                computed.short.as_ref().unwrap_or(&computed.full)
-            } else {
-                &computed.full
-            };
-            j += 1;
-            to_render.render(
-                context,
-                RenderOptions {
-                    x_offset: full_width - blocks_width,
-                    bar_height: full_height,
-                    fg_color: block.color.unwrap_or(config.color),
-                    bg_color: block.background,
-                    r_left: if i == 0 { config.blocks_r } else { 0.0 },
-                    r_right: if i + 1 == s_len { config.blocks_r } else { 0.0 },
-                    overlap: config.blocks_overlap,
-                },
-            );
-            buttons.push(
-                full_width - blocks_width,
-                to_render.width,
-                (block.name.clone(), block.instance.clone()),
-            );
-            blocks_width -= to_render.width;
+    pub fn hide(&mut self, conn: &mut Connection<State>, ss: &mut SharedState) {
+        self.hidden = true;
+        self.auto_hidden = false;
+        if let Some(tooltip) = &mut ss.tooltip {
+            tooltip.bar_gone(conn, self.surface);
         }
-        if j != blocks.len() && series.separator_block_width > 0 {
-            let w = series.separator_block_width as f64;
-            if series.separator && config.separator_width > 0.0 {
-                config.separator.apply(context);
-                context.set_line_width(config.separator_width);
-                context.move_to(full_width - blocks_width + w * 0.5, full_height * 0.1);
-                context.line_to(full_width - blocks_width + w * 0.5, full_height * 0.9);
-                context.stroke().unwrap();
-            }
-            blocks_width -= w;
+        if ss.config.hidden_keeps_exclusive_zone && self.mapped {
+            // The windows keep their size, the bar is just not drawn
+            self.clear(conn, ss);
+            return;
         }
+        self.mapped = false;
+        self.surface.attach(conn, None, 0, 0);
+        self.surface.commit(conn);
     }
+}
+
+/// Run the script bound to the event, if any. Returns whether the default action should be
+/// performed.
+fn run_script(
+    conn: &mut Connection<State>,
+    ss: &mut SharedState,
+    seat: WlSeat,
+    target: Target,
+    button: PointerBtn,
+) -> anyhow::Result<bool> {
+    let Some(actions) = ss.scripts.run(target, button)? else {
+        return Ok(true);
+    };
+    let mut default = false;
+    for action in actions {
+        match action {
+            ScriptAction::River(args) => match ss.get_river() {
+                Some(river) => river.run_command(conn, seat, &args),
+                None => warn!("river commands are not supported by this window manager"),
+            },
+            ScriptAction::Shell(cmd) => spawn_command(&cmd)?,
+            ScriptAction::Default => default = true,
+        }
+    }
+    Ok(default)
+}
+
+/// Append `event` as a line of JSON to `path`, usually a FIFO. Nothing is written if no one is
+/// reading the FIFO.
+fn mirror_click_event(path: &Path, event: &i3bar_protocol::Event) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
 
-    context.reset_clip();
+/// The computed block with the name and the instance of `block`.
+fn find_block<'a>(
+    blocks: &'a [ComputedBlock],
+    block: &(Option<Rc<str>>, Option<Rc<str>>),
+) -> Option<&'a ComputedBlock> {
+    blocks
+        .iter()
+        .find(|computed| computed.block.name == block.0 && computed.block.instance == block.1)
 }
 
-pub fn compute_tag_label(label: &str, config: &Config) -> ComputedText {
+/// Write `n` with superscript digits.
+fn superscript(n: u32) -> String {
+    const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    n.to_string()
+        .chars()
+        .map(|c| DIGITS[c.to_digit(10).unwrap() as usize])
+        .collect()
+}
+
+pub fn compute_tag_label(
+    label: &str,
+    focused: bool,
+    config: &Config,
+    font_scale: f64,
+) -> ComputedText {
+    let (font, padding) = if focused {
+        (
+            config
+                .tag_focused_font
+                .as_ref()
+                .unwrap_or(config.tags_font()),
+            config.tag_focused_padding.unwrap_or(config.tags_padding),
+        )
+    } else {
+        (config.tags_font(), config.tags_padding)
+    };
     ComputedText::new(
         label,
         text::Attributes {
-            font: &config.font.0,
-            padding_left: config.tags_padding,
-            padding_right: config.tags_padding,
-            min_width: None,
-            align: Default::default(),
+            font: &font.scaled(font_scale),
+            padding_left: padding,
+            padding_right: padding,
+            min_width: config.tag_min_width,
+            align: Align::Center,
             markup: false,
         },
     )
 }
 
+/// Let the bar take the keyboard focus when it is clicked, if `keyboard_navigation` is set.
+fn set_keyboard_interactivity(
+    conn: &mut Connection<State>,
+    layer_surface: ZwlrLayerSurfaceV1,
+    config: &Config,
+) {
+    // On-demand focus is new in version 4
+    if layer_surface.version() >= 4 {
+        let interactivity = if config.keyboard_navigation {
+            zwlr_layer_surface_v1::KeyboardInteractivity::OnDemand
+        } else {
+            zwlr_layer_surface_v1::KeyboardInteractivity::None
+        };
+        layer_surface.set_keyboard_interactivity(conn, interactivity);
+    }
+}
+
 fn layer_surface_cb(ctx: EventCtx<State, ZwlrLayerSurfaceV1>) {
     match ctx.event {
         zwlr_layer_surface_v1::Event::Configure(args) => {
@@ -550,14 +1803,19 @@ fn layer_surface_cb(ctx: EventCtx<State, ZwlrLayerSurfaceV1>) {
                 .iter_mut()
                 .find(|bar| bar.layer_surface == ctx.proxy)
                 .unwrap();
-            if bar.hidden {
+            if bar.hidden && !bar.mapped {
                 return;
             }
             assert_ne!(args.width, 0);
+            debug!("{}: configured with width {}", bar.output.name, args.width);
             bar.width = args.width;
             bar.layer_surface.ack_configure(ctx.conn, args.serial);
             bar.mapped = true;
-            bar.frame(ctx.conn, &mut ctx.state.shared_state);
+            if bar.hidden || bar.collapsed {
+                bar.clear(ctx.conn, &mut ctx.state.shared_state);
+            } else {
+                bar.frame(ctx.conn, &mut ctx.state.shared_state);
+            }
         }
         zwlr_layer_surface_v1::Event::Closed => {
             let bar_index = ctx
@@ -566,6 +1824,10 @@ fn layer_surface_cb(ctx: EventCtx<State, ZwlrLayerSurfaceV1>) {
                 .iter()
                 .position(|bar| bar.layer_surface == ctx.proxy)
                 .unwrap();
+            debug!(
+                "{}: layer surface closed",
+                ctx.state.bars[bar_index].output.name
+            );
             ctx.state.drop_bar(ctx.conn, bar_index);
         }
         _ => (),
@@ -583,6 +1845,11 @@ fn fractional_scale_cb(ctx: EventCtx<State, WpFractionalScaleV1>) {
         .find(|b| b.fractional_scale == Some(ctx.proxy))
         .unwrap();
     if bar.scale120 != Some(scale120) {
+        debug!(
+            "{}: preferred scale {}",
+            bar.output.name,
+            scale120 as f64 / 120.0
+        );
         bar.scale120 = Some(scale120);
         bar.frame(ctx.conn, &mut ctx.state.shared_state);
     }
@@ -0,0 +1,175 @@
+//! Blocks pushed by other processes through a socket, see `listen` in README.
+//!
+//! Every client is a source of blocks, speaking the same protocols as the status command. The
+//! blocks of the clients are shown after the ones of the status command, in the order the clients
+//! connected. Every client is registered in the event loop on its own, and removed from it when it
+//! disconnects, when it sends an update longer than a megabyte or when the server is closed.
+
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::event_loop::{self, EventLoopCtx, Handle, Token};
+use crate::i3bar_protocol::{Block, Protocol};
+use crate::utils::{read_to_vec, try_lock};
+
+/// Clients sending a longer line or update without finishing it are dropped.
+const MAX_PENDING_LEN: usize = 1 << 20;
+
+pub struct BlocksServer {
+    listener: UnixListener,
+    clients: Vec<Client>,
+    socket_path: PathBuf,
+    _lock: File,
+}
+
+struct Client {
+    token: Token,
+    stream: UnixStream,
+    protocol: Protocol,
+    buf: Vec<u8>,
+    blocks: Vec<Block>,
+}
+
+impl BlocksServer {
+    /// Take the lock and bind the socket, replacing a stale one.
+    ///
+    /// Like the control socket, the socket is guarded by a lock file, next to it with `.lock`
+    /// appended. Fails if another instance holds the lock, or if something other than a socket is
+    /// in the way.
+    pub fn bind(socket_path: &Path) -> Result<Self> {
+        let mut lock_path = socket_path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let lock = File::create(&lock_path)
+            .with_context(|| format!("Failed to create {}", lock_path.display()))?;
+        ensure!(
+            try_lock(&lock)?,
+            "another instance of i3bar-river is listening on {}",
+            socket_path.display()
+        );
+
+        // We hold the lock, so a socket at the path is stale
+        match fs::symlink_metadata(socket_path) {
+            Ok(metadata) => {
+                ensure!(
+                    metadata.file_type().is_socket(),
+                    "{} exists and is not a socket",
+                    socket_path.display()
+                );
+                fs::remove_file(socket_path)
+                    .with_context(|| format!("Failed to remove {}", socket_path.display()))?;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e).context(format!("Failed to stat {}", socket_path.display())),
+        }
+
+        let listener = UnixListener::bind(socket_path)
+            .with_context(|| format!("Failed to bind {}", socket_path.display()))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            socket_path: socket_path.to_owned(),
+            _lock: lock,
+        })
+    }
+
+    /// Becomes readable when there are new clients, see [`BlocksServer::accept_clients`].
+    pub fn fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+
+    /// The blocks of all the clients.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.clients.iter().flat_map(|c| &c.blocks)
+    }
+
+    /// Accept the new clients and register them in the event loop.
+    pub fn accept_clients(&mut self, handle: &mut Handle) -> io::Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            debug!("new blocks client");
+            stream.set_nonblocking(true)?;
+            let fd = stream.as_raw_fd();
+            self.clients.push(Client {
+                token: handle.register_with_fd(fd, move |ctx| client_cb(ctx, fd)),
+                stream,
+                protocol: Protocol::Unknown,
+                buf: Vec::new(),
+                blocks: Vec::new(),
+            });
+        }
+    }
+
+    /// Remove the clients from the event loop. The socket is removed once the server is dropped.
+    pub fn close(self, handle: &mut Handle) {
+        for client in &self.clients {
+            handle.remove(client.token);
+        }
+    }
+}
+
+/// Read from the client with the socket `fd`, dropping it on errors.
+fn client_cb(ctx: EventLoopCtx, fd: RawFd) -> anyhow::Result<event_loop::Action> {
+    let server = ctx.state.shared_state.blocks_server.as_mut().unwrap();
+    let i = server
+        .clients
+        .iter()
+        .position(|c| c.stream.as_raw_fd() == fd)
+        .unwrap();
+    let (changed, action) = match server.clients[i].receive_blocks() {
+        Ok(changed) => (changed, event_loop::Action::Keep),
+        Err(e) => {
+            debug!("blocks client disconnected: {e}");
+            let client = server.clients.remove(i);
+            (!client.blocks.is_empty(), event_loop::Action::Unregister)
+        }
+    };
+    if changed {
+        ctx.state.update_blocks(ctx.conn);
+    }
+    Ok(action)
+}
+
+impl Drop for BlocksServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+impl Client {
+    /// Returns whether the blocks have changed. Fails when the client should be dropped.
+    fn receive_blocks(&mut self) -> io::Result<bool> {
+        match read_to_vec(&self.stream, &mut self.buf) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::BrokenPipe, "end of stream")),
+            Ok(_) => (),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        let rem = self.protocol.process_new_bytes(&self.buf)?;
+        let used = self.buf.len() - rem.len();
+        self.buf.drain(..used);
+        if self.buf.len() > MAX_PENDING_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidData, "update too long"));
+        }
+
+        match self.protocol.get_blocks(None) {
+            Some(blocks) => {
+                self.blocks = blocks;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Copying the text of the blocks to the clipboard, using wlr-data-control.
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::Write;
+
+use wayrs_client::global::{Globals, GlobalsExt};
+use wayrs_client::{Connection, EventCtx};
+
+use crate::i3bar_protocol::Block;
+use crate::protocol::*;
+use crate::state::State;
+
+const MIME_TYPES: &[&CStr] = &[
+    c"text/plain;charset=utf-8",
+    c"text/plain",
+    c"UTF8_STRING",
+    c"STRING",
+    c"TEXT",
+];
+
+pub struct Clipboard {
+    manager: ZwlrDataControlManagerV1,
+    devices: Vec<(WlSeat, ZwlrDataControlDeviceV1)>,
+    source: Option<ZwlrDataControlSourceV1>,
+    text: String,
+}
+
+impl Clipboard {
+    /// Returns `None` if the compositor does not support wlr-data-control.
+    pub fn bind(conn: &mut Connection<State>, globals: &Globals) -> Option<Self> {
+        Some(Self {
+            manager: globals.bind(conn, 1..=2).ok()?,
+            devices: Vec::new(),
+            source: None,
+            text: String::new(),
+        })
+    }
+
+    /// Set the clipboard of `seat` to `text`.
+    pub fn copy(&mut self, conn: &mut Connection<State>, seat: WlSeat, text: String) {
+        debug!("copying {text:?}");
+
+        if let Some(source) = self.source.take() {
+            source.destroy(conn);
+        }
+        let source = self.manager.create_data_source_with_cb(conn, source_cb);
+        for mime_type in MIME_TYPES {
+            source.offer(conn, (*mime_type).to_owned());
+        }
+
+        let device = match self.devices.iter().find(|(s, _)| *s == seat) {
+            Some((_, device)) => *device,
+            None => {
+                let device = self.manager.get_data_device_with_cb(conn, seat, device_cb);
+                self.devices.push((seat, device));
+                device
+            }
+        };
+        device.set_selection(conn, Some(source));
+
+        self.source = Some(source);
+        self.text = text;
+    }
+
+    pub fn seat_removed(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        if let Some(i) = self.devices.iter().position(|(s, _)| *s == seat) {
+            self.devices.swap_remove(i).1.destroy(conn);
+        }
+    }
+}
+
+fn source_cb(ctx: EventCtx<State, ZwlrDataControlSourceV1>) {
+    let Some(clipboard) = &mut ctx.state.shared_state.clipboard else {
+        return;
+    };
+    match ctx.event {
+        zwlr_data_control_source_v1::Event::Send(args) => {
+            if let Err(e) = File::from(args.fd).write_all(clipboard.text.as_bytes()) {
+                warn!("failed to send the clipboard contents: {e}");
+            }
+        }
+        zwlr_data_control_source_v1::Event::Cancelled => {
+            if clipboard.source == Some(ctx.proxy) {
+                clipboard.source = None;
+            }
+            ctx.proxy.destroy(ctx.conn);
+        }
+        _ => (),
+    }
+}
+
+fn device_cb(ctx: EventCtx<State, ZwlrDataControlDeviceV1>) {
+    // We never read the clipboard, so the offers are not needed
+    match ctx.event {
+        zwlr_data_control_device_v1::Event::Selection(Some(offer))
+        | zwlr_data_control_device_v1::Event::PrimarySelection(Some(offer)) => {
+            offer.destroy(ctx.conn);
+        }
+        zwlr_data_control_device_v1::Event::Finished => {
+            if let Some(clipboard) = &mut ctx.state.shared_state.clipboard {
+                clipboard.devices.retain(|(_, device)| *device != ctx.proxy);
+            }
+            ctx.proxy.destroy(ctx.conn);
+        }
+        _ => (),
+    }
+}
+
+/// The text of `block`, without markup.
+pub fn block_text(block: &Block) -> String {
+    if block.markup.as_deref() == Some("pango") {
+        match pangocairo::pango::parse_markup(&block.full_text, '\0') {
+            Ok((_, text, _)) => text.into(),
+            Err(_) => block.full_text.to_string(),
+        }
+    } else {
+        block.full_text.to_string()
+    }
+}
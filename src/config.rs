@@ -1,12 +1,17 @@
 use crate::color::Color;
+use crate::pointer_btn::PointerBtn;
 use crate::protocol::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 use anyhow::{Context, Result};
+use i3bar_river_core::layout::{BlocksStyle, TagsStyle, UrgentMarker};
+use i3bar_river_core::text::Multiline;
 use pangocairo::pango::FontDescription;
-use serde::{de, Deserialize};
+use serde::{de, Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 use std::{env, fmt};
 
 #[derive(Deserialize, Debug)]
@@ -14,6 +19,11 @@ use std::{env, fmt};
 pub struct Config {
     // command
     pub command: Option<String>,
+    pub protocol: StatusProtocol,
+    pub plain_text_separator: Option<String>,
+    pub plugins: Vec<PluginConfig>,
+    pub builtin: Builtin,
+    pub listen: Option<PathBuf>,
     // colors
     pub background: Color,
     pub color: Color,
@@ -26,8 +36,21 @@ pub struct Config {
     pub tag_urgent_bg: Color,
     pub tag_inactive_fg: Color,
     pub tag_inactive_bg: Color,
+    pub tag_hover_fg: Option<Color>,
+    pub tag_hover_bg: Option<Color>,
+    pub block_hover_bg: Option<Color>,
+    pub group_bg: Option<Color>,
+    pub progress_bg: Color,
+    pub error_fg: Color,
+    pub error_bg: Color,
+    pub theme: Theme,
     // font and size
     pub font: Font,
+    pub tag_focused_font: Option<Font>,
+    pub tags_font: Option<Font>,
+    pub layout_font: Option<Font>,
+    pub mode_font: Option<Font>,
+    pub blocks_font: Option<Font>,
     pub height: u32,
     pub margin_top: i32,
     pub margin_bottom: i32,
@@ -36,28 +59,101 @@ pub struct Config {
     pub separator_width: f64,
     pub tags_r: f64,
     pub tags_padding: f64,
+    pub tag_focused_padding: Option<f64>,
+    pub tag_min_width: Option<f64>,
     pub tags_margin: f64,
     pub blocks_r: f64,
     pub blocks_overlap: f64,
+    pub blocks_margin: f64,
+    pub group_padding: f64,
     // misc
     pub position: Position,
+    pub tags_position: TagsPosition,
+    pub blocks_overflow: BlocksOverflow,
     pub layer: Layer,
+    pub mode: BarMode,
     pub hide_inactive_tags: bool,
-    pub invert_touchpad_scrolling: bool,
+    pub hide_when_empty: bool,
+    pub hidden_keeps_exclusive_zone: bool,
+    #[serde(deserialize_with = "deserialize_nonzero_opt")]
+    pub auto_hide: Option<Timeout>,
+    pub idle: IdleConfig,
+    pub invert_touchpad_scrolling: ScrollInversion,
+    pub invert_touchpad_scrolling_tags: Option<ScrollInversion>,
+    pub invert_touchpad_scrolling_blocks: Option<ScrollInversion>,
+    pub scroll_threshold: ScrollThreshold,
+    pub swipe: Swipe,
+    pub tags_scroll_wrap: bool,
+    pub tags_scroll_occupied_only: bool,
     pub show_tags: bool,
+    pub show_blocks: bool,
+    pub tags_all_outputs: bool,
+    pub show_window_count: bool,
     pub show_layout_name: bool,
+    pub layout_format: String,
     pub blend: bool,
+    pub auto_contrast: bool,
+    pub high_contrast: bool,
+    pub urgent_marker: Option<UrgentMarker>,
+    pub animations: bool,
+    pub animation_duration: Timeout,
+    pub animation_easing: Easing,
+    pub blur: bool,
     pub show_mode: bool,
+    pub show_output_name: bool,
+    pub divider: Divider,
+    pub persist_blocks: bool,
+    pub multiline_blocks: bool,
+    pub line_spacing: f64,
+    #[serde(deserialize_with = "deserialize_nonzero_opt")]
+    pub stale_timeout: Option<Timeout>,
+    pub copy_on_middle_click: bool,
+    pub click_events_file: Option<PathBuf>,
+    pub accept_drops: bool,
+    pub drop_command: Option<String>,
+    pub show_tooltips: bool,
+    pub tooltip_delay: Timeout,
+    pub hover_events: bool,
+    pub keyboard_navigation: bool,
+    pub tray: bool,
+    pub tray_padding: f64,
+    pub tray_icon_theme: Option<String>,
+    pub seats: Vec<String>,
+    pub ignore_seats: Vec<String>,
+    // signals
+    pub toggle_signal: Signal,
+    pub peek_signal: Option<Signal>,
+    pub peek_duration: Timeout,
+    pub forward_signals: Vec<Signal>,
+    pub pause_when_hidden: bool,
+    pub pause_when_off: bool,
+    pub watch_config: bool,
+    // scripts
+    pub on_click: EventScripts,
+    pub on_scroll: EventScripts,
+    pub click: ClickCommands,
     // wm-specific
     pub wm: WmConfig,
+    // layout names
+    pub layout_names: HashMap<String, String>,
     // overrides
     pub output: HashMap<String, OutputOverrides>,
+    /// Named sets of overrides, selected with `--config-profile`. Checked once selected.
+    profile: HashMap<String, toml::Table>,
+    // set by the swaybar compatibility mode
+    #[serde(skip)]
+    pub only_outputs: Option<Vec<String>>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             command: None,
+            protocol: StatusProtocol::Auto,
+            plain_text_separator: None,
+            plugins: Vec::new(),
+            builtin: Builtin::default(),
+            listen: None,
 
             // A kind of gruvbox theme
             background: Color::from_rgba_hex(0x282828ff),
@@ -71,8 +167,21 @@ impl Default for Config {
             tag_urgent_bg: Color::from_rgba_hex(0xcc241dff),
             tag_inactive_fg: Color::from_rgba_hex(0xd79921ff),
             tag_inactive_bg: Color::from_rgba_hex(0x282828ff),
+            tag_hover_fg: None,
+            tag_hover_bg: None,
+            block_hover_bg: None,
+            group_bg: None,
+            progress_bg: Color::from_rgba_hex(0x458588ff),
+            error_fg: Color::from_rgba_hex(0x282828ff),
+            error_bg: Color::from_rgba_hex(0xcc241dff),
+            theme: Theme::default(),
 
             font: Font::new("monospace 10"),
+            tag_focused_font: None,
+            tags_font: None,
+            layout_font: None,
+            mode_font: None,
+            blocks_font: None,
             height: 24,
             margin_top: 0,
             margin_bottom: 0,
@@ -81,30 +190,91 @@ impl Default for Config {
             separator_width: 2.0,
             tags_r: 0.0,
             tags_padding: 25.0,
+            tag_focused_padding: None,
+            tag_min_width: None,
             tags_margin: 0.0,
             blocks_r: 0.0,
             blocks_overlap: 0.0,
+            blocks_margin: 0.0,
+            group_padding: 0.0,
 
             position: Position::Top,
+            tags_position: TagsPosition::Left,
+            blocks_overflow: BlocksOverflow::Shorten,
             layer: Layer::Top,
+            mode: BarMode::Dock,
             hide_inactive_tags: true,
-            invert_touchpad_scrolling: true,
+            hide_when_empty: false,
+            hidden_keeps_exclusive_zone: false,
+            auto_hide: None,
+            idle: IdleConfig::default(),
+            invert_touchpad_scrolling: ScrollInversion::Always,
+            invert_touchpad_scrolling_tags: None,
+            invert_touchpad_scrolling_blocks: None,
+            scroll_threshold: ScrollThreshold::default(),
+            swipe: Swipe::default(),
+            tags_scroll_wrap: false,
+            tags_scroll_occupied_only: false,
             show_tags: true,
+            show_blocks: true,
+            tags_all_outputs: false,
+            show_window_count: false,
             show_layout_name: true,
+            layout_format: "{}".into(),
             blend: true,
+            auto_contrast: false,
+            high_contrast: false,
+            urgent_marker: None,
+            animations: false,
+            animation_duration: Timeout(Duration::from_millis(150)),
+            animation_easing: Easing::EaseOut,
+            blur: false,
             show_mode: true,
+            show_output_name: false,
+            divider: Divider::default(),
+            persist_blocks: false,
+            multiline_blocks: false,
+            line_spacing: 0.0,
+            stale_timeout: None,
+            copy_on_middle_click: false,
+            click_events_file: None,
+            accept_drops: false,
+            drop_command: None,
+            show_tooltips: true,
+            tooltip_delay: Timeout(Duration::from_millis(500)),
+            hover_events: false,
+            keyboard_navigation: false,
+            tray: false,
+            tray_padding: 2.0,
+            tray_icon_theme: None,
+            seats: Vec::new(),
+            ignore_seats: Vec::new(),
 
-            wm: WmConfig {
-                river: RiverConfig { max_tag: 9 },
-            },
+            toggle_signal: Signal(libc::SIGUSR1),
+            peek_signal: None,
+            peek_duration: Timeout(Duration::from_secs(2)),
+            forward_signals: Vec::new(),
+            pause_when_hidden: false,
+            pause_when_off: false,
+            watch_config: false,
 
+            on_click: EventScripts::default(),
+            on_scroll: EventScripts::default(),
+            click: ClickCommands::default(),
+
+            wm: WmConfig::default(),
+
+            layout_names: HashMap::new(),
             output: HashMap::new(),
+            profile: HashMap::new(),
+            only_outputs: None,
         }
     }
 }
 
 impl Config {
-    pub fn new(path: Option<&Path>) -> Result<Self> {
+    /// Load the config at `path` (or the default path), with the overrides of `profile`.
+    pub fn new(path: Option<&Path>, profile: Option<&str>) -> Result<Self> {
         let buf;
 
         let path = match path {
@@ -115,25 +285,344 @@ impl Config {
             }
         };
 
-        Ok(match path {
+        let mut config: Self = match path {
             Some(config_path) => {
+                info!("Using configuration from {}", config_path.display());
                 let config = read_to_string(config_path).context("Failed to read configuration")?;
                 toml::from_str(&config).context("Failed to deserialize configuration")?
             }
             None => {
-                eprintln!("Could not find the configuration path");
-                eprintln!("Using default configuration");
+                info!("Could not find the configuration path, using default configuration");
                 Self::default()
             }
-        })
+        };
+
+        if let Some(name) = profile {
+            info!("Using the configuration profile {name}");
+            let overrides = config
+                .profile
+                .remove(name)
+                .with_context(|| format!("There is no profile '{name}' in the configuration"))?;
+            // The base config has been checked, so reading it again cannot fail
+            let mut table: toml::Table = match path {
+                Some(path) => read_to_string(path)?.parse()?,
+                None => toml::Table::new(),
+            };
+            table.remove("profile");
+            merge_tables(&mut table, overrides);
+            config = table
+                .try_into()
+                .with_context(|| format!("Failed to deserialize the profile '{name}'"))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Take the options which are only read at startup from `old`, so that a reloaded config
+    /// describes the running bar.
+    pub fn keep_startup_options(&mut self, old: &mut Config) {
+        use std::mem::swap;
+
+        // The status command and the other sources of blocks
+        swap(&mut self.command, &mut old.command);
+        swap(&mut self.protocol, &mut old.protocol);
+        swap(
+            &mut self.plain_text_separator,
+            &mut old.plain_text_separator,
+        );
+        swap(&mut self.plugins, &mut old.plugins);
+        swap(&mut self.builtin, &mut old.builtin);
+        swap(&mut self.listen, &mut old.listen);
+        swap(&mut self.persist_blocks, &mut old.persist_blocks);
+        // The signals and the timers
+        swap(&mut self.toggle_signal, &mut old.toggle_signal);
+        swap(&mut self.peek_signal, &mut old.peek_signal);
+        swap(&mut self.forward_signals, &mut old.forward_signals);
+        swap(&mut self.watch_config, &mut old.watch_config);
+        swap(&mut self.stale_timeout, &mut old.stale_timeout);
+        swap(&mut self.auto_hide, &mut old.auto_hide);
+        swap(&mut self.theme.schedule, &mut old.theme.schedule);
+        // The protocols bound and the helpers set up by `State::new`
+        swap(
+            &mut self.copy_on_middle_click,
+            &mut old.copy_on_middle_click,
+        );
+        swap(&mut self.show_tooltips, &mut old.show_tooltips);
+        swap(&mut self.tooltip_delay, &mut old.tooltip_delay);
+        swap(&mut self.tray_icon_theme, &mut old.tray_icon_theme);
+        swap(&mut self.blur, &mut old.blur);
+        swap(&mut self.accept_drops, &mut old.accept_drops);
+        swap(&mut self.idle.timeout, &mut old.idle.timeout);
+        swap(&mut self.swipe.enable, &mut old.swipe.enable);
+        swap(&mut self.swipe.fingers, &mut old.swipe.fingers);
+        swap(&mut self.on_click, &mut old.on_click);
+        swap(&mut self.on_scroll, &mut old.on_scroll);
+        swap(&mut self.wm, &mut old.wm);
+        // Which outputs have a bar
+        swap(&mut self.only_outputs, &mut old.only_outputs);
+        for (name, overrides) in &mut self.output {
+            overrides.enable = old.output.get(name).and_then(|o| o.enable);
+        }
+        for (name, overrides) in &old.output {
+            if overrides.enable.is_some() {
+                self.output.entry(name.clone()).or_default().enable = overrides.enable;
+            }
+        }
+    }
+
+    pub fn seat_enabled(&self, seat: &str) -> bool {
+        (self.seats.is_empty() || self.seats.iter().any(|s| s == seat))
+            && !self.ignore_seats.iter().any(|s| s == seat)
     }
 
     pub fn output_enabled(&self, output: &str) -> bool {
+        if let Some(only_outputs) = &self.only_outputs {
+            if !only_outputs.iter().any(|o| o == output) {
+                return false;
+            }
+        }
         self.output
             .get(output)
             .and_then(|o| o.enable)
             .unwrap_or(true)
     }
+
+    /// Override whether `output` has a bar, also if `only_outputs` leaves it out.
+    pub fn set_output_enabled(&mut self, output: &str, enabled: bool) {
+        self.output.entry(output.to_owned()).or_default().enable = Some(enabled);
+        if let Some(only_outputs) = self.only_outputs.as_mut().filter(|_| enabled) {
+            if !only_outputs.iter().any(|o| o == output) {
+                only_outputs.push(output.to_owned());
+            }
+        }
+    }
+
+    /// The output whose bar the bar on `output` duplicates.
+    pub fn output_mirror_of<'a>(&'a self, output: &str) -> Option<&'a str> {
+        self.output
+            .get(output)
+            .and_then(|o| o.mirror_of.as_deref())
+            .filter(|&mirrored| mirrored != output)
+    }
+
+    /// The overrides which decide what the bar on `output` shows: those of the mirrored output if
+    /// it mirrors one.
+    fn content_overrides(&self, output: &str) -> Option<&OutputOverrides> {
+        let output = self.output_mirror_of(output).unwrap_or(output);
+        self.output.get(output)
+    }
+
+    /// What the bar on `output` shows.
+    pub fn output_content(&self, output: &str) -> BarContent {
+        let overrides = self.content_overrides(output);
+        if let Some(content) = overrides.and_then(|o| o.content) {
+            return content;
+        }
+        if overrides
+            .and_then(|o| o.show_blocks)
+            .unwrap_or(self.show_blocks)
+        {
+            BarContent::Full
+        } else {
+            BarContent::TagsOnly
+        }
+    }
+
+    /// `fg`, or black or white if it is not readable on `bg` and `high_contrast` is set.
+    pub fn readable(&self, fg: Color, bg: Color) -> Color {
+        if self.high_contrast {
+            fg.readable_on(bg)
+        } else {
+            fg
+        }
+    }
+
+    pub fn tags_font(&self) -> &Font {
+        self.tags_font.as_ref().unwrap_or(&self.font)
+    }
+
+    pub fn layout_font(&self) -> &Font {
+        self.layout_font.as_ref().unwrap_or(&self.font)
+    }
+
+    pub fn mode_font(&self) -> &Font {
+        self.mode_font.as_ref().unwrap_or(&self.font)
+    }
+
+    pub fn blocks_font(&self) -> &Font {
+        self.blocks_font.as_ref().unwrap_or(&self.font)
+    }
+
+    /// How the blocks with several lines are laid out, `None` to join the lines.
+    pub fn multiline(&self) -> Option<Multiline> {
+        self.multiline_blocks.then(|| Multiline {
+            max_height: self.height as f64,
+            line_spacing: self.line_spacing,
+        })
+    }
+
+    /// The number of river tags shown on `output`.
+    /// Switch to the `[theme.night]` or the `[theme.day]` colors.
+    pub fn apply_theme(&mut self, night: bool) {
+        let base = self.theme.base.take().unwrap_or_else(|| Palette::of(self));
+        let palette = if night {
+            &self.theme.night
+        } else {
+            &self.theme.day
+        };
+        palette.clone().or(&base).apply(self);
+        self.theme.base = Some(base);
+    }
+
+    /// What `show_output_name` shows for `output`.
+    pub fn output_label<'a>(&'a self, output: &'a str) -> &'a str {
+        self.output
+            .get(output)
+            .and_then(|o| o.label.as_deref())
+            .unwrap_or(output)
+    }
+
+    pub fn river_max_tag(&self, output: &str) -> u8 {
+        self.output
+            .get(output)
+            .and_then(|o| o.wm.river.max_tag)
+            .unwrap_or(self.wm.river.max_tag)
+    }
+
+    /// The names of the river tags on `output`, starting with tag 1.
+    pub fn river_tag_names(&self, output: &str) -> &[String] {
+        self.output
+            .get(output)
+            .and_then(|o| o.wm.river.tag_names.as_deref())
+            .unwrap_or(&self.wm.river.tag_names)
+    }
+
+    /// Whether any bar may show the blocks. If not, the status command is not started.
+    pub fn blocks_shown(&self) -> bool {
+        self.show_blocks
+            || self.output.values().any(|o| {
+                o.show_blocks == Some(true)
+                    || matches!(o.content, Some(BarContent::Full | BarContent::BlocksOnly))
+            })
+    }
+
+    /// The height of a row of the bar on `output`.
+    pub fn output_height(&self, output: &str) -> u32 {
+        self.output
+            .get(output)
+            .and_then(|o| o.height)
+            .unwrap_or(self.height)
+    }
+
+    pub fn output_position(&self, output: &str) -> Position {
+        self.output
+            .get(output)
+            .and_then(|o| o.position)
+            .unwrap_or(self.position)
+    }
+
+    /// The top, right, bottom and left margins of the bar on `output`.
+    pub fn output_margins(&self, output: &str) -> [i32; 4] {
+        let o = self.output.get(output);
+        [
+            o.and_then(|o| o.margin_top).unwrap_or(self.margin_top),
+            o.and_then(|o| o.margin_right).unwrap_or(self.margin_right),
+            o.and_then(|o| o.margin_bottom)
+                .unwrap_or(self.margin_bottom),
+            o.and_then(|o| o.margin_left).unwrap_or(self.margin_left),
+        ]
+    }
+
+    pub fn output_show_tags(&self, output: &str) -> bool {
+        self.output
+            .get(output)
+            .and_then(|o| o.show_tags)
+            .unwrap_or(self.show_tags)
+    }
+
+    /// How much the fonts are scaled on `output`.
+    pub fn output_font_scale(&self, output: &str) -> f64 {
+        self.output
+            .get(output)
+            .and_then(|o| o.font_scale)
+            .unwrap_or(1.0)
+    }
+
+    /// Every font scale other than 1 used by an output, without duplicates.
+    pub fn font_scales(&self) -> Vec<f64> {
+        let mut scales: Vec<f64> = Vec::new();
+        for scale in self.output.values().filter_map(|o| o.font_scale) {
+            if scale != 1.0 && !scales.contains(&scale) {
+                scales.push(scale);
+            }
+        }
+        scales
+    }
+
+    /// The colors of the bar on `output`, with its `palette` applied over the current colors.
+    pub fn output_colors(&self, output: &str) -> Colors {
+        match self.output.get(output) {
+            Some(overrides) => overrides.palette.resolve(self),
+            None => Palette::default().resolve(self),
+        }
+    }
+
+    /// Whether the bar on `output` shows the tray.
+    pub fn output_tray(&self, output: &str) -> bool {
+        self.content_overrides(output)
+            .and_then(|o| o.tray)
+            .unwrap_or(self.tray)
+    }
+
+    /// Whether any bar may show the tray. If not, the bar does not connect to D-Bus.
+    pub fn tray_shown(&self) -> bool {
+        self.tray || self.output.values().any(|o| o.tray == Some(true))
+    }
+
+    /// The text shown for the layout `name`, `None` if it is mapped to an empty string.
+    pub fn format_layout_name(&self, name: &str) -> Option<String> {
+        let name = self.layout_names.get(name).map_or(name, String::as_str);
+        if name.is_empty() {
+            return None;
+        }
+        Some(self.layout_format.replace("{}", name))
+    }
+
+    /// Whether touchpad scrolling over the tags (or the blocks) is inverted.
+    pub fn touchpad_scroll_inversion(&self, over_tags: bool) -> ScrollInversion {
+        let area = if over_tags {
+            self.invert_touchpad_scrolling_tags
+        } else {
+            self.invert_touchpad_scrolling_blocks
+        };
+        area.unwrap_or(self.invert_touchpad_scrolling)
+    }
+
+    pub fn tags_style(&self) -> TagsStyle {
+        TagsStyle {
+            tags_r: self.tags_r,
+            tags_margin: self.tags_margin,
+            urgent_marker: self.urgent_marker,
+        }
+    }
+
+    pub fn blocks_style(&self, colors: &Colors) -> BlocksStyle {
+        BlocksStyle {
+            color: colors.color,
+            separator: colors.separator,
+            separator_width: self.separator_width,
+            blocks_r: self.blocks_r,
+            blocks_overlap: self.blocks_overlap,
+            blocks_margin: self.blocks_margin,
+            group_bg: colors.group_bg,
+            group_padding: self.group_padding,
+            auto_contrast: self.auto_contrast,
+            high_contrast: self.high_contrast,
+            background: colors.background,
+            progress_bg: colors.progress_bg,
+            urgent_marker: self.urgent_marker,
+        }
+    }
 }
 
 fn config_dir() -> Option<PathBuf> {
@@ -142,13 +631,23 @@ fn config_dir() -> Option<PathBuf> {
         .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))
 }
 
-fn config_path() -> Option<PathBuf> {
+/// The default config, if it exists.
+pub fn config_path() -> Option<PathBuf> {
     let mut path = config_dir()?;
     path.push("i3bar-river");
     path.push("config.toml");
     path.exists().then_some(path)
 }
 
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusProtocol {
+    /// Either i3bar protocol or plain text, detected automatically.
+    Auto,
+    /// lemonbar's input format.
+    Lemonbar,
+}
+
 #[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Position {
@@ -165,6 +664,49 @@ impl From<Position> for zwlr_layer_surface_v1::Anchor {
     }
 }
 
+/// Where the tags, the layout name and the mode are shown. The blocks take the remaining space.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagsPosition {
+    /// Blocks are on the right.
+    Left,
+    /// Blocks are on the left.
+    Center,
+    /// Blocks are on the left.
+    Right,
+}
+
+/// What to do when the blocks do not fit.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlocksOverflow {
+    /// Switch the blocks to their short text.
+    Shorten,
+    /// Double the height of the bar and move the remaining blocks to the second row.
+    Grow,
+}
+
+/// How the transitions of `animations` progress over time.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// The progress of a transition at the time `t`, both from 0 to 1.
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Self::Linear => t,
+            Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Self::EaseInOut if t < 0.5 => 4.0 * t.powi(3),
+            Self::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(3) * 0.5,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Layer {
@@ -174,6 +716,31 @@ pub enum Layer {
     Overlay,
 }
 
+/// When the bar is shown, like swaybar's `mode`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BarMode {
+    /// Shown, unless hidden with `toggle_signal`.
+    Dock,
+    /// Hidden, unless revealed with the `reveal` IPC command, `toggle_signal` or a peek.
+    Hide,
+    /// Never shown. The status command keeps running.
+    Invisible,
+}
+
+impl FromStr for BarMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dock" => Ok(Self::Dock),
+            "hide" => Ok(Self::Hide),
+            "invisible" => Ok(Self::Invisible),
+            _ => Err(()),
+        }
+    }
+}
+
 impl From<Layer> for zwlr_layer_shell_v1::Layer {
     fn from(layer: Layer) -> Self {
         match layer {
@@ -185,20 +752,478 @@ impl From<Layer> for zwlr_layer_shell_v1::Layer {
     }
 }
 
+/// How far to scroll to switch one tag or send one scroll event to a block, per source.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ScrollThreshold {
+    pub wheel: f64,
+    pub finger: f64,
+    pub continuous: f64,
+}
+
+impl Default for ScrollThreshold {
+    fn default() -> Self {
+        Self {
+            wheel: 15.0,
+            finger: 15.0,
+            continuous: 15.0,
+        }
+    }
+}
+
+/// Hiding the bar while the seats are idle.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct IdleConfig {
+    pub timeout: Option<Timeout>,
+    /// Draw the bar with `opacity` instead of hiding it.
+    pub fade: bool,
+    pub opacity: f64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            fade: false,
+            opacity: 0.3,
+        }
+    }
+}
+
+/// Switching tags by swiping horizontally over the bar.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Swipe {
+    pub enable: bool,
+    /// `2` for touchpad scrolling, more for a swipe gesture.
+    pub fingers: u32,
+    /// How far to swipe to switch one tag.
+    pub threshold: f64,
+}
+
+impl Default for Swipe {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            fingers: 3,
+            threshold: 50.0,
+        }
+    }
+}
+
+/// Merge `overrides` into `table`, recursively for the tables present in both.
+fn merge_tables(table: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(overrides)) => {
+                merge_tables(table, overrides);
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Blocks computed by the bar itself, shown after the blocks of the plugins.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Builtin {
+    pub sysinfo: SysInfoConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SysInfoConfig {
+    pub enable: bool,
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub interval: Timeout,
+    pub temperature: bool,
+    /// The name of the hwmon device to read the temperature from.
+    pub sensor: Option<String>,
+}
+
+impl Default for SysInfoConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interval: Timeout(Duration::from_secs(5)),
+            temperature: false,
+            sensor: None,
+        }
+    }
+}
+
+/// Colors which depend on the time of day.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Theme {
+    pub schedule: Option<ThemeSchedule>,
+    pub day: Palette,
+    pub night: Palette,
+    /// The colors from the top level of the config, which the palettes override.
+    #[serde(skip)]
+    base: Option<Palette>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeSchedule {
+    pub day: TimeOfDay,
+    pub night: TimeOfDay,
+}
+
+impl ThemeSchedule {
+    pub fn is_night(self, now: TimeOfDay) -> bool {
+        if self.day <= self.night {
+            now < self.day || now >= self.night
+        } else {
+            now >= self.night && now < self.day
+        }
+    }
+}
+
+macro_rules! palette {
+    (colors: [$($color:ident),*], optional: [$($optional:ident),*]) => {
+        /// Overrides of the colors. Unset colors are taken from the top level of the config.
+        #[derive(Debug, Default, Clone, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        pub struct Palette {
+            $(pub $color: Option<Color>,)*
+            $(pub $optional: Option<Color>,)*
+        }
+
+        /// The colors a bar is drawn with, see [`Config::output_colors`].
+        #[derive(Debug, Clone, Copy)]
+        pub struct Colors {
+            $(pub $color: Color,)*
+            $(pub $optional: Option<Color>,)*
+        }
+
+        impl Palette {
+            fn of(config: &Config) -> Self {
+                Self {
+                    $($color: Some(config.$color),)*
+                    $($optional: config.$optional,)*
+                }
+            }
+
+            fn or(self, other: &Self) -> Self {
+                Self {
+                    $($color: self.$color.or(other.$color),)*
+                    $($optional: self.$optional.or(other.$optional),)*
+                }
+            }
+
+            /// The colors of `config`, with the ones set in this palette overridden.
+            fn resolve(&self, config: &Config) -> Colors {
+                Colors {
+                    $($color: self.$color.unwrap_or(config.$color),)*
+                    $($optional: self.$optional.or(config.$optional),)*
+                }
+            }
+
+            fn apply(&self, config: &mut Config) {
+                $(
+                    if let Some(color) = self.$color {
+                        config.$color = color;
+                    }
+                )*
+                $(config.$optional = self.$optional;)*
+            }
+        }
+    };
+}
+
+palette! {
+    colors: [
+        background, color, separator, tag_fg, tag_bg, tag_focused_fg, tag_focused_bg,
+        tag_urgent_fg, tag_urgent_bg, tag_inactive_fg, tag_inactive_bg, progress_bg, error_fg, error_bg
+    ],
+    optional: [tag_hover_fg, tag_hover_bg, block_hover_bg, group_bg]
+}
+
+/// The divider between the tags and the blocks.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Divider {
+    pub enable: bool,
+    /// Drawn instead of the line if set.
+    pub text: Option<String>,
+    /// The width of the line, `0` for just a gap.
+    pub width: f64,
+    /// The space on both sides of the line or the text.
+    pub gap: f64,
+    /// Defaults to `separator`.
+    pub color: Option<Color>,
+}
+
+impl Default for Divider {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            text: None,
+            width: 2.0,
+            gap: 8.0,
+            color: None,
+        }
+    }
+}
+
+/// Deserialized from a boolean or `"auto"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollInversion {
+    Never,
+    Always,
+    /// Invert unless the compositor already does, i.e. natural scrolling is enabled.
+    Auto,
+}
+
+impl<'de> de::Deserialize<'de> for ScrollInversion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct ScrollInversionVisitor;
+
+        impl de::Visitor<'_> for ScrollInversionVisitor {
+            type Value = ScrollInversion;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("true, false or \"auto\"")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(if v {
+                    ScrollInversion::Always
+                } else {
+                    ScrollInversion::Never
+                })
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                match s {
+                    "auto" => Ok(ScrollInversion::Auto),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(ScrollInversionVisitor)
+    }
+}
+
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    pub path: PathBuf,
+    /// Defaults to the file name without the extension.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// How often the plugin is updated.
+    #[serde(
+        default = "default_plugin_interval",
+        deserialize_with = "deserialize_nonzero"
+    )]
+    pub interval: Timeout,
+}
+
+fn default_plugin_interval() -> Timeout {
+    Timeout(Duration::from_secs(1))
+}
+
+/// For the durations of timers, which would fire continuously if they were zero.
+fn deserialize_nonzero<'de, D>(deserializer: D) -> Result<Timeout, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let timeout = Timeout::deserialize(deserializer)?;
+    if timeout.0.is_zero() {
+        return Err(de::Error::custom("the duration must not be zero"));
+    }
+    Ok(timeout)
+}
+
+/// Like [`deserialize_nonzero`], for the optional durations.
+fn deserialize_nonzero_opt<'de, D>(deserializer: D) -> Result<Option<Timeout>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    deserialize_nonzero(deserializer).map(Some)
+}
+
+/// Rhai scripts, see src/scripting.rs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct EventScripts {
+    pub tag: Option<String>,
+    pub blocks: Option<String>,
+}
+
+/// Shell commands run on clicks instead of the default actions, see `[click]` in README.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ClickCommands {
+    pub tags: ButtonCommands,
+    pub layout: ButtonCommands,
+    /// By block name.
+    pub blocks: HashMap<String, ButtonCommands>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ButtonCommands {
+    pub left: Option<String>,
+    pub middle: Option<String>,
+    pub right: Option<String>,
+    pub up: Option<String>,
+    pub down: Option<String>,
+    pub forward: Option<String>,
+    pub back: Option<String>,
+}
+
+impl ButtonCommands {
+    pub fn get(&self, btn: PointerBtn) -> Option<&str> {
+        match btn {
+            PointerBtn::Left => self.left.as_deref(),
+            PointerBtn::Middle => self.middle.as_deref(),
+            PointerBtn::Right => self.right.as_deref(),
+            PointerBtn::WheelUp => self.up.as_deref(),
+            PointerBtn::WheelDown => self.down.as_deref(),
+            PointerBtn::Forward => self.forward.as_deref(),
+            PointerBtn::Back => self.back.as_deref(),
+            PointerBtn::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct WmConfig {
     pub river: RiverConfig,
+    pub hyprland: HyprlandConfig,
+    pub niri: NiriConfig,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(default)]
 pub struct RiverConfig {
     pub max_tag: u8,
+    pub always_show_tags: u8,
+    pub show_focused_combination: bool,
+    pub back_and_forth: bool,
+    pub scroll_occupied_only: bool,
+    pub tag_names: Vec<String>,
+    pub on_left_click: Option<String>,
+    pub on_middle_click: Option<String>,
+    pub on_right_click: Option<String>,
+}
+
+impl Default for RiverConfig {
+    fn default() -> Self {
+        Self {
+            max_tag: 9,
+            always_show_tags: 0,
+            show_focused_combination: false,
+            back_and_forth: false,
+            scroll_occupied_only: false,
+            tag_names: Vec::new(),
+            on_left_click: None,
+            on_middle_click: None,
+            on_right_click: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HyprlandConfig {
+    pub always_show_workspaces: u32,
+    pub scroll_skip_special: bool,
+    pub scroll_skip_empty: bool,
+}
+
+impl Default for HyprlandConfig {
+    fn default() -> Self {
+        Self {
+            always_show_workspaces: 0,
+            scroll_skip_special: true,
+            scroll_skip_empty: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NiriConfig {
+    pub always_show_workspaces: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct OutputOverrides {
     #[serde(default)]
     enable: Option<bool>,
+    #[serde(default)]
+    content: Option<BarContent>,
+    #[serde(default)]
+    show_blocks: Option<bool>,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    tray: Option<bool>,
+    #[serde(default)]
+    wm: OutputWmOverrides,
+    #[serde(default)]
+    mirror_of: Option<String>,
+    #[serde(default)]
+    height: Option<u32>,
+    #[serde(default)]
+    position: Option<Position>,
+    #[serde(default)]
+    margin_top: Option<i32>,
+    #[serde(default)]
+    margin_right: Option<i32>,
+    #[serde(default)]
+    margin_bottom: Option<i32>,
+    #[serde(default)]
+    margin_left: Option<i32>,
+    #[serde(default)]
+    font_scale: Option<f64>,
+    #[serde(default)]
+    show_tags: Option<bool>,
+    #[serde(default)]
+    palette: Palette,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct OutputWmOverrides {
+    pub river: OutputRiverOverrides,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct OutputRiverOverrides {
+    pub max_tag: Option<u8>,
+    pub tag_names: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BarContent {
+    /// Tags, layout name, mode and blocks.
+    Full,
+    /// Tags, layout name and mode.
+    TagsOnly,
+    /// Just the blocks.
+    BlocksOnly,
 }
 
 #[derive(Debug)]
@@ -208,6 +1233,17 @@ impl Font {
     pub fn new(desc: &str) -> Self {
         Self(FontDescription::from_string(desc))
     }
+
+    /// The same font, `scale` times as large.
+    pub fn scaled(&self, scale: f64) -> Self {
+        let mut desc = self.0.clone();
+        if desc.is_size_absolute() {
+            desc.set_absolute_size(desc.size() as f64 * scale);
+        } else {
+            desc.set_size((desc.size() as f64 * scale).round() as i32);
+        }
+        Self(desc)
+    }
 }
 
 impl Deref for Font {
@@ -243,3 +1279,206 @@ impl<'de> de::Deserialize<'de> for Font {
         deserializer.deserialize_str(FontVisitor)
     }
 }
+
+/// A signal number, deserialized from a name like `"SIGUSR1"`, `"SIGRTMIN+4"` or a raw number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal(pub i32);
+
+impl FromStr for Signal {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_prefix("SIG").unwrap_or(s);
+
+        let rt_offset = |offset: &str, sign: char| -> Result<i32, ()> {
+            match offset {
+                "" => Ok(0),
+                _ => offset.strip_prefix(sign).ok_or(())?.parse().map_err(|_| ()),
+            }
+        };
+
+        let signal = if let Some(offset) = name.strip_prefix("RTMIN") {
+            libc::SIGRTMIN() + rt_offset(offset, '+')?
+        } else if let Some(offset) = name.strip_prefix("RTMAX") {
+            libc::SIGRTMAX() - rt_offset(offset, '-')?
+        } else {
+            match name {
+                "HUP" => libc::SIGHUP,
+                "INT" => libc::SIGINT,
+                "QUIT" => libc::SIGQUIT,
+                "USR1" => libc::SIGUSR1,
+                "USR2" => libc::SIGUSR2,
+                "ALRM" => libc::SIGALRM,
+                "TERM" => libc::SIGTERM,
+                "CONT" => libc::SIGCONT,
+                "WINCH" => libc::SIGWINCH,
+                _ => name.parse().map_err(|_| ())?,
+            }
+        };
+
+        if signal <= 0 || signal > libc::SIGRTMAX() {
+            return Err(());
+        }
+
+        Ok(Self(signal))
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Signal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct SignalVisitor;
+
+        impl de::Visitor<'_> for SignalVisitor {
+            type Value = Signal;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("signal name or number")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                s.parse()
+                    .map_err(|_| E::custom(format!("'{s}' is not a valid signal")))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                self.visit_str(&v.to_string())
+            }
+        }
+
+        deserializer.deserialize_any(SignalVisitor)
+    }
+}
+
+/// Deserialized from a number of seconds or a string such as `"500ms"`, `"30s"`, `"5m"` or `"1h"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout(pub Duration);
+
+impl FromStr for Timeout {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or(())?;
+        let (value, unit) = s.split_at(split);
+        let value: f64 = value.parse().map_err(|_| ())?;
+        let secs = match unit.trim() {
+            "ms" => value / 1000.0,
+            "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 3600.0,
+            _ => return Err(()),
+        };
+        Duration::try_from_secs_f64(secs).map(Self).map_err(|_| ())
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Timeout {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct TimeoutVisitor;
+
+        impl de::Visitor<'_> for TimeoutVisitor {
+            type Value = Timeout;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("number of seconds or duration such as \"30s\"")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                s.parse()
+                    .map_err(|_| E::custom(format!("'{s}' is not a valid duration")))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                self.visit_f64(v as f64)
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Duration::try_from_secs_f64(v)
+                    .map(Timeout)
+                    .map_err(|_| E::custom(format!("{v} is not a valid number of seconds")))
+            }
+        }
+
+        deserializer.deserialize_any(TimeoutVisitor)
+    }
+}
+
+/// Minutes since midnight, deserialized from `"HH:MM"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(pub u32);
+
+impl TimeOfDay {
+    /// The current local time.
+    pub fn now() -> Self {
+        let tm = unsafe {
+            let mut tm: libc::tm = std::mem::zeroed();
+            let t = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&t, &mut tm);
+            tm
+        };
+        Self((tm.tm_hour * 60 + tm.tm_min) as u32)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hours, minutes) = s.split_once(':').ok_or(())?;
+        let hours: u32 = hours.parse().map_err(|_| ())?;
+        let minutes: u32 = minutes.parse().map_err(|_| ())?;
+        if hours >= 24 || minutes >= 60 {
+            return Err(());
+        }
+        Ok(Self(hours * 60 + minutes))
+    }
+}
+
+impl<'de> de::Deserialize<'de> for TimeOfDay {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct TimeOfDayVisitor;
+
+        impl de::Visitor<'_> for TimeOfDayVisitor {
+            type Value = TimeOfDay;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("time of day such as \"20:00\"")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                s.parse()
+                    .map_err(|_| E::custom(format!("'{s}' is not a valid time of day")))
+            }
+        }
+
+        deserializer.deserialize_str(TimeOfDayVisitor)
+    }
+}
@@ -0,0 +1,82 @@
+//! Watching the config file for changes, with inotify.
+//!
+//! The directory is watched rather than the file, because most editors save by writing a new file
+//! and renaming it over the old one, which would end a watch on the file itself.
+
+use std::ffi::{CString, OsStr, OsString};
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+pub struct ConfigWatcher {
+    fd: OwnedFd,
+    file_name: OsString,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::from(ErrorKind::InvalidInput))?
+            .to_owned();
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd, file_name })
+    }
+
+    /// Becomes readable when a file in the directory of the config is written.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Whether the config has been written since the last call.
+    pub fn read(&self) -> io::Result<bool> {
+        const HEADER: usize = mem::size_of::<libc::inotify_event>();
+
+        let mut changed = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            let ret = unsafe { libc::read(self.fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if ret == -1 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    ErrorKind::WouldBlock => return Ok(changed),
+                    ErrorKind::Interrupted => continue,
+                    _ => return Err(err),
+                }
+            }
+            let mut events = &buf[..ret as usize];
+            while events.len() >= HEADER {
+                let event: libc::inotify_event = unsafe {
+                    events
+                        .as_ptr()
+                        .cast::<libc::inotify_event>()
+                        .read_unaligned()
+                };
+                let end = HEADER + event.len as usize;
+                // The name is padded with nul bytes
+                let name = &events[HEADER..end];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                if OsStr::from_bytes(name) == self.file_name {
+                    changed = true;
+                }
+                events = &events[end..];
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Dropping text and files onto the blocks, using wl_data_device.
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use wayrs_client::global::{Globals, GlobalsExt};
+use wayrs_client::proxy::Proxy;
+use wayrs_client::{Connection, EventCtx, IoMode};
+
+use crate::bar::Bar;
+use crate::protocol::*;
+use crate::state::State;
+
+/// The accepted MIME types, most preferred first.
+const MIME_TYPES: &[&CStr] = &[c"text/uri-list", c"text/plain;charset=utf-8", c"text/plain"];
+
+/// How long to wait for the source to send the dropped data.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The dropped data is truncated to this many bytes.
+const MAX_SIZE: u64 = 1 << 20;
+
+pub struct DragAndDrop {
+    manager: WlDataDeviceManager,
+    devices: Vec<(WlSeat, WlDataDevice)>,
+    /// The live offers and their most preferred MIME type.
+    offers: Vec<(WlDataOffer, Option<&'static CStr>)>,
+    drag: Option<Drag>,
+}
+
+/// A drag over one of the bars.
+struct Drag {
+    device: WlDataDevice,
+    offer: Option<WlDataOffer>,
+    surface: WlSurface,
+    serial: u32,
+    x: f64,
+    y: f64,
+}
+
+impl DragAndDrop {
+    pub fn bind(conn: &mut Connection<State>, globals: &Globals) -> Option<Self> {
+        Some(Self {
+            manager: globals.bind(conn, 1..=3).ok()?,
+            devices: Vec::new(),
+            offers: Vec::new(),
+            drag: None,
+        })
+    }
+
+    pub fn seat_added(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        let device = self.manager.get_data_device_with_cb(conn, seat, device_cb);
+        self.devices.push((seat, device));
+    }
+
+    pub fn seat_removed(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        let Some(i) = self.devices.iter().position(|(s, _)| *s == seat) else {
+            return;
+        };
+        let (_, device) = self.devices.swap_remove(i);
+        if self.drag.as_ref().is_some_and(|drag| drag.device == device) {
+            if let Some(offer) = self.drag.take().unwrap().offer {
+                self.destroy_offer(conn, offer);
+            }
+        }
+        if device.version() >= 2 {
+            device.release(conn);
+        }
+    }
+
+    fn mime_type(&self, offer: WlDataOffer) -> Option<&'static CStr> {
+        self.offers.iter().find(|(o, _)| *o == offer)?.1
+    }
+
+    /// Accept the drag only while it is over a block and offers a supported MIME type.
+    fn accept(&self, conn: &mut Connection<State>, bars: &[Bar]) {
+        let Some(Drag {
+            offer: Some(offer),
+            surface,
+            serial,
+            x,
+            y,
+            ..
+        }) = self.drag
+        else {
+            return;
+        };
+        let over_block = bars
+            .iter()
+            .find(|b| b.surface == surface)
+            .is_some_and(|b| b.block_at(x, y).is_some());
+        let mime_type = self.mime_type(offer).filter(|_| over_block);
+        offer.accept(conn, serial, mime_type.map(CStr::to_owned));
+    }
+
+    fn destroy_offer(&mut self, conn: &mut Connection<State>, offer: WlDataOffer) {
+        self.offers.retain(|(o, _)| *o != offer);
+        offer.destroy(conn);
+    }
+}
+
+fn device_cb(ctx: EventCtx<State, WlDataDevice>) {
+    use wl_data_device::Event;
+
+    let Some(dnd) = &mut ctx.state.dnd else {
+        return;
+    };
+    match ctx.event {
+        Event::DataOffer(offer) => {
+            ctx.conn.set_callback_for(offer, offer_cb);
+            dnd.offers.push((offer, None));
+        }
+        Event::Enter(args) => {
+            let offer = args
+                .id
+                .and_then(|id| dnd.offers.iter().find(|(o, _)| o.id() == id))
+                .map(|(o, _)| *o);
+            let bar = ctx
+                .state
+                .bars
+                .iter()
+                .find(|b| b.surface.id() == args.surface);
+            let Some(bar) = bar else {
+                if let Some(offer) = offer {
+                    dnd.destroy_offer(ctx.conn, offer);
+                }
+                return;
+            };
+            if let Some(offer) = offer {
+                if offer.version() >= 3 {
+                    let copy = wl_data_device_manager::DndAction::Copy;
+                    offer.set_actions(ctx.conn, copy, copy);
+                }
+            }
+            dnd.drag = Some(Drag {
+                device: ctx.proxy,
+                offer,
+                surface: bar.surface,
+                serial: args.serial,
+                x: args.x.as_f64(),
+                y: args.y.as_f64(),
+            });
+            dnd.accept(ctx.conn, &ctx.state.bars);
+        }
+        Event::Motion(args) => {
+            let Some(drag) = &mut dnd.drag else { return };
+            drag.x = args.x.as_f64();
+            drag.y = args.y.as_f64();
+            dnd.accept(ctx.conn, &ctx.state.bars);
+        }
+        Event::Leave => {
+            if let Some(offer) = dnd.drag.take().and_then(|drag| drag.offer) {
+                dnd.destroy_offer(ctx.conn, offer);
+            }
+        }
+        Event::Drop => {
+            let Some(drag) = dnd.drag.take() else { return };
+            let Some(offer) = drag.offer else { return };
+            let received = match dnd.mime_type(offer) {
+                Some(mime_type) => receive(ctx.conn, offer, mime_type)
+                    .map(|data| (mime_type, data))
+                    .map_err(|e| warn!("failed to receive the dropped data: {e}"))
+                    .ok(),
+                None => None,
+            };
+            if offer.version() >= 3 {
+                offer.finish(ctx.conn);
+            }
+            dnd.destroy_offer(ctx.conn, offer);
+            let Some((mime_type, data)) = received else {
+                return;
+            };
+            let mime_type = mime_type.to_string_lossy();
+            if let Some(bar) = ctx.state.bars.iter().find(|b| b.surface == drag.surface) {
+                if let Err(e) = bar.drop_data(
+                    &mut ctx.state.shared_state,
+                    drag.x,
+                    drag.y,
+                    &mime_type,
+                    &data,
+                ) {
+                    ctx.state.set_error(ctx.conn, "drop", e);
+                }
+            }
+        }
+        Event::Selection(offer) => {
+            // The bar never reads the selection
+            let offer = offer
+                .and_then(|id| dnd.offers.iter().find(|(o, _)| o.id() == id))
+                .map(|(o, _)| *o);
+            if let Some(offer) = offer {
+                dnd.destroy_offer(ctx.conn, offer);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn offer_cb(ctx: EventCtx<State, WlDataOffer>) {
+    let wl_data_offer::Event::Offer(mime_type) = ctx.event else {
+        return;
+    };
+    let Some(dnd) = &mut ctx.state.dnd else {
+        return;
+    };
+    let Some(&mime_type) = MIME_TYPES.iter().find(|m| **m == mime_type.as_c_str()) else {
+        return;
+    };
+    let rank = |m: &CStr| MIME_TYPES.iter().position(|&p| p == m);
+    if let Some((_, best)) = dnd.offers.iter_mut().find(|(o, _)| *o == ctx.proxy) {
+        if best.is_none_or(|best| rank(best) > rank(mime_type)) {
+            *best = Some(mime_type);
+        }
+    }
+}
+
+/// Read the data of `offer`, waiting at most [`RECEIVE_TIMEOUT`] for it.
+fn receive(
+    conn: &mut Connection<State>,
+    offer: WlDataOffer,
+    mime_type: &CStr,
+) -> io::Result<String> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    offer.receive(conn, mime_type.to_owned(), write);
+    conn.flush(IoMode::Blocking)?;
+
+    let deadline = Instant::now() + RECEIVE_TIMEOUT;
+    let mut data = Vec::new();
+    let mut file = File::from(read).take(MAX_SIZE);
+    loop {
+        match file.read_to_end(&mut data) {
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout.is_zero() {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                let mut pollfd = libc::pollfd {
+                    fd: file.get_ref().as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) } == -1 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
@@ -1,23 +1,37 @@
-use std::collections::HashMap;
-use std::io;
+//! Callback-based event loop.
+//!
+//! By default the loop is implemented using `epoll`. With the `calloop` feature, calloop is used
+//! instead. Both backends have the same API.
+//!
+//! Every fd and timer is identified by a [`Token`], so a component can own several of them and
+//! remove them individually. The callbacks get a [`Handle`] to register new sources and remove
+//! existing ones, e.g. to re-register after reconnecting. These changes are applied once the
+//! callback returns.
+
 use std::os::fd::RawFd;
+use std::time::Duration;
 
 use anyhow::Result;
 use wayrs_client::Connection;
 
 use crate::state::State;
 
+#[cfg(not(feature = "calloop"))]
+mod epoll_backend;
+#[cfg(not(feature = "calloop"))]
+pub use epoll_backend::EventLoop;
+
+#[cfg(feature = "calloop")]
+mod calloop_backend;
+#[cfg(feature = "calloop")]
+pub use calloop_backend::EventLoop;
+
 type Callback = Box<dyn FnMut(EventLoopCtx) -> Result<Action>>;
 
 pub struct EventLoopCtx<'a> {
     pub conn: &'a mut Connection<State>,
     pub state: &'a mut State,
-}
-
-/// Simple callback-based event loop. Implemented using `poll`.
-pub struct EventLoop {
-    cbs: HashMap<RawFd, Callback>,
-    on_idle: Vec<Callback>,
+    pub handle: &'a mut Handle,
 }
 
 pub enum Action {
@@ -25,74 +39,53 @@ pub enum Action {
     Unregister,
 }
 
-impl EventLoop {
-    pub fn new() -> Self {
-        Self {
-            cbs: HashMap::new(),
-            on_idle: Vec::new(),
-        }
-    }
+/// Identifies a registered fd or timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token(u64);
+
+/// Registers and removes the sources of the event loop. The changes are applied by the loop before
+/// it waits for the next events.
+#[derive(Default)]
+pub struct Handle {
+    next_token: u64,
+    pending: Vec<Pending>,
+}
+
+enum Pending {
+    Fd(Token, RawFd, Callback),
+    Timer(Token, Duration, Callback),
+    Remove(Token),
+}
 
-    pub fn register_with_fd<F>(&mut self, fd: RawFd, cb: F)
+impl Handle {
+    /// Call `cb` whenever `fd` is readable, until it returns [`Action::Unregister`].
+    pub fn register_with_fd<F>(&mut self, fd: RawFd, cb: F) -> Token
     where
         F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
     {
-        self.cbs.insert(fd, Box::new(cb));
+        let token = self.next_token();
+        self.pending.push(Pending::Fd(token, fd, Box::new(cb)));
+        token
     }
 
-    pub fn add_on_idle<F>(&mut self, cb: F)
+    /// Call `cb` every `interval`, until it returns [`Action::Unregister`].
+    pub fn add_timer<F>(&mut self, interval: Duration, cb: F) -> Token
     where
         F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
     {
-        self.on_idle.push(Box::new(cb));
+        let token = self.next_token();
+        self.pending
+            .push(Pending::Timer(token, interval, Box::new(cb)));
+        token
     }
 
-    pub fn run(&mut self, conn: &mut Connection<State>, state: &mut State) -> Result<()> {
-        let mut pollfds = Vec::new();
-        let mut on_idle_scratch = Vec::new();
-
-        while !self.cbs.is_empty() {
-            pollfds.clear();
-            for &fd in self.cbs.keys() {
-                pollfds.push(libc::pollfd {
-                    fd,
-                    events: libc::POLLIN,
-                    revents: 0,
-                });
-            }
-
-            loop {
-                let result = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as _, -1) };
-                if result == -1 {
-                    let err = io::Error::last_os_error();
-                    if err.kind() == io::ErrorKind::Interrupted {
-                        continue;
-                    }
-                    return Err(err.into());
-                }
-                break;
-            }
-
-            for fd in &pollfds {
-                if fd.revents != 0 {
-                    let mut cb = self.cbs.remove(&fd.fd).unwrap();
-                    match cb(EventLoopCtx { conn, state })? {
-                        Action::Keep => {
-                            self.cbs.insert(fd.fd, cb);
-                        }
-                        Action::Unregister => (),
-                    }
-                }
-            }
+    /// Remove the fd or the timer. Does nothing if it has already been removed.
+    pub fn remove(&mut self, token: Token) {
+        self.pending.push(Pending::Remove(token));
+    }
 
-            for mut cb in self.on_idle.drain(..) {
-                match cb(EventLoopCtx { conn, state })? {
-                    Action::Keep => on_idle_scratch.push(cb),
-                    Action::Unregister => (),
-                }
-            }
-            self.on_idle.append(&mut on_idle_scratch);
-        }
-        Ok(())
+    fn next_token(&mut self) -> Token {
+        self.next_token += 1;
+        Token(self.next_token)
     }
 }
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::os::fd::{BorrowedFd, RawFd};
+use std::time::Duration;
+
+use anyhow::Result;
+use calloop::generic::Generic;
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{Interest, Mode, PostAction, RegistrationToken};
+use wayrs_client::Connection;
+
+use super::{Action, Callback, EventLoopCtx, Handle, Pending, Token};
+use crate::state::State;
+
+/// Callback-based event loop. Implemented using calloop.
+///
+/// calloop sources only record which of them are ready, the callbacks are then called with the
+/// connection and the state, which calloop knows nothing about.
+pub struct EventLoop {
+    inner: calloop::EventLoop<'static, Vec<Token>>,
+    handle: Handle,
+    cbs: HashMap<Token, (RegistrationToken, Callback)>,
+    on_idle: Vec<Callback>,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        Self {
+            inner: calloop::EventLoop::try_new().expect("failed to create calloop event loop"),
+            handle: Handle::default(),
+            cbs: HashMap::new(),
+            on_idle: Vec::new(),
+        }
+    }
+
+    /// Call `cb` whenever `fd` is readable, until it returns [`Action::Unregister`].
+    pub fn register_with_fd<F>(&mut self, fd: RawFd, cb: F) -> Token
+    where
+        F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
+    {
+        self.handle.register_with_fd(fd, cb)
+    }
+
+    /// Call `cb` every `interval`, until it returns [`Action::Unregister`].
+    pub fn add_timer<F>(&mut self, interval: Duration, cb: F) -> Token
+    where
+        F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
+    {
+        self.handle.add_timer(interval, cb)
+    }
+
+    pub fn add_on_idle<F>(&mut self, cb: F)
+    where
+        F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
+    {
+        self.on_idle.push(Box::new(cb));
+    }
+
+    pub fn run(&mut self, conn: &mut Connection<State>, state: &mut State) -> Result<()> {
+        let mut ready = Vec::new();
+        let mut on_idle_scratch = Vec::new();
+
+        self.apply_pending();
+        while !self.cbs.is_empty() {
+            self.inner.dispatch(None, &mut ready)?;
+
+            for token in ready.drain(..) {
+                let Some((_, cb)) = self.cbs.get_mut(&token) else {
+                    continue;
+                };
+                let ctx = EventLoopCtx {
+                    conn,
+                    state,
+                    handle: &mut self.handle,
+                };
+                match cb(ctx)? {
+                    Action::Keep => (),
+                    Action::Unregister => self.handle.remove(token),
+                }
+                self.apply_pending();
+            }
+
+            for mut cb in self.on_idle.drain(..) {
+                let ctx = EventLoopCtx {
+                    conn,
+                    state,
+                    handle: &mut self.handle,
+                };
+                match cb(ctx)? {
+                    Action::Keep => on_idle_scratch.push(cb),
+                    Action::Unregister => (),
+                }
+            }
+            self.on_idle.append(&mut on_idle_scratch);
+            self.apply_pending();
+        }
+        Ok(())
+    }
+
+    /// Apply the changes requested through the [`Handle`].
+    fn apply_pending(&mut self) {
+        for pending in std::mem::take(&mut self.handle.pending) {
+            match pending {
+                Pending::Fd(token, fd, cb) => {
+                    // calloop only needs the fd for the epoll registration, and the kernel drops
+                    // closed fds from the epoll set on its own
+                    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+                    let registration = self
+                        .inner
+                        .handle()
+                        .insert_source(
+                            Generic::new(borrowed, Interest::READ, Mode::Level),
+                            move |_, _, ready: &mut Vec<Token>| {
+                                ready.push(token);
+                                Ok(PostAction::Continue)
+                            },
+                        )
+                        .map_err(|e| e.error)
+                        .expect("failed to register fd");
+                    self.cbs.insert(token, (registration, cb));
+                }
+                Pending::Timer(token, interval, cb) => {
+                    let registration = self
+                        .inner
+                        .handle()
+                        .insert_source(
+                            Timer::from_duration(interval),
+                            move |_, _, ready: &mut Vec<Token>| {
+                                ready.push(token);
+                                TimeoutAction::ToDuration(interval)
+                            },
+                        )
+                        .map_err(|e| e.error)
+                        .expect("failed to register timer");
+                    self.cbs.insert(token, (registration, cb));
+                }
+                Pending::Remove(token) => {
+                    if let Some((registration, _)) = self.cbs.remove(&token) {
+                        self.inner.handle().remove(registration);
+                    }
+                }
+            }
+        }
+    }
+}
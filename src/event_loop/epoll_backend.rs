@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use wayrs_client::Connection;
+
+use super::{Action, Callback, EventLoopCtx, Handle, Pending, Token};
+use crate::state::State;
+
+/// Simple callback-based event loop. Implemented using `epoll`.
+pub struct EventLoop {
+    epoll: OwnedFd,
+    handle: Handle,
+    fds: HashMap<Token, (RawFd, Callback)>,
+    timers: Vec<Timer>,
+    on_idle: Vec<Callback>,
+}
+
+struct Timer {
+    token: Token,
+    deadline: Instant,
+    interval: Duration,
+    cb: Callback,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        assert_ne!(epoll, -1, "epoll_create1: {}", io::Error::last_os_error());
+        Self {
+            epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+            handle: Handle::default(),
+            fds: HashMap::new(),
+            timers: Vec::new(),
+            on_idle: Vec::new(),
+        }
+    }
+
+    /// Call `cb` whenever `fd` is readable, until it returns [`Action::Unregister`].
+    pub fn register_with_fd<F>(&mut self, fd: RawFd, cb: F) -> Token
+    where
+        F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
+    {
+        self.handle.register_with_fd(fd, cb)
+    }
+
+    /// Call `cb` every `interval`, until it returns [`Action::Unregister`].
+    pub fn add_timer<F>(&mut self, interval: Duration, cb: F) -> Token
+    where
+        F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
+    {
+        self.handle.add_timer(interval, cb)
+    }
+
+    pub fn add_on_idle<F>(&mut self, cb: F)
+    where
+        F: FnMut(EventLoopCtx) -> Result<Action> + 'static,
+    {
+        self.on_idle.push(Box::new(cb));
+    }
+
+    pub fn run(&mut self, conn: &mut Connection<State>, state: &mut State) -> Result<()> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 16];
+        let mut on_idle_scratch = Vec::new();
+
+        self.apply_pending()?;
+        while !self.fds.is_empty() || !self.timers.is_empty() {
+            let n = loop {
+                let timeout = match self.timers.iter().map(|t| t.deadline).min() {
+                    // Round up, so that we do not wake up too early
+                    Some(deadline) => deadline
+                        .saturating_duration_since(Instant::now())
+                        .as_micros()
+                        .div_ceil(1000)
+                        .min(libc::c_int::MAX as u128)
+                        as libc::c_int,
+                    None => -1,
+                };
+                let result = unsafe {
+                    libc::epoll_wait(
+                        self.epoll.as_raw_fd(),
+                        events.as_mut_ptr(),
+                        events.len() as _,
+                        timeout,
+                    )
+                };
+                if result == -1 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(err.into());
+                }
+                break result as usize;
+            };
+
+            for event in &events[..n] {
+                let token = Token(event.u64);
+                // Removed by an earlier callback
+                let Some((fd, mut cb)) = self.fds.remove(&token) else {
+                    continue;
+                };
+                let ctx = EventLoopCtx {
+                    conn,
+                    state,
+                    handle: &mut self.handle,
+                };
+                match cb(ctx)? {
+                    Action::Keep => {
+                        self.fds.insert(token, (fd, cb));
+                    }
+                    Action::Unregister => self.unwatch(fd),
+                }
+                self.apply_pending()?;
+            }
+
+            let now = Instant::now();
+            let mut i = 0;
+            while i < self.timers.len() {
+                let timer = &mut self.timers[i];
+                if timer.deadline > now {
+                    i += 1;
+                    continue;
+                }
+                let ctx = EventLoopCtx {
+                    conn,
+                    state,
+                    handle: &mut self.handle,
+                };
+                match (timer.cb)(ctx)? {
+                    Action::Keep => {
+                        timer.deadline = now + timer.interval;
+                        i += 1;
+                    }
+                    Action::Unregister => {
+                        self.timers.swap_remove(i);
+                    }
+                }
+            }
+            self.apply_pending()?;
+
+            for mut cb in self.on_idle.drain(..) {
+                let ctx = EventLoopCtx {
+                    conn,
+                    state,
+                    handle: &mut self.handle,
+                };
+                match cb(ctx)? {
+                    Action::Keep => on_idle_scratch.push(cb),
+                    Action::Unregister => (),
+                }
+            }
+            self.on_idle.append(&mut on_idle_scratch);
+            self.apply_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Apply the changes requested through the [`Handle`].
+    fn apply_pending(&mut self) -> io::Result<()> {
+        for pending in std::mem::take(&mut self.handle.pending) {
+            match pending {
+                Pending::Fd(token, fd, cb) => {
+                    let mut event = libc::epoll_event {
+                        events: libc::EPOLLIN as u32,
+                        u64: token.0,
+                    };
+                    let ret = unsafe {
+                        libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event)
+                    };
+                    if ret == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    self.fds.insert(token, (fd, cb));
+                }
+                Pending::Timer(token, interval, cb) => self.timers.push(Timer {
+                    token,
+                    deadline: Instant::now() + interval,
+                    interval,
+                    cb,
+                }),
+                Pending::Remove(token) => {
+                    if let Some((fd, _)) = self.fds.remove(&token) {
+                        self.unwatch(fd);
+                    }
+                    self.timers.retain(|t| t.token != token);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unwatch(&self, fd: RawFd) {
+        // Fails if the fd has already been closed, which removes it from the epoll set anyway
+        unsafe {
+            libc::epoll_ctl(
+                self.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                fd,
+                std::ptr::null_mut(),
+            )
+        };
+    }
+}
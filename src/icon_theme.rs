@@ -0,0 +1,263 @@
+//! Finding the icons of the tray items in the freedesktop icon themes, see `tray_icon_theme` in
+//! README.
+//!
+//! Follows the lookup of the Icon Theme Specification: the configured theme and the themes it
+//! inherits from are searched for the icon closest to the requested size, then hicolor, then the
+//! `pixmaps` directories. Only PNG icons are considered, and SVG icons with the `svg` feature.
+//! The sizes are in physical pixels, so the directories of scaled icons (`Scale=2`) are only used
+//! when nothing closer exists.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The extensions of the icon files, most preferred first.
+const EXTENSIONS: &[&str] = if cfg!(feature = "svg") {
+    &["png", "svg"]
+} else {
+    &["png"]
+};
+
+pub struct IconThemes {
+    /// The `icons` directories, where the themes are.
+    base_dirs: Vec<PathBuf>,
+    /// The `pixmaps` directories, searched last.
+    pixmaps_dirs: Vec<PathBuf>,
+    /// The configured theme and the themes it inherits from, then hicolor.
+    themes: Vec<Theme>,
+}
+
+struct Theme {
+    name: String,
+    /// The directories of the theme, e.g. `/usr/share/icons/Adwaita`.
+    dirs: Vec<PathBuf>,
+    subdirs: Vec<SubDir>,
+}
+
+struct SubDir {
+    path: String,
+    size: u32,
+    scale: u32,
+    kind: SizeKind,
+}
+
+enum SizeKind {
+    Fixed,
+    Scalable { min: u32, max: u32 },
+    Threshold(u32),
+}
+
+impl IconThemes {
+    /// Load `theme` and its parents. Without a theme, or if it is not installed, only hicolor is
+    /// searched.
+    pub fn new(theme: Option<&str>) -> Self {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".local/share")));
+        let data_dirs = env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+        let data_dirs: Vec<PathBuf> = data_home
+            .into_iter()
+            .chain(data_dirs.split(':').map(PathBuf::from))
+            .collect();
+
+        let mut this = Self {
+            base_dirs: data_dirs.iter().map(|dir| dir.join("icons")).collect(),
+            pixmaps_dirs: data_dirs.iter().map(|dir| dir.join("pixmaps")).collect(),
+            themes: Vec::new(),
+        };
+        if let Some(home) = env::var_os("HOME") {
+            this.base_dirs.insert(0, PathBuf::from(home).join(".icons"));
+        }
+
+        let mut pending: Vec<String> = theme.into_iter().map(String::from).collect();
+        while let Some(name) = pending.pop() {
+            if name == "hicolor" || this.themes.iter().any(|t| t.name == name) {
+                continue;
+            }
+            match Theme::load(&name, &this.base_dirs) {
+                Some((theme, parents)) => {
+                    this.themes.push(theme);
+                    // The first parent is searched first
+                    pending.extend(parents.into_iter().rev());
+                }
+                None => warn!("the icon theme {name} is not installed"),
+            }
+        }
+        if let Some((hicolor, _)) = Theme::load("hicolor", &this.base_dirs) {
+            this.themes.push(hicolor);
+        }
+        this
+    }
+
+    /// The file of the icon `name` closest to `size` pixels. The themes in `extra_dir`, the
+    /// `IconThemePath` of an item, are searched first, as well as the icons directly in it.
+    pub fn find(&self, name: &str, size: u32, extra_dir: Option<&Path>) -> Option<PathBuf> {
+        if name.starts_with('/') {
+            return Some(PathBuf::from(name));
+        }
+        if let Some(extra_dir) = extra_dir {
+            // Usually without an index.theme, so laid out like the installed theme
+            let found = self
+                .themes
+                .iter()
+                .find_map(|theme| theme.lookup(&[extra_dir.join(&theme.name)], name, size))
+                .or_else(|| find_in_dir(extra_dir, name));
+            if found.is_some() {
+                return found;
+            }
+        }
+        self.themes
+            .iter()
+            .find_map(|theme| theme.lookup(&theme.dirs, name, size))
+            .or_else(|| {
+                self.pixmaps_dirs
+                    .iter()
+                    .find_map(|dir| find_in_dir(dir, name))
+            })
+    }
+}
+
+impl Theme {
+    /// Read the `index.theme` of the theme `name`. Returns the theme and the names of its parents.
+    fn load(name: &str, base_dirs: &[PathBuf]) -> Option<(Self, Vec<String>)> {
+        let dirs: Vec<PathBuf> = base_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        let index = dirs
+            .iter()
+            .find_map(|dir| fs::read_to_string(dir.join("index.theme")).ok())?;
+        let sections = parse_ini(&index);
+
+        let main = sections
+            .iter()
+            .find(|(section, _)| *section == "Icon Theme")
+            .map(|(_, entries)| entries.as_slice())
+            .unwrap_or_default();
+        let get = |entries: &[(&str, &str)], key: &str| {
+            entries.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+        };
+        let list = |value: Option<&str>| -> Vec<String> {
+            value
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let parents = list(get(main, "Inherits"));
+        let mut subdir_names = list(get(main, "Directories"));
+        subdir_names.extend(list(get(main, "ScaledDirectories")));
+
+        let subdirs = subdir_names
+            .into_iter()
+            .filter_map(|path| {
+                let (_, entries) = sections.iter().find(|(section, _)| *section == path)?;
+                let number = |key: &str| get(entries, key).and_then(|v| v.parse::<u32>().ok());
+                let size = number("Size")?;
+                let kind = match get(entries, "Type").unwrap_or("Threshold") {
+                    "Fixed" => SizeKind::Fixed,
+                    "Scalable" => SizeKind::Scalable {
+                        min: number("MinSize").unwrap_or(size),
+                        max: number("MaxSize").unwrap_or(size),
+                    },
+                    _ => SizeKind::Threshold(number("Threshold").unwrap_or(2)),
+                };
+                Some(SubDir {
+                    path,
+                    size,
+                    scale: number("Scale").unwrap_or(1),
+                    kind,
+                })
+            })
+            .collect();
+
+        let theme = Self {
+            name: name.to_owned(),
+            dirs,
+            subdirs,
+        };
+        Some((theme, parents))
+    }
+
+    /// The file of the icon `name` in `dirs`, of exactly `size` pixels or the closest one.
+    fn lookup(&self, dirs: &[PathBuf], name: &str, size: u32) -> Option<PathBuf> {
+        let files = || {
+            self.subdirs.iter().flat_map(move |subdir| {
+                dirs.iter().flat_map(move |dir| {
+                    EXTENSIONS.iter().map(move |ext| {
+                        let file = dir.join(&subdir.path).join(format!("{name}.{ext}"));
+                        (subdir, file)
+                    })
+                })
+            })
+        };
+        files()
+            .find(|(subdir, file)| subdir.matches(size) && file.is_file())
+            .or_else(|| {
+                files()
+                    .filter(|(_, file)| file.is_file())
+                    .min_by_key(|(subdir, _)| subdir.distance(size))
+            })
+            .map(|(_, file)| file)
+    }
+}
+
+impl SubDir {
+    fn matches(&self, size: u32) -> bool {
+        if self.scale != 1 {
+            return false;
+        }
+        match self.kind {
+            SizeKind::Fixed => self.size == size,
+            SizeKind::Scalable { min, max } => (min..=max).contains(&size),
+            SizeKind::Threshold(t) => (self.size.saturating_sub(t)..=self.size + t).contains(&size),
+        }
+    }
+
+    /// How far the icons of this directory are from `size` pixels.
+    fn distance(&self, size: u32) -> u32 {
+        let (min, max) = match self.kind {
+            SizeKind::Fixed => (self.size, self.size),
+            SizeKind::Scalable { min, max } => (min, max),
+            SizeKind::Threshold(t) => (self.size.saturating_sub(t), self.size + t),
+        };
+        let (min, max) = (min * self.scale, max * self.scale);
+        if size < min {
+            min - size
+        } else {
+            size.saturating_sub(max)
+        }
+    }
+}
+
+/// The icon `name` directly in `dir`.
+fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{name}.{ext}")))
+        .find(|path| path.is_file())
+}
+
+/// The sections of an INI file, with their entries.
+fn parse_ini(text: &str) -> Vec<(&str, Vec<(&str, &str)>)> {
+    let mut sections: Vec<(&str, Vec<(&str, &str)>)> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((section, Vec::new()));
+        } else if let (Some((key, value)), Some((_, entries))) =
+            (line.split_once('='), sections.last_mut())
+        {
+            entries.push((key.trim(), value.trim()));
+        }
+    }
+    sections
+}
@@ -0,0 +1,81 @@
+//! Hiding or fading the bar while the seats are idle, using ext-idle-notify-v1.
+
+use std::time::Duration;
+
+use wayrs_client::global::{Globals, GlobalsExt};
+use wayrs_client::{Connection, EventCtx};
+
+use crate::protocol::*;
+use crate::state::State;
+
+pub struct Idle {
+    notifier: ExtIdleNotifierV1,
+    timeout_ms: u32,
+    notifications: Vec<Notification>,
+}
+
+struct Notification {
+    seat: WlSeat,
+    notification: ExtIdleNotificationV1,
+    idle: bool,
+}
+
+impl Idle {
+    pub fn bind(
+        conn: &mut Connection<State>,
+        globals: &Globals,
+        timeout: Duration,
+    ) -> Option<Self> {
+        Some(Self {
+            notifier: globals.bind(conn, 1).ok()?,
+            timeout_ms: timeout.as_millis().try_into().unwrap_or(u32::MAX),
+            notifications: Vec::new(),
+        })
+    }
+
+    pub fn seat_added(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        let notification = self.notifier.get_idle_notification_with_cb(
+            conn,
+            self.timeout_ms,
+            seat,
+            notification_cb,
+        );
+        self.notifications.push(Notification {
+            seat,
+            notification,
+            idle: false,
+        });
+    }
+
+    pub fn seat_removed(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        self.notifications.retain(|n| {
+            if n.seat == seat {
+                n.notification.destroy(conn);
+            }
+            n.seat != seat
+        });
+    }
+
+    /// Whether every seat has been idle for the timeout.
+    pub fn is_idle(&self) -> bool {
+        !self.notifications.is_empty() && self.notifications.iter().all(|n| n.idle)
+    }
+}
+
+fn notification_cb(ctx: EventCtx<State, ExtIdleNotificationV1>) {
+    let Some(idle) = &mut ctx.state.idle else {
+        return;
+    };
+    let Some(notification) = idle
+        .notifications
+        .iter_mut()
+        .find(|n| n.notification == ctx.proxy)
+    else {
+        return;
+    };
+    notification.idle = match ctx.event {
+        ext_idle_notification_v1::Event::Idled => true,
+        ext_idle_notification_v1::Event::Resumed => false,
+    };
+    ctx.state.idle_changed(ctx.conn);
+}
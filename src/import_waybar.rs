@@ -0,0 +1,342 @@
+//! `--import-waybar`: translate a waybar config into an i3bar-river config.
+//!
+//! Only the options which have an equivalent are translated: the position, the layer, the height,
+//! the margins and the disabled outputs from the config, and the colors and the font from the
+//! `style.css` next to it, if the rules are simple enough. Everything else is left to the user.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use toml::Table;
+
+pub fn run(path: &Path) -> Result<()> {
+    let config =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let style_path = path.with_file_name("style.css");
+    let style = fs::read_to_string(&style_path).ok();
+    let toml = translate(&config, style.as_deref())
+        .with_context(|| format!("Failed to translate {}", path.display()))?;
+    println!("# Imported from waybar, see the README for the other options");
+    if style.is_some() {
+        println!(
+            "# The colors and the font are from {}",
+            style_path.display()
+        );
+    }
+    print!("{toml}");
+    Ok(())
+}
+
+/// The i3bar-river config equivalent to the waybar `config` and `style`, with comments about what
+/// could not be translated first.
+fn translate(config: &str, style: Option<&str>) -> Result<String> {
+    let config: Value = serde_json::from_str(&strip_jsonc(config))?;
+    // A config may describe several bars, only the first one is translated
+    let bar = match &config {
+        Value::Array(bars) => bars.first().context("the config has no bars")?,
+        bar => bar,
+    };
+
+    let mut table = Table::new();
+    let mut notes = Vec::new();
+    translate_config(bar, &mut table, &mut notes);
+    if let Some(style) = style {
+        translate_style(style, &mut table);
+    }
+
+    let mut toml: String = notes.iter().map(|note| format!("# {note}\n")).collect();
+    toml.push_str(&toml::to_string(&table)?);
+    Ok(toml)
+}
+
+fn translate_config(bar: &Value, table: &mut Table, notes: &mut Vec<String>) {
+    match bar["position"].as_str() {
+        Some(position @ ("top" | "bottom")) => {
+            table.insert("position".into(), position.into());
+        }
+        Some(position) => notes.push(format!("position {position:?} is not supported")),
+        None => (),
+    }
+    if let Some(layer @ ("top" | "bottom" | "overlay")) = bar["layer"].as_str() {
+        table.insert("layer".into(), layer.into());
+    }
+    if let Some(height) = bar["height"].as_i64().filter(|&h| h >= 0) {
+        table.insert("height".into(), height.into());
+    }
+
+    // "margin" is CSS-like, the specific ones take precedence
+    let mut margins = match &bar["margin"] {
+        Value::Number(n) => n.as_i64().map(|m| [m; 4]),
+        Value::String(s) => css_margins(s),
+        _ => None,
+    }
+    .unwrap_or_default();
+    for (i, side) in ["top", "right", "bottom", "left"].into_iter().enumerate() {
+        if let Some(margin) = bar[format!("margin-{side}")].as_i64() {
+            margins[i] = margin;
+        }
+    }
+    for (margin, side) in margins.into_iter().zip(["top", "right", "bottom", "left"]) {
+        if margin != 0 {
+            table.insert(format!("margin_{side}"), margin.into());
+        }
+    }
+
+    let outputs: Vec<&str> = match &bar["output"] {
+        Value::String(output) => vec![output],
+        Value::Array(outputs) => outputs.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let mut disabled_outputs = Table::new();
+    for output in outputs {
+        match output.strip_prefix('!') {
+            Some(disabled) => {
+                let overrides = Table::from_iter([("enable".to_owned(), false.into())]);
+                disabled_outputs.insert(disabled.into(), overrides.into());
+            }
+            None => notes.push(format!(
+                "only on output {output:?}: disable the other outputs with [output.NAME]"
+            )),
+        }
+    }
+    if !disabled_outputs.is_empty() {
+        table.insert("output".into(), disabled_outputs.into());
+    }
+}
+
+/// Parse `"top [right [bottom [left]]]"` like CSS.
+fn css_margins(s: &str) -> Option<[i64; 4]> {
+    let values: Vec<i64> = s
+        .split_whitespace()
+        .map(|v| v.trim_end_matches("px").parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    Some(match *values.as_slice() {
+        [all] => [all; 4],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return None,
+    })
+}
+
+/// Pick the colors and the font from the rules which have a counterpart. Properties are applied
+/// in order, so a later rule overrides an earlier one, like in CSS.
+fn translate_style(style: &str, table: &mut Table) {
+    let style = strip_comments(style, false);
+    let mut family = None;
+    let mut size = None;
+    for rule in style.split('}') {
+        let Some((selectors, declarations)) = rule.split_once('{') else {
+            continue;
+        };
+        for selector in selectors.split(',').map(str::trim) {
+            let (bg, fg) = match selector {
+                "window#waybar" => ("background", "color"),
+                "#workspaces button" => ("tag_bg", "tag_fg"),
+                "#workspaces button.focused" | "#workspaces button.active" => {
+                    ("tag_focused_bg", "tag_focused_fg")
+                }
+                "#workspaces button.urgent" => ("tag_urgent_bg", "tag_urgent_fg"),
+                "*" | "window#waybar *" => ("", ""),
+                _ => continue,
+            };
+            for declaration in declarations.split(';') {
+                let Some((property, value)) = declaration.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim().trim_end_matches("!important").trim();
+                let option = match property.trim() {
+                    "background" | "background-color" => bg,
+                    "color" => fg,
+                    "font-family" => {
+                        family = value
+                            .split(',')
+                            .next()
+                            .map(|f| f.trim().trim_matches(['"', '\'']).to_owned());
+                        continue;
+                    }
+                    "font-size" => {
+                        // Pango takes points without a unit, or pixels with "px"
+                        size = Some(value.trim_end_matches("pt").to_owned());
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if let (false, Some(color)) = (option.is_empty(), css_color(value)) {
+                    table.insert(option.into(), color.into());
+                }
+            }
+        }
+    }
+    if let Some(family) = family {
+        let size = size.as_deref().unwrap_or("10");
+        table.insert("font".into(), format!("{family} {size}").into());
+    }
+}
+
+/// Convert `#rgb`, `#rrggbb` and `rgb[a](r, g, b[, a])` to `#rrggbbaa`.
+fn css_color(value: &str) -> Option<String> {
+    if let Some(hex) = value.strip_prefix('#') {
+        return match hex.len() {
+            3 if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let doubled: String = hex.chars().flat_map(|c| [c, c]).collect();
+                Some(format!("#{doubled}ff"))
+            }
+            6 if hex.chars().all(|c| c.is_ascii_hexdigit()) => Some(format!("#{hex}ff")),
+            _ => None,
+        };
+    }
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let args: Vec<&str> = args.split(',').map(str::trim).collect();
+    let channel = |s: &str| s.parse::<u8>().ok();
+    let (r, g, b) = (
+        channel(args.first()?)?,
+        channel(args.get(1)?)?,
+        channel(args.get(2)?)?,
+    );
+    let a = match args.get(3) {
+        Some(a) => (a.parse::<f64>().ok()?.clamp(0.0, 1.0) * 255.0).round() as u8,
+        None => 255,
+    };
+    Some(format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+}
+
+/// waybar configs are JSON with comments and trailing commas.
+fn strip_jsonc(s: &str) -> String {
+    let s = strip_comments(s, true);
+    let mut out = String::with_capacity(s.len());
+    let mut in_string = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c == ',' {
+            // Drop the comma if only whitespace separates it from the end of an object or array
+            let rest = chars.clone().find(|c| !c.is_whitespace());
+            if !matches!(rest, Some('}' | ']')) {
+                out.push(c);
+            }
+        } else {
+            in_string = c == '"';
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Remove `/* */` comments, and `//` comments if `line_comments`, outside of strings.
+fn strip_comments(s: &str, line_comments: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_string = None;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(quote) = in_string {
+            out.push(c);
+            if c == '\\' {
+                out.extend(chars.next());
+            } else if c == quote {
+                in_string = None;
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            ('/', Some('/')) if line_comments => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('"' | '\'', _) => {
+                in_string = Some(c);
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_configs() {
+        let cases = [
+            (
+                r#"{"position": "bottom", "layer": "top", "height": 24}"#,
+                None,
+                "height = 24\nlayer = \"top\"\nposition = \"bottom\"\n",
+            ),
+            (
+                // JSONC, and only the first bar
+                r#"[{"margin": "4 8", /* comment */ "margin-top": 2,}, {"height": 30}]"#,
+                None,
+                "margin_bottom = 4\nmargin_left = 8\nmargin_right = 8\nmargin_top = 2\n",
+            ),
+            (
+                r#"{"position": "left", "output": ["eDP-1", "!HDMI-A-1", "!My \"TV\""]}"#,
+                None,
+                "# position \"left\" is not supported\n\
+                 # only on output \"eDP-1\": disable the other outputs with [output.NAME]\n\
+                 [output.HDMI-A-1]\nenable = false\n\n\
+                 [output.'My \"TV\"']\nenable = false\n",
+            ),
+            (
+                "{}",
+                Some(
+                    r#"window#waybar { background: rgba(0, 0, 0, 0.5); color: #fff; }
+                    #workspaces button.focused { background-color: #123456 !important; }
+                    * { font-family: "JetBrains Mono", monospace; font-size: 12px; }"#,
+                ),
+                "background = \"#00000080\"\ncolor = \"#ffffffff\"\n\
+                 font = \"JetBrains Mono 12px\"\ntag_focused_bg = \"#123456ff\"\n",
+            ),
+        ];
+        for (config, style, expected) in cases {
+            let toml = translate(config, style).unwrap();
+            assert_eq!(toml, expected, "{config}");
+            // The output is a valid config
+            toml::from_str::<Table>(&toml).unwrap();
+        }
+    }
+
+    #[test]
+    fn css_colors() {
+        let cases = [
+            ("#abc", Some("#aabbccff")),
+            ("#a1b2c3", Some("#a1b2c3ff")),
+            ("rgb(1, 2, 255)", Some("#0102ffff")),
+            ("rgba(0,0,0,1)", Some("#000000ff")),
+            ("#abcd", None),
+            ("red", None),
+            ("rgb(256, 0, 0)", None),
+        ];
+        for (css, expected) in cases {
+            assert_eq!(css_color(css).as_deref(), expected, "{css}");
+        }
+    }
+}
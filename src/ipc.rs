@@ -0,0 +1,337 @@
+//! The control socket.
+//!
+//! Clients connect to `$XDG_RUNTIME_DIR/i3bar-river-$WAYLAND_DISPLAY.sock`, write a single line
+//! with a command and receive a single line of JSON in reply. After `subscribe`, the connection is
+//! kept open and a line of JSON is sent for every event.
+//!
+//! The socket is guarded by a lock file, which also ensures that only one instance of the bar is
+//! running per Wayland display.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use wayrs_client::Connection;
+
+use crate::config::{BarMode, Timeout};
+use crate::event_loop::{self, EventLoop, Handle};
+use crate::state::State;
+use crate::utils::{read_to_vec, try_lock, wayland_display_name};
+
+/// How long to wait for the previous instance to quit when replacing it.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a client to send a command.
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Clients sending a longer command are dropped.
+const MAX_REQUEST_LEN: usize = 4096;
+
+pub enum Request {
+    Quit,
+    Show,
+    Hide,
+    Toggle,
+    ReloadConfig,
+    SetOutputEnabled(String, bool),
+    GetState,
+    Subscribe,
+    Peek(Option<Duration>),
+    BarMode(BarMode),
+    Reveal(bool),
+    SetProfile(String),
+}
+
+impl FromStr for Request {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut args = s.split_whitespace();
+        let cmd = args.next().context("empty command")?;
+        let request = match cmd {
+            "quit" => Self::Quit,
+            "show" => Self::Show,
+            "hide" => Self::Hide,
+            "toggle" => Self::Toggle,
+            "reload-config" => Self::ReloadConfig,
+            "set-output-enabled" => {
+                let output = args.next().context("missing output")?.to_owned();
+                Self::SetOutputEnabled(
+                    output,
+                    match args.next() {
+                        Some("true") => true,
+                        Some("false") => false,
+                        _ => bail!("expected 'set-output-enabled OUTPUT true|false'"),
+                    },
+                )
+            }
+            "get-state" => Self::GetState,
+            "subscribe" => Self::Subscribe,
+            "peek" => Self::Peek(match args.next() {
+                Some(duration) => Some(
+                    duration
+                        .parse::<Timeout>()
+                        .map_err(|()| anyhow!("'{duration}' is not a valid duration"))?
+                        .0,
+                ),
+                None => None,
+            }),
+            "bar-mode" => {
+                let mode = args.next().context("missing mode")?;
+                Self::BarMode(mode.parse().map_err(|()| {
+                    anyhow!("unknown mode '{mode}', expected dock, hide or invisible")
+                })?)
+            }
+            "reveal" => Self::Reveal(match args.next() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => bail!("expected 'reveal on' or 'reveal off'"),
+            }),
+            "set-profile" => Self::SetProfile(args.next().context("missing profile")?.to_owned()),
+            other => bail!("unknown command '{other}'"),
+        };
+        ensure!(args.next().is_none(), "too many arguments for '{cmd}'");
+        Ok(request)
+    }
+}
+
+pub struct IpcServer {
+    listener: UnixListener,
+    socket_path: Rc<Path>,
+    _lock: File,
+}
+
+impl IpcServer {
+    /// Take the lock and bind the socket.
+    ///
+    /// Fails if another instance is running, unless `replace` is set, in which case that instance
+    /// is asked to quit. Returns `None` if `$XDG_RUNTIME_DIR` is not set.
+    pub fn bind(bar_id: Option<&str>, replace: bool) -> Result<Option<Self>> {
+        let Some(socket_path) = socket_path(bar_id) else {
+            return Ok(None);
+        };
+        let lock_path = socket_path.with_extension("lock");
+        let lock = File::create(&lock_path).context("Failed to create the lock file")?;
+
+        if !try_lock(&lock)? {
+            ensure!(
+                replace,
+                "another instance of i3bar-river is already running on this display, \
+                 use --replace to replace it"
+            );
+            send_request(&socket_path, "quit")
+                .context("Failed to ask the running instance to quit")?;
+            let start = Instant::now();
+            while !try_lock(&lock)? {
+                ensure!(
+                    start.elapsed() < REPLACE_TIMEOUT,
+                    "the running instance did not quit in time"
+                );
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+
+        // We hold the lock, so the socket (if any) is stale.
+        let _ = fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).context("Failed to bind the socket")?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Some(Self {
+            listener,
+            socket_path: socket_path.into(),
+            _lock: lock,
+        }))
+    }
+
+    pub fn register(self, event_loop: &mut EventLoop) {
+        event_loop.register_with_fd(self.listener.as_raw_fd(), move |ctx| {
+            self.accept_clients(ctx.handle)?;
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    /// Accept the new clients and register them in the event loop, until they send a command.
+    fn accept_clients(&self, handle: &mut Handle) -> io::Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            stream.set_nonblocking(true)?;
+            let fd = stream.as_raw_fd();
+            let mut client = Client {
+                stream,
+                buf: Vec::new(),
+                socket_path: self.socket_path.clone(),
+            };
+            let token = handle.register_with_fd(fd, move |ctx| {
+                // A misbehaving client must not break the bar
+                Ok(client
+                    .receive(ctx.conn, ctx.state, ctx.handle)
+                    .unwrap_or(event_loop::Action::Unregister))
+            });
+            // Drop the client if it does not send a command in time
+            handle.add_timer(READ_TIMEOUT, move |ctx| {
+                ctx.handle.remove(token);
+                Ok(event_loop::Action::Unregister)
+            });
+        }
+    }
+}
+
+/// A client which has not sent a complete command yet.
+struct Client {
+    stream: UnixStream,
+    buf: Vec<u8>,
+    socket_path: Rc<Path>,
+}
+
+impl Client {
+    /// Read the available bytes and handle the command once the whole line has been received.
+    fn receive(
+        &mut self,
+        conn: &mut Connection<State>,
+        state: &mut State,
+        handle: &mut Handle,
+    ) -> io::Result<event_loop::Action> {
+        let eof = match read_to_vec(&self.stream, &mut self.buf) {
+            Ok(0) if self.buf.is_empty() => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => n == 0,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(event_loop::Action::Keep),
+            Err(e) => return Err(e),
+        };
+        let line = match self.buf.iter().position(|&b| b == b'\n') {
+            Some(end) => &self.buf[..end],
+            None if eof => &self.buf[..],
+            None if self.buf.len() > MAX_REQUEST_LEN => return Err(ErrorKind::InvalidData.into()),
+            None => return Ok(event_loop::Action::Keep),
+        };
+        let line = String::from_utf8_lossy(line).into_owned();
+        self.stream.set_nonblocking(false)?;
+        self.handle_request(conn, state, handle, &line)?;
+        Ok(event_loop::Action::Unregister)
+    }
+
+    fn handle_request(
+        &mut self,
+        conn: &mut Connection<State>,
+        state: &mut State,
+        handle: &mut Handle,
+        line: &str,
+    ) -> io::Result<()> {
+        debug!("request: {}", line.trim());
+        let reply = match line.parse::<Request>() {
+            Ok(Request::Quit) => {
+                write_reply(&mut self.stream, &json!({ "success": true }))?;
+                let _ = fs::remove_file(&*self.socket_path);
+                state.quit();
+            }
+            Ok(Request::Show) => {
+                state.set_visible(conn, true);
+                json!({ "success": true })
+            }
+            Ok(Request::Hide) => {
+                state.set_visible(conn, false);
+                json!({ "success": true })
+            }
+            Ok(Request::Toggle) => {
+                state.toggle_visibility(conn);
+                json!({ "success": true })
+            }
+            Ok(Request::ReloadConfig) => match state.reload_config(conn) {
+                Ok(()) => json!({ "success": true }),
+                Err(e) => json!({ "success": false, "error": format!("{e:#}") }),
+            },
+            Ok(Request::SetOutputEnabled(output, enabled)) => {
+                match state.set_output_enabled(conn, &output, enabled) {
+                    Ok(()) => json!({ "success": true }),
+                    Err(e) => json!({ "success": false, "error": e.to_string() }),
+                }
+            }
+            Ok(Request::Peek(duration)) => {
+                let duration = duration.unwrap_or(state.shared_state.config.peek_duration.0);
+                state.peek(conn, handle, duration);
+                json!({ "success": true })
+            }
+            Ok(Request::BarMode(mode)) => {
+                state.set_mode(conn, mode);
+                json!({ "success": true })
+            }
+            Ok(Request::Reveal(revealed)) => {
+                state.reveal(conn, revealed);
+                json!({ "success": true })
+            }
+            Ok(Request::SetProfile(name)) => match state.check_profile(&name) {
+                Ok(()) => {
+                    write_reply(&mut self.stream, &json!({ "success": true }))?;
+                    state.restart_with_profile(conn, &name);
+                    return Ok(());
+                }
+                Err(e) => json!({ "success": false, "error": format!("{e:#}") }),
+            },
+            Ok(Request::GetState) => json!({ "success": true, "state": state.ipc_state() }),
+            Ok(Request::Subscribe) => {
+                write_reply(&mut self.stream, &json!({ "success": true }))?;
+                self.stream.set_nonblocking(true)?;
+                let stream = self.stream.try_clone()?;
+                state.shared_state.subscribers.0.push(stream);
+                return Ok(());
+            }
+            Err(e) => json!({ "success": false, "error": e.to_string() }),
+        };
+
+        write_reply(&mut self.stream, &reply)
+    }
+}
+
+/// The clients of the `subscribe` command.
+#[derive(Default)]
+pub struct Subscribers(Vec<UnixStream>);
+
+impl Subscribers {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Send `event` to every subscriber, dropping the ones which have disconnected or are too slow
+    /// to keep up.
+    pub fn send(&mut self, event: &serde_json::Value) {
+        self.0
+            .retain_mut(|stream| write_reply(stream, event).is_ok());
+    }
+}
+
+fn write_reply(stream: &mut UnixStream, reply: &serde_json::Value) -> io::Result<()> {
+    serde_json::to_writer(&mut *stream, reply)?;
+    stream.write_all(b"\n")
+}
+
+fn socket_path(bar_id: Option<&str>) -> Option<PathBuf> {
+    let mut path = PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?);
+    let display = wayland_display_name();
+    // Sway may run several bars, each one has its own socket
+    path.push(match bar_id {
+        Some(bar_id) => format!("i3bar-river-{display}-{bar_id}.sock"),
+        None => format!("i3bar-river-{display}.sock"),
+    });
+    Some(path)
+}
+
+/// Send a command to a running instance and return its reply.
+fn send_request(socket_path: &Path, request: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(b"\n")?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+    Ok(reply)
+}
@@ -0,0 +1,125 @@
+//! A small `log` backend.
+//!
+//! The filter is a comma-separated list of directives, each one either a level (`debug`) or a
+//! target and a level (`i3bar_river::wm_info_provider=trace`). The most specific target wins.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// The environment variable which is used if `--log-level` is not specified.
+pub const LOG_ENV: &str = "I3BAR_RIVER_LOG";
+
+struct Logger {
+    default: LevelFilter,
+    directives: Vec<(String, LevelFilter)>,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Logger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut output = self.output.lock().unwrap();
+        let _ = writeln!(
+            output,
+            "{} {:<5} [{}] {}",
+            timestamp(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = self.output.lock().unwrap().flush();
+    }
+}
+
+/// Initialize the logger. If `filter` is `None`, it is read from [`LOG_ENV`], and defaults to
+/// `info`. If `file` is `None`, logs are written to stderr.
+pub fn init(filter: Option<&str>, file: Option<&Path>) -> Result<()> {
+    let env_filter = std::env::var(LOG_ENV).ok();
+    let filter = filter.or(env_filter.as_deref()).unwrap_or("info");
+
+    let mut default = LevelFilter::Info;
+    let mut directives = Vec::new();
+    for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((target, level)) => {
+                let level = level
+                    .parse()
+                    .with_context(|| format!("invalid log level '{level}'"))?;
+                directives.push((target.to_owned(), level));
+            }
+            None => {
+                default = directive
+                    .parse()
+                    .with_context(|| format!("invalid log level '{directive}'"))?;
+            }
+        }
+    }
+
+    let output: Box<dyn Write + Send> = match file {
+        Some(path) => Box::new(File::create(path).context("Failed to create the log file")?),
+        None => Box::new(io::stderr()),
+    };
+
+    let max_level = directives
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(default, LevelFilter::max);
+
+    log::set_boxed_logger(Box::new(Logger {
+        default,
+        directives,
+        output: Mutex::new(output),
+    }))?;
+    log::set_max_level(max_level);
+
+    Ok(())
+}
+
+/// Local time as `HH:MM:SS.mmm`.
+fn timestamp() -> String {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_REALTIME, &mut ts);
+        libc::localtime_r(&ts.tv_sec, &mut tm);
+    }
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        ts.tv_nsec / 1_000_000
+    )
+}
@@ -1,29 +1,48 @@
 #[macro_use]
 extern crate anyhow;
+#[macro_use]
+extern crate log;
 
 mod bar;
-mod blocks_cache;
-mod button_manager;
-mod color;
+mod blocks_server;
+mod clipboard;
 mod config;
+mod config_watcher;
+mod dnd;
 mod event_loop;
-mod i3bar_protocol;
+#[cfg(feature = "tray")]
+mod icon_theme;
+mod idle;
+mod import_waybar;
+mod ipc;
+mod logger;
 mod output;
-mod pointer_btn;
+mod output_power;
+mod persist;
+mod plugins;
+mod print_blocks;
+mod profiler;
 mod protocol;
+mod recording;
+mod scripting;
 mod shared_state;
+mod signals;
 mod state;
 mod status_cmd;
-mod text;
+mod swaybar;
+mod sysinfo;
+mod tooltip;
+mod tray;
 mod utils;
 mod wm_info_provider;
 
-use std::io::{self, ErrorKind};
-use std::os::fd::{AsRawFd, RawFd};
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::Parser;
-use signal_hook::consts::*;
+use i3bar_river_core::{blocks_cache, button_manager, color, i3bar_protocol, pointer_btn, text};
 use wayrs_client::{Connection, IoMode};
 
 use event_loop::EventLoop;
@@ -31,21 +50,102 @@ use state::State;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-struct Cli {
+pub struct Cli {
     /// The path to a config file.
     #[arg(short, long, value_name = "FILE")]
-    config: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    /// Apply the overrides of the profile `[profile.NAME]` of the config.
+    #[arg(long, value_name = "NAME")]
+    pub config_profile: Option<String>,
+    /// Swaybar compatibility: read the configuration of this bar from sway.
+    #[arg(short, long, value_name = "BAR_ID")]
+    pub bar_id: Option<String>,
+    /// Replace the running instance of the bar.
+    #[arg(long)]
+    pub replace: bool,
+    /// Log level, optionally per module, e.g. "debug" or "info,i3bar_river::state=trace".
+    /// Defaults to $I3BAR_RIVER_LOG or "info".
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+    /// Write logs to a file instead of stderr.
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+    /// Record the output of the status command, with timestamps, to a file.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// Replay a recorded session instead of running the status command.
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+    /// Print the blocks received from the status command, without drawing the bar.
+    #[arg(long)]
+    pub print_blocks: bool,
+    /// Print a config translated from a waybar config (and its style.css), then exit.
+    #[arg(long, value_name = "FILE")]
+    pub import_waybar: Option<PathBuf>,
+    /// Log frame timing statistics every SECONDS (10 by default).
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "10")]
+    pub profile: Option<u64>,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
+    logger::init(args.log_level.as_deref(), args.log_file.as_deref())?;
+
+    if args.print_blocks {
+        return print_blocks::run(&args);
+    }
+    if let Some(path) = &args.import_waybar {
+        return import_waybar::run(path);
+    }
 
-    let [sig_read, sig_write] = pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
-    signal_hook::low_level::pipe::register(SIGUSR1, sig_write)?;
+    let ipc = ipc::IpcServer::bind(args.bar_id.as_deref(), args.replace)?;
+
+    // All the signals are handled in the event loop. They are blocked before any thread is
+    // spawned, e.g. by the status command, since the threads inherit the signal mask.
+    let config = config::Config::new(args.config.as_deref(), args.config_profile.as_deref());
+    let default_config;
+    let signals_config = match &config {
+        Ok(config) => config,
+        Err(_) => {
+            default_config = config::Config::default();
+            &default_config
+        }
+    };
+    let mut signal_errors = Vec::new();
+    let toggle_signal = signals_config.toggle_signal;
+    let mut peek_signal = signals_config.peek_signal;
+    if peek_signal == Some(toggle_signal) {
+        signal_errors.push("peek_signal is also used as toggle_signal");
+        peek_signal = None;
+    }
+    let mut forwarded = signals_config.forward_signals.clone();
+    if forwarded
+        .iter()
+        .any(|&signal| signal == toggle_signal || Some(signal) == peek_signal)
+    {
+        signal_errors.push("a forwarded signal is also used as toggle_signal or peek_signal");
+        forwarded.retain(|&signal| signal != toggle_signal && Some(signal) != peek_signal);
+    }
+    let mut handled: Vec<_> = forwarded.iter().map(|signal| signal.0).collect();
+    handled.extend([toggle_signal.0, libc::SIGHUP, libc::SIGTERM, libc::SIGCHLD]);
+    handled.extend(peek_signal.map(|signal| signal.0));
+    let signals = signals::Signals::new(&handled)?;
 
     let (mut conn, globals) = Connection::connect_and_collect_globals()?;
     let mut el = EventLoop::new();
-    let mut state = State::new(&mut conn, &globals, &mut el, args.config.as_deref());
+    let mut state = State::new(&mut conn, &globals, &mut el, &args, config);
+
+    // Get the names and scales of the outputs, which are passed to the status command
+    conn.blocking_roundtrip()?;
+    conn.dispatch_events(&mut state);
+    if state.fractional_scale_manager.is_some() {
+        // The fractional scale is sent once the bars are configured and have entered the outputs
+        for _ in 0..2 {
+            conn.blocking_roundtrip()?;
+            conn.dispatch_events(&mut state);
+        }
+    }
+    state.start_status_cmd(&mut conn, &args);
     conn.flush(IoMode::Blocking)?;
 
     el.add_on_idle(|ctx| {
@@ -53,19 +153,153 @@ fn main() -> anyhow::Result<()> {
         Ok(event_loop::Action::Keep)
     });
 
-    el.register_with_fd(sig_read, move |ctx| {
-        let mut buf = [0u8];
-        assert_eq!(
-            unsafe { libc::read(sig_read, buf.as_mut_ptr().cast(), 1) },
-            1
-        );
-        ctx.state.toggle_visibility(ctx.conn);
+    for error in signal_errors {
+        state.set_error(&mut conn, "config", error);
+    }
+    el.register_with_fd(signals.fd(), move |ctx| {
+        for signal in signals.read()? {
+            if signal == toggle_signal.0 {
+                ctx.state.toggle_visibility(ctx.conn);
+            } else if peek_signal.is_some_and(|s| s.0 == signal) {
+                let duration = ctx.state.shared_state.config.peek_duration.0;
+                ctx.state.peek(ctx.conn, ctx.handle, duration);
+            } else if forwarded.iter().any(|s| s.0 == signal) {
+                if let Some(cmd) = &ctx.state.shared_state.status_cmd {
+                    let _ = cmd.send_signal(signal);
+                }
+            } else if signal == libc::SIGHUP {
+                // The error is shown on the bar
+                let _ = ctx.state.reload_config(ctx.conn);
+            } else if signal == libc::SIGCHLD {
+                utils::reap_children();
+            } else if signal == libc::SIGTERM {
+                info!("terminated");
+                ctx.state.quit();
+            }
+        }
         Ok(event_loop::Action::Keep)
     });
 
+    if state.shared_state.config.watch_config {
+        let path = args.config.clone().or_else(config::config_path);
+        match path.map(|path| config_watcher::ConfigWatcher::new(&path)) {
+            Some(Ok(watcher)) => {
+                el.register_with_fd(watcher.fd(), move |ctx| {
+                    if watcher.read()? {
+                        // The error is shown on the bar
+                        let _ = ctx.state.reload_config(ctx.conn);
+                    }
+                    Ok(event_loop::Action::Keep)
+                });
+            }
+            Some(Err(e)) => state.set_error(&mut conn, "watch_config", e),
+            None => warn!("there is no config file to watch"),
+        }
+    }
+
+    if let Some(profiler) = &state.shared_state.profiler {
+        el.add_timer(profiler.interval, |ctx| {
+            if let Some(profiler) = &mut ctx.state.shared_state.profiler {
+                profiler.report();
+            }
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if state.shared_state.config.theme.schedule.is_some() {
+        state.update_theme(&mut conn);
+        el.add_timer(Duration::from_secs(30), |ctx| {
+            ctx.state.update_theme(ctx.conn);
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if let Some(timeout) = state.shared_state.config.stale_timeout {
+        el.add_timer(timeout.0.min(Duration::from_secs(1)), |ctx| {
+            ctx.state.check_stale(ctx.conn);
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if let Some(timeout) = state.shared_state.config.auto_hide {
+        el.add_timer(timeout.0.min(Duration::from_millis(250)), |ctx| {
+            ctx.state.check_auto_hide(ctx.conn);
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if state.shared_state.blocks_persist.is_some() {
+        el.add_timer(persist::SAVE_INTERVAL, |ctx| {
+            ctx.state.flush_blocks();
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    for (i, interval) in state
+        .shared_state
+        .plugins
+        .intervals()
+        .into_iter()
+        .enumerate()
+    {
+        state.update_plugin(&mut conn, i);
+        el.add_timer(interval, move |ctx| {
+            ctx.state.update_plugin(ctx.conn, i);
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if state.shared_state.sysinfo.is_some() {
+        state.update_sysinfo(&mut conn);
+        let interval = state.shared_state.config.builtin.sysinfo.interval.0;
+        el.add_timer(interval, |ctx| {
+            ctx.state.update_sysinfo(ctx.conn);
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if let Some(ipc) = ipc {
+        ipc.register(&mut el);
+    }
+
+    if let Some(server) = &state.shared_state.blocks_server {
+        el.register_with_fd(server.fd(), |ctx| {
+            let server = ctx.state.shared_state.blocks_server.as_mut().unwrap();
+            if let Err(e) = server.accept_clients(ctx.handle) {
+                let server = ctx.state.shared_state.blocks_server.take().unwrap();
+                server.close(ctx.handle);
+                ctx.state.update_blocks(ctx.conn);
+                ctx.state.set_error(ctx.conn, "listen", e);
+                return Ok(event_loop::Action::Unregister);
+            }
+            Ok(event_loop::Action::Keep)
+        });
+    }
+
+    if let Some(tray) = &state.shared_state.tray {
+        el.register_with_fd(tray.fd(), |ctx| {
+            match ctx.state.shared_state.tray.as_mut().unwrap().dispatch() {
+                Ok(false) => Ok(event_loop::Action::Keep),
+                Ok(true) => {
+                    ctx.state.draw_all(ctx.conn);
+                    Ok(event_loop::Action::Keep)
+                }
+                Err(e) => {
+                    ctx.state.shared_state.tray = None;
+                    ctx.state.draw_all(ctx.conn);
+                    ctx.state.set_error(ctx.conn, "tray", e);
+                    Ok(event_loop::Action::Unregister)
+                }
+            }
+        });
+    }
+
     el.register_with_fd(conn.as_raw_fd(), |ctx| {
         match ctx.conn.recv_events(IoMode::NonBlocking) {
-            Ok(()) => ctx.conn.dispatch_events(ctx.state),
+            Ok(()) => {
+                ctx.conn.dispatch_events(ctx.state);
+                ctx.state.schedule_tooltip(ctx.handle);
+            }
             Err(e) if e.kind() == ErrorKind::WouldBlock => (),
             Err(e) => bail!(e),
         }
@@ -84,18 +318,14 @@ fn main() -> anyhow::Result<()> {
             {
                 Ok(None) => Ok(event_loop::Action::Keep),
                 Ok(Some(blocks)) => {
-                    ctx.state.set_blocks(ctx.conn, blocks);
+                    if let Some(persist) = &mut ctx.state.shared_state.blocks_persist {
+                        persist.update(&blocks);
+                    }
+                    ctx.state.set_status_blocks(ctx.conn, blocks);
                     Ok(event_loop::Action::Keep)
                 }
                 Err(e) => {
-                    let _ = ctx
-                        .state
-                        .shared_state
-                        .status_cmd
-                        .take()
-                        .unwrap()
-                        .child
-                        .kill();
+                    ctx.state.shared_state.status_cmd.take().unwrap().kill();
                     ctx.state.set_error(ctx.conn, "status", e);
                     Ok(event_loop::Action::Unregister)
                 }
@@ -106,12 +336,3 @@ fn main() -> anyhow::Result<()> {
     el.run(&mut conn, &mut state)?;
     unreachable!();
 }
-
-fn pipe(flags: libc::c_int) -> io::Result<[RawFd; 2]> {
-    let mut fds = [0; 2];
-    if unsafe { libc::pipe2(fds.as_mut_ptr(), flags) } == -1 {
-        Err(io::Error::last_os_error())
-    } else {
-        Ok(fds)
-    }
-}
@@ -4,11 +4,12 @@ use wayrs_client::{protocol::*, EventCtx};
 
 use crate::state::State;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Output {
     pub wl: WlOutput,
     pub reg_name: u32,
     pub scale: u32,
+    pub geometry: Geometry,
     pub name: String,
 }
 
@@ -16,6 +17,31 @@ pub struct PendingOutput {
     pub wl: WlOutput,
     pub reg_name: u32,
     pub scale: u32,
+    pub geometry: Geometry,
+}
+
+/// The position of an output in the compositor space and the size of its current mode.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    /// In physical pixels, before the transform.
+    pub mode_width: i32,
+    pub mode_height: i32,
+    /// Whether the output is rotated by 90 or 270 degrees.
+    pub rotated: bool,
+}
+
+impl Geometry {
+    /// The height of the output in logical pixels.
+    pub fn logical_height(&self, scale: f64) -> f64 {
+        let height = if self.rotated {
+            self.mode_width
+        } else {
+            self.mode_height
+        };
+        height as f64 / scale
+    }
 }
 
 impl PendingOutput {
@@ -26,6 +52,7 @@ impl PendingOutput {
                 .expect("could not bind wl_output"),
             reg_name: global.name,
             scale: 1,
+            geometry: Geometry::default(),
         }
     }
 }
@@ -51,27 +78,45 @@ fn wl_output_cb(ctx: EventCtx<State, WlOutput>) {
                 wl: output.wl,
                 reg_name: output.reg_name,
                 scale: output.scale,
+                geometry: output.geometry,
                 name,
             };
             ctx.state.register_output(ctx.conn, output);
         }
         wl_output::Event::Scale(scale) => {
-            if let Some(bar) = ctx
-                .state
-                .bars
-                .iter_mut()
-                .find(|bar| bar.output.wl == ctx.proxy)
-            {
-                bar.output.scale = scale as u32;
-            } else if let Some(output) = ctx
-                .state
-                .pending_outputs
-                .iter_mut()
-                .find(|o| o.wl == ctx.proxy)
-            {
-                output.scale = scale as u32;
+            if let Some((output_scale, _)) = output_info(ctx.state, ctx.proxy) {
+                *output_scale = scale as u32;
+            }
+        }
+        wl_output::Event::Geometry(args) => {
+            if let Some((_, geometry)) = output_info(ctx.state, ctx.proxy) {
+                geometry.x = args.x;
+                geometry.y = args.y;
+                // 90, 270, flipped-90 and flipped-270 are odd
+                geometry.rotated = u32::from(args.transform) % 2 == 1;
+            }
+        }
+        wl_output::Event::Mode(args) if args.flags.contains(wl_output::Mode::Current) => {
+            if let Some((_, geometry)) = output_info(ctx.state, ctx.proxy) {
+                geometry.mode_width = args.width;
+                geometry.mode_height = args.height;
             }
         }
         _ => (),
     }
 }
+
+/// The scale and the geometry of `wl`, whether it has a bar or not.
+fn output_info(state: &mut State, wl: WlOutput) -> Option<(&mut u32, &mut Geometry)> {
+    if let Some(bar) = state.bars.iter_mut().find(|bar| bar.output.wl == wl) {
+        return Some((&mut bar.output.scale, &mut bar.output.geometry));
+    }
+    if let Some(output) = state.disabled_outputs.iter_mut().find(|o| o.wl == wl) {
+        return Some((&mut output.scale, &mut output.geometry));
+    }
+    state
+        .pending_outputs
+        .iter_mut()
+        .find(|o| o.wl == wl)
+        .map(|o| (&mut o.scale, &mut o.geometry))
+}
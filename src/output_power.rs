@@ -0,0 +1,79 @@
+//! Tracking which outputs are powered off, using wlr-output-power-management-unstable-v1.
+
+use wayrs_client::global::{Globals, GlobalsExt};
+use wayrs_client::{Connection, EventCtx};
+
+use crate::protocol::*;
+use crate::state::State;
+
+pub struct OutputPower {
+    manager: ZwlrOutputPowerManagerV1,
+    powers: Vec<Power>,
+}
+
+struct Power {
+    output: WlOutput,
+    power: ZwlrOutputPowerV1,
+    on: bool,
+}
+
+impl OutputPower {
+    pub fn bind(conn: &mut Connection<State>, globals: &Globals) -> Option<Self> {
+        Some(Self {
+            manager: globals.bind(conn, 1).ok()?,
+            powers: Vec::new(),
+        })
+    }
+
+    pub fn output_added(&mut self, conn: &mut Connection<State>, output: WlOutput) {
+        let power = self
+            .manager
+            .get_output_power_with_cb(conn, output, power_cb);
+        self.powers.push(Power {
+            output,
+            power,
+            on: true,
+        });
+    }
+
+    pub fn output_removed(&mut self, conn: &mut Connection<State>, output: WlOutput) {
+        self.powers.retain(|p| {
+            if p.output == output {
+                p.power.destroy(conn);
+            }
+            p.output != output
+        });
+    }
+
+    /// Whether `output` is powered on, or its power mode is unknown.
+    pub fn is_on(&self, output: WlOutput) -> bool {
+        self.powers
+            .iter()
+            .find(|p| p.output == output)
+            .is_none_or(|p| p.on)
+    }
+}
+
+fn power_cb(ctx: EventCtx<State, ZwlrOutputPowerV1>) {
+    let Some(output_power) = &mut ctx.state.output_power else {
+        return;
+    };
+    let Some(i) = output_power
+        .powers
+        .iter()
+        .position(|p| p.power == ctx.proxy)
+    else {
+        return;
+    };
+    let output = output_power.powers[i].output;
+    match ctx.event {
+        zwlr_output_power_v1::Event::Mode(mode) => {
+            output_power.powers[i].on = matches!(mode, zwlr_output_power_v1::Mode::On);
+        }
+        zwlr_output_power_v1::Event::Failed => {
+            // The output is gone, or another client controls its power
+            output_power.powers.swap_remove(i).power.destroy(ctx.conn);
+        }
+    }
+    ctx.state.output_power_changed(ctx.conn, output);
+}
@@ -0,0 +1,75 @@
+//! Persisting the last received blocks across restarts.
+//!
+//! The blocks are saved to `$XDG_RUNTIME_DIR/i3bar-river/<output>.json` for every output with a
+//! bar, and loaded from the file of the first output that appears.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::i3bar_protocol::Block;
+
+/// Do not write the blocks to the disk more often than this.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct BlocksPersist {
+    dir: PathBuf,
+    /// The blocks that have not been saved yet.
+    pending: Option<Vec<Block>>,
+    /// Set once blocks have been loaded or received, after which nothing is loaded anymore.
+    has_blocks: bool,
+}
+
+impl BlocksPersist {
+    /// Returns `None` if `$XDG_RUNTIME_DIR` is not set.
+    pub fn new() -> Option<Self> {
+        let mut dir = PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?);
+        dir.push("i3bar-river");
+        Some(Self {
+            dir,
+            pending: None,
+            has_blocks: false,
+        })
+    }
+
+    fn path(&self, output: &str) -> PathBuf {
+        self.dir.join(format!("{output}.json"))
+    }
+
+    /// The blocks saved for `output`, unless blocks have already been loaded or received.
+    pub fn load(&mut self, output: &str) -> Option<Vec<Block>> {
+        if self.has_blocks {
+            return None;
+        }
+        let buf = fs::read(self.path(output)).ok()?;
+        let blocks = serde_json::from_slice(&buf).ok()?;
+        self.has_blocks = true;
+        Some(blocks)
+    }
+
+    /// Remember the blocks, to be saved with the next [`BlocksPersist::flush`].
+    pub fn update(&mut self, blocks: &[Block]) {
+        self.pending = Some(blocks.to_vec());
+        self.has_blocks = true;
+    }
+
+    /// Save the blocks for every output in `outputs`, if they have changed since the last call.
+    /// Called every [`SAVE_INTERVAL`] and on exit.
+    pub fn flush<'a>(&mut self, outputs: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+        let Some(blocks) = self.pending.take() else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_vec(&blocks)?;
+        for output in outputs {
+            // Write to a temporary file first, so that a crash never leaves a half-written file
+            let path = self.path(output);
+            let tmp_path = path.with_extension("json.tmp");
+            fs::write(&tmp_path, &json)?;
+            fs::rename(tmp_path, path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! WASM plugins, which provide blocks without spawning a process.
+//!
+//! A plugin is a WASM module without imports, which exports:
+//!
+//! - `memory`;
+//! - `i3bar_alloc(len: i32) -> i32`, which allocates `len` bytes;
+//! - `i3bar_update(dt_ms: i64) -> i64`, called every `interval`, which returns a pointer to a JSON
+//!   array of blocks in the upper 32 bits and its length in the lower 32 bits, or `0` if the
+//!   blocks did not change;
+//! - optionally, `i3bar_init()`, called once after the module is loaded;
+//! - optionally, `i3bar_on_click(ptr: i32, len: i32)`, which receives a JSON click event, like
+//!   the ones sent to the status command.
+//!
+//! Block names are prefixed with `<plugin name>:` to route clicks, the prefix is stripped from the
+//! click events.
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::config::PluginConfig;
+use crate::i3bar_protocol::{Block, Event};
+
+#[cfg(feature = "wasm-plugins")]
+pub use imp::Plugins;
+
+#[cfg(not(feature = "wasm-plugins"))]
+#[derive(Default)]
+pub struct Plugins;
+
+#[cfg(not(feature = "wasm-plugins"))]
+impl Plugins {
+    pub fn load(configs: &[PluginConfig]) -> Result<Self> {
+        ensure!(
+            configs.is_empty(),
+            "plugins are configured, but i3bar-river was built without the wasm-plugins feature"
+        );
+        Ok(Self)
+    }
+
+    pub fn intervals(&self) -> Vec<Duration> {
+        Vec::new()
+    }
+
+    pub fn update(&mut self, _index: usize) -> Result<bool> {
+        Ok(false)
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        std::iter::empty()
+    }
+
+    pub fn click(&mut self, _event: &Event) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(feature = "wasm-plugins")]
+mod imp {
+    use std::time::Instant;
+
+    use anyhow::Context;
+    use wasmtime::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+    use super::*;
+
+    /// The amount of fuel available to each call, so that a buggy plugin cannot hang the bar.
+    const FUEL: u64 = 100_000_000;
+
+    #[derive(Default)]
+    pub struct Plugins {
+        plugins: Vec<Plugin>,
+    }
+
+    struct Plugin {
+        name: String,
+        interval: Duration,
+        store: Store<()>,
+        memory: Memory,
+        alloc: TypedFunc<u32, u32>,
+        update: TypedFunc<u64, u64>,
+        on_click: Option<TypedFunc<(u32, u32), ()>>,
+        last_update: Instant,
+        blocks: Vec<Block>,
+    }
+
+    impl Plugins {
+        pub fn load(configs: &[PluginConfig]) -> Result<Self> {
+            if configs.is_empty() {
+                return Ok(Self::default());
+            }
+            let mut config = Config::new();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config)?;
+            let plugins = configs
+                .iter()
+                .map(|config| {
+                    Plugin::load(&engine, config)
+                        .with_context(|| format!("failed to load {}", config.path.display()))
+                })
+                .collect::<Result<_>>()?;
+            Ok(Self { plugins })
+        }
+
+        pub fn intervals(&self) -> Vec<Duration> {
+            self.plugins.iter().map(|p| p.interval).collect()
+        }
+
+        /// Returns `true` if the blocks of the plugin have changed.
+        pub fn update(&mut self, index: usize) -> Result<bool> {
+            let plugin = &mut self.plugins[index];
+            plugin
+                .update()
+                .with_context(|| format!("plugin {}", plugin.name))
+        }
+
+        pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+            self.plugins.iter().flat_map(|p| &p.blocks)
+        }
+
+        /// Returns `false` if the block does not belong to a plugin.
+        pub fn click(&mut self, event: &Event) -> Result<bool> {
+            let Some((plugin_name, name)) = event.name.and_then(|n| n.split_once(':')) else {
+                return Ok(false);
+            };
+            let Some(plugin) = self.plugins.iter_mut().find(|p| p.name == plugin_name) else {
+                return Ok(false);
+            };
+            let event = Event {
+                name: Some(name),
+                instance: event.instance,
+                button: event.button,
+                x: event.x,
+                y: event.y,
+                relative_x: event.relative_x,
+                relative_y: event.relative_y,
+                output_x: event.output_x,
+                output_y: event.output_y,
+                width: event.width,
+                height: event.height,
+                ..Default::default()
+            };
+            plugin
+                .click(&event)
+                .with_context(|| format!("plugin {}", plugin.name))?;
+            Ok(true)
+        }
+    }
+
+    impl Plugin {
+        fn load(engine: &Engine, config: &PluginConfig) -> Result<Self> {
+            let module = Module::from_file(engine, &config.path)?;
+            let mut store = Store::new(engine, ());
+            store.set_fuel(FUEL)?;
+            // No imports: plugins can only compute blocks
+            let instance = Linker::new(engine).instantiate(&mut store, &module)?;
+
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .context("'memory' is not exported")?;
+            let alloc = instance.get_typed_func(&mut store, "i3bar_alloc")?;
+            let update = instance.get_typed_func(&mut store, "i3bar_update")?;
+            let on_click = instance.get_typed_func(&mut store, "i3bar_on_click").ok();
+            if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "i3bar_init") {
+                init.call(&mut store, ())?;
+            }
+
+            let name = match &config.name {
+                Some(name) => name.clone(),
+                None => config
+                    .path
+                    .file_stem()
+                    .context("invalid path")?
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+
+            debug!("loaded plugin {name}");
+
+            Ok(Self {
+                name,
+                interval: config.interval.0,
+                store,
+                memory,
+                alloc,
+                update,
+                on_click,
+                last_update: Instant::now(),
+                blocks: Vec::new(),
+            })
+        }
+
+        fn update(&mut self) -> Result<bool> {
+            let dt = self.last_update.elapsed().as_millis() as u64;
+            self.last_update = Instant::now();
+
+            self.store.set_fuel(FUEL)?;
+            let packed = self.update.call(&mut self.store, dt)?;
+            if packed == 0 {
+                return Ok(false);
+            }
+
+            let ptr = (packed >> 32) as usize;
+            let len = (packed & 0xffff_ffff) as usize;
+            let json = self
+                .memory
+                .data(&self.store)
+                .get(ptr..ptr + len)
+                .context("blocks are out of bounds")?;
+            let mut blocks: Vec<Block> = serde_json::from_slice(json)?;
+            for block in &mut blocks {
+                let name = block.name.as_deref().unwrap_or_default();
+                block.name = Some(format!("{}:{name}", self.name).into());
+            }
+
+            self.blocks = blocks;
+            Ok(true)
+        }
+
+        fn click(&mut self, event: &Event) -> Result<()> {
+            let Some(on_click) = &self.on_click else {
+                return Ok(());
+            };
+            let json = serde_json::to_vec(event)?;
+
+            self.store.set_fuel(FUEL)?;
+            let ptr = self.alloc.call(&mut self.store, json.len() as u32)?;
+            self.memory.write(&mut self.store, ptr as usize, &json)?;
+            on_click.call(&mut self.store, (ptr, json.len() as u32))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "wasm-plugins"))]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::Timeout;
+
+    /// Load a plugin named `name`, whose `i3bar_on_click` runs the instructions `on_click`.
+    fn load(name: &str, on_click: &str) -> Plugins {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "i3bar_alloc") (param i32) (result i32) i32.const 0)
+                (func (export "i3bar_update") (param i64) (result i64) i64.const 0)
+                (func (export "i3bar_on_click") (param i32 i32) {on_click}))"#
+        );
+        let path = std::env::temp_dir().join(format!(
+            "i3bar-river-test-{name}-{}.wat",
+            std::process::id()
+        ));
+        fs::write(&path, wat).unwrap();
+        let plugins = Plugins::load(&[PluginConfig {
+            path: path.clone(),
+            name: Some(name.into()),
+            interval: Timeout(Duration::from_secs(1)),
+        }]);
+        fs::remove_file(&path).unwrap();
+        plugins.unwrap()
+    }
+
+    #[test]
+    fn failing_clicks_are_errors() {
+        for (name, on_click) in [("trap", "unreachable"), ("out-of-fuel", "(loop (br 0))")] {
+            let mut plugins = load(name, on_click);
+            let block = format!("{name}:block");
+            let event = Event {
+                name: Some(&block),
+                ..Default::default()
+            };
+            assert!(plugins.click(&event).is_err(), "{name}");
+            // The plugin can still be called afterwards
+            assert!(plugins.click(&event).is_err(), "{name}");
+            assert!(!plugins.click(&Event::default()).unwrap(), "{name}");
+        }
+    }
+
+    #[test]
+    fn successful_click() {
+        let mut plugins = load("nop", "nop");
+        let event = Event {
+            name: Some("nop:block"),
+            ..Default::default()
+        };
+        assert!(plugins.click(&event).unwrap());
+    }
+}
@@ -0,0 +1,86 @@
+//! `--print-blocks`: run the status command and print the parsed blocks instead of drawing them.
+
+use std::io::{self, ErrorKind, Write};
+
+use anyhow::Result;
+use pangocairo::pango;
+
+use crate::blocks_cache::{BlocksCache, ComputedBlock};
+use crate::config::Config;
+use crate::i3bar_protocol::MinWidth;
+use crate::status_cmd::StatusCmd;
+use crate::Cli;
+
+pub fn run(args: &Cli) -> Result<()> {
+    let config = Config::new(args.config.as_deref(), args.config_profile.as_deref())?;
+    let mut cmd = match (&args.replay, &config.command) {
+        (Some(path), _) => StatusCmd::replay(path, &config)?,
+        (None, Some(cmd)) => StatusCmd::new(cmd, &config, &[])?,
+        (None, None) => bail!("no status command is configured"),
+    };
+
+    let mut cache = BlocksCache::default();
+    let mut updates = 0;
+
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd: cmd.fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, -1) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err.into());
+        }
+
+        if let Some(blocks) = cmd.receive_blocks()? {
+            updates += 1;
+            cache.process_new_blocks(config.blocks_font(), blocks);
+            print_blocks(updates, cache.get_computed())?;
+        }
+    }
+}
+
+fn print_blocks(update: usize, blocks: &[ComputedBlock]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    writeln!(out, "update #{update}: {} block(s)", blocks.len())?;
+    for (i, computed) in blocks.iter().enumerate() {
+        let block = &computed.block;
+        writeln!(
+            out,
+            "  [{i}] name={:?} instance={:?} markup={:?} align={:?}",
+            block.name, block.instance, block.markup, block.align
+        )?;
+        writeln!(
+            out,
+            "      full_text {:?}: {}px",
+            block.full_text, computed.full.width
+        )?;
+        if let (Some(text), Some(short)) = (&block.short_text, &computed.short) {
+            writeln!(out, "      short_text {text:?}: {}px", short.width)?;
+        }
+        if let (Some(min_width), Some(px)) = (&block.min_width, computed.min_width) {
+            match min_width {
+                MinWidth::Pixels(_) => writeln!(out, "      min_width: {px}px")?,
+                MinWidth::Text(text) => writeln!(out, "      min_width {text:?}: {px}px")?,
+            }
+        }
+        if block.markup.as_deref() == Some("pango") {
+            let texts = std::iter::once(&block.full_text).chain(&block.short_text);
+            for text in texts {
+                if let Err(e) = pango::parse_markup(text, '\0') {
+                    writeln!(out, "      invalid markup in {text:?}: {e}")?;
+                }
+            }
+        } else if let Some(markup) = block.markup.as_deref().filter(|m| *m != "none") {
+            writeln!(
+                out,
+                "      unknown markup {markup:?}, treated as plain text"
+            )?;
+        }
+    }
+    out.flush()
+}
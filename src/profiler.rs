@@ -0,0 +1,70 @@
+//! `--profile`: frame timing statistics, logged periodically.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Time spent on the stages of a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTimes {
+    /// Laying out the text.
+    pub layout: Duration,
+    /// Allocating the buffer and drawing with cairo.
+    pub paint: Duration,
+    /// Sending the requests to the compositor.
+    pub commit: Duration,
+}
+
+impl FrameTimes {
+    fn total(&self) -> Duration {
+        self.layout + self.paint + self.commit
+    }
+}
+
+pub struct Profiler {
+    pub interval: Duration,
+    frames: HashMap<String, Vec<FrameTimes>>,
+}
+
+impl Profiler {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            frames: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, output: &str, times: FrameTimes) {
+        self.frames
+            .entry(output.to_owned())
+            .or_default()
+            .push(times);
+    }
+
+    /// Log the statistics since the last report.
+    pub fn report(&mut self) {
+        if self.frames.is_empty() {
+            info!("no frames in the last {:?}", self.interval);
+            return;
+        }
+        let mut outputs: Vec<_> = self.frames.drain().collect();
+        outputs.sort_by(|a, b| a.0.cmp(&b.0));
+        for (output, frames) in outputs {
+            info!(
+                "{output}: {} frames, layout {}, paint {}, commit {}, total {}",
+                frames.len(),
+                stats(&frames, |f| f.layout),
+                stats(&frames, |f| f.paint),
+                stats(&frames, |f| f.commit),
+                stats(&frames, FrameTimes::total),
+            );
+        }
+    }
+}
+
+/// Format p50 and p95 of a stage.
+fn stats(frames: &[FrameTimes], stage: impl Fn(&FrameTimes) -> Duration) -> String {
+    let mut times: Vec<_> = frames.iter().map(stage).collect();
+    times.sort_unstable();
+    let percentile = |p: usize| times[(times.len() - 1) * p / 100].as_secs_f64() * 1000.0;
+    format!("p50 {:.2}ms p95 {:.2}ms", percentile(50), percentile(95))
+}
@@ -1,6 +1,13 @@
 pub use wayrs_client::protocol::*;
+pub use wayrs_protocols::ext_idle_notify_v1::*;
 pub use wayrs_protocols::fractional_scale_v1::*;
+pub use wayrs_protocols::pointer_gestures_unstable_v1::*;
 pub use wayrs_protocols::viewporter::*;
+pub use wayrs_protocols::wlr_data_control_unstable_v1::*;
 pub use wayrs_protocols::wlr_layer_shell_unstable_v1::*;
+pub use wayrs_protocols::wlr_output_power_management_unstable_v1::*;
+pub use wayrs_protocols::xdg_shell::*;
 wayrs_client::generate!("protocols/river-status-unstable-v1.xml");
 wayrs_client::generate!("protocols/river-control-unstable-v1.xml");
+wayrs_client::generate!("protocols/kde-blur.xml");
+wayrs_client::generate!("protocols/ext-workspace-v1.xml");
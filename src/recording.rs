@@ -0,0 +1,107 @@
+//! Recording and replaying the output of the status command.
+//!
+//! A session is a JSON lines file, each line being a chunk of bytes read from the status command
+//! along with the time (in milliseconds) since the recording has started.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Chunk {
+    t: u64,
+    /// The chunk, if it is valid UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    /// The raw bytes otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes: Option<Vec<u8>>,
+}
+
+impl Chunk {
+    fn into_bytes(self) -> Vec<u8> {
+        match (self.data, self.bytes) {
+            (Some(data), _) => data.into_bytes(),
+            (None, Some(bytes)) => bytes,
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create '{}'", path.display()))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let t = self.start.elapsed().as_millis() as u64;
+        let chunk = match std::str::from_utf8(bytes) {
+            Ok(data) => Chunk {
+                t,
+                data: Some(data.to_owned()),
+                bytes: None,
+            },
+            Err(_) => Chunk {
+                t,
+                data: None,
+                bytes: Some(bytes.to_vec()),
+            },
+        };
+        serde_json::to_writer(&mut self.file, &chunk)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+/// Start replaying a recorded session. Returns the read end of a pipe, which receives the chunks
+/// at the original pace.
+pub fn replay(path: &Path) -> Result<OwnedFd> {
+    let file = File::open(path).with_context(|| format!("failed to open '{}'", path.display()))?;
+    let mut chunks = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let chunk: Chunk = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}: invalid chunk", path.display(), i + 1))?;
+        chunks.push(chunk);
+    }
+
+    let [read, write] = crate::utils::pipe(libc::O_CLOEXEC)?;
+
+    std::thread::spawn(move || {
+        let mut write = File::from(write);
+        let start = Instant::now();
+        for chunk in chunks {
+            let at = Duration::from_millis(chunk.t);
+            if let Some(delay) = at.checked_sub(start.elapsed()) {
+                std::thread::sleep(delay);
+            }
+            if write.write_all(&chunk.into_bytes()).is_err() {
+                return;
+            }
+        }
+        debug!("replay finished");
+        // Keep the pipe open, so that the last blocks stay on the bar
+        std::mem::forget(write);
+    });
+
+    Ok(read)
+}
@@ -0,0 +1,219 @@
+//! Rhai scripts bound to clicks and scrolls, configured with `on_click` and `on_scroll`.
+//!
+//! Scripts do not act immediately: the functions available to them only queue [`ScriptAction`]s,
+//! which are performed by the bar after the script has finished. The following variables are
+//! available:
+//!
+//! - `btn`: `"left"`, `"middle"`, `"right"`, `"up"`, `"down"`, `"forward"` or `"back"`;
+//! - for tags: `tag`, the id of the tag (`()` between the tags) and `output`, its name;
+//! - for blocks: `name` and `instance` of the block (`()` if not set).
+//!
+//! And the functions:
+//!
+//! - `river(args...)`: run a river command, with up to five arguments;
+//! - `shell(cmd)`: run a shell command;
+//! - `run_default()`: also perform the default action, which is skipped otherwise.
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::pointer_btn::PointerBtn;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub enum ScriptAction {
+    River(Vec<String>),
+    Shell(String),
+    Default,
+}
+
+/// What was clicked.
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub enum Target<'a> {
+    Tag {
+        id: Option<u32>,
+        output: &'a str,
+    },
+    Block {
+        name: Option<&'a str>,
+        instance: Option<&'a str>,
+    },
+}
+
+#[cfg(feature = "scripting")]
+pub use imp::Scripts;
+
+#[cfg(not(feature = "scripting"))]
+#[derive(Default)]
+pub struct Scripts;
+
+#[cfg(not(feature = "scripting"))]
+impl Scripts {
+    pub fn new(config: &Config) -> Result<Self> {
+        ensure!(
+            [&config.on_click, &config.on_scroll]
+                .iter()
+                .all(|s| s.tag.is_none() && s.blocks.is_none()),
+            "scripts are configured, but i3bar-river was built without the scripting feature"
+        );
+        Ok(Self)
+    }
+
+    /// Returns `None` if there is no script for this event.
+    pub fn run(&self, _target: Target, _btn: PointerBtn) -> Result<Option<Vec<ScriptAction>>> {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "scripting")]
+mod imp {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use rhai::{Dynamic, Engine, Scope, AST};
+
+    use super::*;
+    use crate::config::EventScripts;
+
+    /// Scripts are expected to be tiny, stop the runaway ones.
+    const MAX_OPERATIONS: u64 = 100_000;
+
+    #[derive(Default)]
+    struct Compiled {
+        tag: Option<AST>,
+        blocks: Option<AST>,
+    }
+
+    pub struct Scripts {
+        engine: Engine,
+        actions: Rc<RefCell<Vec<ScriptAction>>>,
+        on_click: Compiled,
+        on_scroll: Compiled,
+    }
+
+    impl Default for Scripts {
+        fn default() -> Self {
+            Self {
+                engine: Engine::new_raw(),
+                actions: Default::default(),
+                on_click: Compiled::default(),
+                on_scroll: Compiled::default(),
+            }
+        }
+    }
+
+    impl Scripts {
+        pub fn new(config: &Config) -> Result<Self> {
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+
+            let actions: Rc<RefCell<Vec<ScriptAction>>> = Default::default();
+            let river = |actions: &Rc<RefCell<Vec<ScriptAction>>>, args: &[&str]| {
+                let args = args.iter().map(|a| a.to_string()).collect();
+                actions.borrow_mut().push(ScriptAction::River(args));
+            };
+            let a = actions.clone();
+            engine.register_fn("river", move |a1: &str| river(&a, &[a1]));
+            let a = actions.clone();
+            engine.register_fn("river", move |a1: &str, a2: &str| river(&a, &[a1, a2]));
+            let a = actions.clone();
+            engine.register_fn("river", move |a1: &str, a2: &str, a3: &str| {
+                river(&a, &[a1, a2, a3])
+            });
+            let a = actions.clone();
+            engine.register_fn("river", move |a1: &str, a2: &str, a3: &str, a4: &str| {
+                river(&a, &[a1, a2, a3, a4])
+            });
+            let a = actions.clone();
+            engine.register_fn(
+                "river",
+                move |a1: &str, a2: &str, a3: &str, a4: &str, a5: &str| {
+                    river(&a, &[a1, a2, a3, a4, a5])
+                },
+            );
+            let a = actions.clone();
+            engine.register_fn("shell", move |cmd: &str| {
+                a.borrow_mut().push(ScriptAction::Shell(cmd.to_owned()))
+            });
+            let a = actions.clone();
+            engine.register_fn("run_default", move || {
+                a.borrow_mut().push(ScriptAction::Default)
+            });
+
+            let on_click = compile(&engine, &config.on_click, "on_click")?;
+            let on_scroll = compile(&engine, &config.on_scroll, "on_scroll")?;
+
+            Ok(Self {
+                engine,
+                actions,
+                on_click,
+                on_scroll,
+            })
+        }
+
+        /// Returns `None` if there is no script for this event.
+        pub fn run(&self, target: Target, btn: PointerBtn) -> Result<Option<Vec<ScriptAction>>> {
+            let scripts = if is_scroll(btn) {
+                &self.on_scroll
+            } else {
+                &self.on_click
+            };
+
+            let mut scope = Scope::new();
+            scope.push_constant("btn", btn_name(btn));
+            let ast = match target {
+                Target::Tag { id, output } => {
+                    scope.push_constant("tag", id.map_or(Dynamic::UNIT, |id| (id as i64).into()));
+                    scope.push_constant("output", output.to_owned());
+                    &scripts.tag
+                }
+                Target::Block { name, instance } => {
+                    let opt = |s: Option<&str>| s.map_or(Dynamic::UNIT, |s| s.to_owned().into());
+                    scope.push_constant("name", opt(name));
+                    scope.push_constant("instance", opt(instance));
+                    &scripts.blocks
+                }
+            };
+            let Some(ast) = ast else {
+                return Ok(None);
+            };
+
+            self.actions.borrow_mut().clear();
+            self.engine
+                .run_ast_with_scope(&mut scope, ast)
+                .map_err(|e| anyhow!("{e}"))?;
+            Ok(Some(self.actions.take()))
+        }
+    }
+
+    fn btn_name(btn: PointerBtn) -> &'static str {
+        match btn {
+            PointerBtn::Left => "left",
+            PointerBtn::Middle => "middle",
+            PointerBtn::Right => "right",
+            PointerBtn::WheelUp => "up",
+            PointerBtn::WheelDown => "down",
+            PointerBtn::Forward => "forward",
+            PointerBtn::Back => "back",
+            PointerBtn::Unknown => "unknown",
+        }
+    }
+
+    fn is_scroll(btn: PointerBtn) -> bool {
+        matches!(btn, PointerBtn::WheelUp | PointerBtn::WheelDown)
+    }
+
+    fn compile(engine: &Engine, scripts: &EventScripts, context: &str) -> Result<Compiled> {
+        let compile = |script: &Option<String>, target: &str| {
+            script
+                .as_deref()
+                .map(|s| engine.compile(s))
+                .transpose()
+                .map_err(|e| anyhow!("{context}.{target}: {e}"))
+        };
+        Ok(Compiled {
+            tag: compile(&scripts.tag, "tag")?,
+            blocks: compile(&scripts.blocks, "blocks")?,
+        })
+    }
+}
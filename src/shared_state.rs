@@ -1,18 +1,44 @@
 use crate::{
-    blocks_cache::BlocksCache,
+    blocks_cache::{BlocksCache, ComputedBlock},
+    blocks_server::BlocksServer,
+    clipboard::Clipboard,
     config::Config,
+    i3bar_protocol::Block,
+    ipc::Subscribers,
+    persist::BlocksPersist,
+    plugins::Plugins,
+    profiler::Profiler,
+    scripting::Scripts,
     status_cmd::StatusCmd,
+    sysinfo::SysInfo,
+    tooltip::Tooltip,
+    tray::Tray,
     wm_info_provider::{self, WmInfoProvider},
 };
 
+use i3bar_river_core::layout::UrgentMarker;
 use wayrs_utils::shm_alloc::ShmAlloc;
 
 pub struct SharedState {
     pub shm: ShmAlloc,
     pub config: Config,
     pub status_cmd: Option<StatusCmd>,
-    pub blocks_cache: BlocksCache,
+    pub blocks_cache: BlocksCaches,
+    pub blocks_persist: Option<BlocksPersist>,
+    pub blocks_server: Option<BlocksServer>,
+    /// The status command has not sent new blocks within `stale_timeout`.
+    pub blocks_stale: bool,
+    /// Every seat is idle and `idle.fade` is set.
+    pub faded: bool,
+    pub plugins: Plugins,
+    pub sysinfo: Option<SysInfo>,
+    pub scripts: Scripts,
+    pub profiler: Option<Profiler>,
+    pub clipboard: Option<Clipboard>,
+    pub tooltip: Option<Tooltip>,
+    pub tray: Option<Tray>,
     pub wm_info_provider: Box<dyn WmInfoProvider>,
+    pub subscribers: Subscribers,
 }
 
 impl SharedState {
@@ -27,4 +53,53 @@ impl SharedState {
     pub fn get_niri(&mut self) -> Option<&mut wm_info_provider::NiriInfoProvider> {
         self.wm_info_provider.as_any().downcast_mut()
     }
+
+    pub fn get_ext_workspace(&mut self) -> Option<&mut wm_info_provider::ExtWorkspaceInfoProvider> {
+        self.wm_info_provider.as_any().downcast_mut()
+    }
+}
+
+/// The blocks laid out with the fonts of every output.
+pub struct BlocksCaches {
+    base: BlocksCache,
+    /// For the outputs with a `font_scale`, by scale.
+    scaled: Vec<(f64, BlocksCache)>,
+}
+
+impl BlocksCaches {
+    pub fn new(config: &Config) -> Self {
+        let cache = || {
+            BlocksCache::with_multiline(config.multiline())
+                .with_urgent_glyph(config.urgent_marker == Some(UrgentMarker::Glyph))
+        };
+        Self {
+            base: cache(),
+            scaled: config
+                .font_scales()
+                .into_iter()
+                .map(|scale| (scale, cache()))
+                .collect(),
+        }
+    }
+
+    pub fn process_new_blocks(&mut self, config: &Config, blocks: Vec<Block>) {
+        for (scale, cache) in &mut self.scaled {
+            cache.process_new_blocks(&config.blocks_font().scaled(*scale), blocks.clone());
+        }
+        self.base.process_new_blocks(config.blocks_font(), blocks);
+    }
+
+    /// The blocks laid out with the fonts of the outputs without a `font_scale`.
+    pub fn get_computed(&self) -> &[ComputedBlock] {
+        self.base.get_computed()
+    }
+
+    /// The blocks laid out with the fonts scaled by `font_scale`.
+    pub fn get_scaled(&self, font_scale: f64) -> &[ComputedBlock] {
+        self.scaled
+            .iter()
+            .find(|(scale, _)| *scale == font_scale)
+            .map_or(&self.base, |(_, cache)| cache)
+            .get_computed()
+    }
 }
@@ -0,0 +1,70 @@
+//! Signals, received through a signalfd instead of signal handlers.
+//!
+//! The handled signals are blocked, so that they stay pending until they are read from the fd in
+//! the event loop, where they can be handled like any other event. The children start with an
+//! empty signal mask, as `std::process::Command` resets it.
+
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+pub struct Signals {
+    fd: OwnedFd,
+}
+
+impl Signals {
+    /// Block `signals` and receive them through [`Signals::fd`]. Only the threads spawned
+    /// afterwards inherit the mask, so this should be called before spawning threads.
+    pub fn new(signals: &[libc::c_int]) -> io::Result<Self> {
+        let fd = unsafe {
+            let mut set: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            for &signal in signals {
+                libc::sigaddset(&mut set, signal);
+            }
+            let ret = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+            if ret != 0 {
+                return Err(io::Error::from_raw_os_error(ret));
+            }
+            libc::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC)
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Becomes readable when a signal is delivered.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// The signals delivered since the last call, in order. Standard signals delivered several
+    /// times while pending are only reported once.
+    pub fn read(&self) -> io::Result<Vec<libc::c_int>> {
+        let mut signals = Vec::new();
+        loop {
+            let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+            let size = mem::size_of::<libc::signalfd_siginfo>();
+            let ret = unsafe {
+                libc::read(
+                    self.fd(),
+                    (&mut info as *mut libc::signalfd_siginfo).cast(),
+                    size,
+                )
+            };
+            if ret == -1 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    ErrorKind::WouldBlock => return Ok(signals),
+                    ErrorKind::Interrupted => continue,
+                    _ => return Err(err),
+                }
+            }
+            assert_eq!(ret as usize, size);
+            signals.push(info.ssi_signo as libc::c_int);
+        }
+    }
+}
@@ -1,12 +1,29 @@
-use crate::blocks_cache::BlocksCache;
-use crate::event_loop::EventLoop;
+use crate::blocks_server::BlocksServer;
+use crate::clipboard::Clipboard;
+use crate::dnd::DragAndDrop;
+use crate::event_loop::{self, EventLoop, Handle};
+use crate::idle::Idle;
+use crate::ipc::Subscribers;
 use crate::output::{Output, PendingOutput};
+use crate::output_power::OutputPower;
+use crate::persist::BlocksPersist;
+use crate::plugins::Plugins;
+use crate::profiler::Profiler;
 use crate::protocol::*;
+use crate::scripting::Scripts;
+use crate::swaybar;
+use crate::sysinfo::SysInfo;
+use crate::tooltip::Tooltip;
+use crate::tray::Tray;
 use crate::wm_info_provider;
 
+use std::ffi::{CString, OsString};
 use std::fmt::Display;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::path::Path;
+use std::os::unix::io::RawFd;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
 
 use wayrs_client::global::{GlobalExt, Globals, GlobalsExt};
 use wayrs_client::proxy::Proxy;
@@ -16,8 +33,13 @@ use wayrs_utils::seats::{SeatHandler, Seats};
 use wayrs_utils::shm_alloc::ShmAlloc;
 
 use crate::{
-    bar::Bar, config::Config, i3bar_protocol::Block, pointer_btn::PointerBtn,
-    shared_state::SharedState, status_cmd::StatusCmd,
+    bar::Bar,
+    config::{BarMode, Config, ScrollInversion, TimeOfDay},
+    i3bar_protocol::Block,
+    pointer_btn::PointerBtn,
+    shared_state::{BlocksCaches, SharedState},
+    status_cmd::StatusCmd,
+    Cli,
 };
 
 pub struct State {
@@ -25,18 +47,43 @@ pub struct State {
     pub layer_shell: ZwlrLayerShellV1,
     pub viewporter: WpViewporter,
     pub fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    pub blur_manager: Option<OrgKdeKwinBlurManager>,
+    pointer_gestures: Option<ZwpPointerGesturesV1>,
+    pub idle: Option<Idle>,
+    pub output_power: Option<OutputPower>,
+    pub dnd: Option<DragAndDrop>,
 
     seats: Seats,
+    ignored_seats: Vec<WlSeat>,
     pointers: Vec<Pointer>,
+    keyboards: Vec<Keyboard>,
 
     // Outputs that haven't yet advertised their names
     pub pending_outputs: Vec<PendingOutput>,
+    // Outputs without a bar, kept to be enabled with the `set-output-enabled` IPC command
+    pub disabled_outputs: Vec<Output>,
 
     pub hidden: bool,
+    // Whether the bars are hidden or faded because every seat is idle
+    is_idle: bool,
+    // When the bar shown by a peek is hidden again
+    peek_deadline: Option<Instant>,
+    // Whether the night colors of `[theme]` are used, once the schedule has been checked
+    night: Option<bool>,
     pub has_error: bool,
     pub bars: Vec<Bar>,
+    // The last blocks from the status command, shown before the blocks of the plugins
+    status_blocks: Vec<Block>,
+    status_blocks_received: Instant,
 
     pub shared_state: SharedState,
+    // The `--config` argument, to check a profile before switching to it
+    config_path: Option<PathBuf>,
+    // The `--config-profile` and `--bar-id` arguments, applied again when the config is reloaded
+    config_profile: Option<String>,
+    bar_id: Option<String>,
+    // Whether the shown error is from reloading the config, so that a successful reload clears it
+    config_error: bool,
 
     cursor_theme: CursorTheme,
     default_cursor: Option<CursorImage>,
@@ -50,8 +97,38 @@ struct Pointer {
     x: f64,
     y: f64,
     pending_button: Option<PointerBtn>,
+    pending_release: Option<PointerBtn>,
     pending_scroll: f64,
     scroll_frame: ScrollFrame,
+    swipe: Option<ZwpPointerGestureSwipeV1>,
+    /// The distance swiped since the last switch, if a swipe is in progress.
+    swipe_dx: Option<f64>,
+}
+
+struct Keyboard {
+    seat: WlSeat,
+    keyboard: WlKeyboard,
+    /// The bar which has the keyboard focus.
+    focus: Option<WlSurface>,
+}
+
+impl Pointer {
+    /// Accumulate `dx` of the ongoing swipe. Returns the scroll to switch tags with once the swipe
+    /// is long enough.
+    fn swipe(&mut self, dx: f64, threshold: f64) -> Option<PointerBtn> {
+        let total = self.swipe_dx.as_mut()?;
+        *total += dx;
+        if total.abs() < threshold {
+            return None;
+        }
+        let btn = if *total < 0.0 {
+            PointerBtn::WheelDown
+        } else {
+            PointerBtn::WheelUp
+        };
+        *total = 0.0;
+        Some(btn)
+    }
 }
 
 impl State {
@@ -59,18 +136,20 @@ impl State {
         conn: &mut Connection<Self>,
         globals: &Globals,
         event_loop: &mut EventLoop,
-        config_path: Option<&Path>,
+        args: &Cli,
+        config: anyhow::Result<Config>,
     ) -> Self {
         let mut error = Ok(());
 
-        let config = Config::new(config_path)
-            .map_err(|e| error = Err(e))
-            .unwrap_or_default();
+        let mut config = config.map_err(|e| error = Err(e)).unwrap_or_default();
 
-        let status_cmd = config
-            .command
-            .as_ref()
-            .and_then(|cmd| StatusCmd::new(cmd).map_err(|e| error = Err(e)).ok());
+        if let Some(bar_id) = &args.bar_id {
+            match swaybar::BarConfig::query(bar_id) {
+                Ok(bar_config) => bar_config.apply(&mut config),
+                Err(e) => error = Err(e),
+            }
+        }
+        let hidden = config.mode != BarMode::Dock;
 
         conn.add_registry_cb(wl_registry_cb);
         let wl_compositor = globals.bind(conn, 4..=5).unwrap();
@@ -81,35 +160,168 @@ impl State {
             .map_err(|e| error = Err(e.into()))
             .ok();
 
-        let wm_info_provider = wm_info_provider::bind(conn, globals, &config.wm);
+        let has_status_cmd =
+            (config.command.is_some() || args.replay.is_some()) && config.blocks_shown();
+        let blocks_persist = if config.persist_blocks && has_status_cmd {
+            BlocksPersist::new()
+        } else {
+            None
+        };
+
+        let blocks_server = config
+            .listen
+            .as_deref()
+            .and_then(|path| BlocksServer::bind(path).map_err(|e| error = Err(e)).ok());
+
+        let plugins = Plugins::load(&config.plugins)
+            .map_err(|e| error = Err(e))
+            .unwrap_or_default();
+
+        let sysinfo = config
+            .builtin
+            .sysinfo
+            .enable
+            .then(|| SysInfo::new(&config.builtin.sysinfo));
+
+        let scripts = Scripts::new(&config)
+            .map_err(|e| error = Err(e))
+            .unwrap_or_default();
+
+        let wm_info_provider = wm_info_provider::bind(conn, globals, &config);
         wm_info_provider.register(event_loop);
 
+        let clipboard = if config.copy_on_middle_click {
+            let clipboard = Clipboard::bind(conn, globals);
+            if clipboard.is_none() {
+                warn!("the compositor does not support wlr-data-control, copying is disabled");
+            }
+            clipboard
+        } else {
+            None
+        };
+
+        let tooltip = if config.show_tooltips {
+            let tooltip = Tooltip::bind(conn, globals, wl_compositor, config.tooltip_delay.0);
+            if tooltip.is_none() {
+                warn!("the compositor does not support xdg-shell, tooltips are disabled");
+            }
+            tooltip
+        } else {
+            None
+        };
+
+        let tray = if config.tray_shown() {
+            Tray::connect(config.tray_icon_theme.as_deref())
+                .map_err(|e| error = Err(e))
+                .ok()
+        } else {
+            None
+        };
+
+        let blur_manager = if config.blur {
+            let blur_manager = globals.bind(conn, 1..=1).ok();
+            if blur_manager.is_none() {
+                info!(
+                    "org_kde_kwin_blur is not supported, blur the bar with the compositor's rules"
+                );
+            }
+            blur_manager
+        } else {
+            None
+        };
+
+        let dnd = if config.accept_drops {
+            let dnd = DragAndDrop::bind(conn, globals);
+            if dnd.is_none() {
+                warn!("the compositor does not support wl_data_device_manager, drops are disabled");
+            }
+            dnd
+        } else {
+            None
+        };
+
+        let idle = config.idle.timeout.and_then(|timeout| {
+            let idle = Idle::bind(conn, globals, timeout.0);
+            if idle.is_none() {
+                warn!("the compositor does not support ext-idle-notify-v1, the bar is never idle");
+            }
+            idle
+        });
+
+        let output_power = OutputPower::bind(conn, globals);
+        if output_power.is_none() {
+            debug!("the compositor does not support wlr-output-power-management-unstable-v1");
+        }
+
+        let pointer_gestures = if config.swipe.enable && config.swipe.fingers > 2 {
+            let pointer_gestures = globals.bind(conn, 1..=3).ok();
+            if pointer_gestures.is_none() {
+                warn!("the compositor does not support pointer gestures, swiping is disabled");
+            }
+            pointer_gestures
+        } else {
+            None
+        };
+
+        let blocks_cache = BlocksCaches::new(&config);
+
         let mut this = Self {
             wl_compositor,
             layer_shell: globals.bind(conn, 1..=4).unwrap(),
             viewporter: globals.bind(conn, 1..=1).unwrap(),
             fractional_scale_manager: globals.bind(conn, 1..=1).ok(),
+            blur_manager,
+            pointer_gestures,
+            idle,
+            output_power,
+            dnd,
 
             seats: Seats::bind(conn, globals),
+            ignored_seats: Vec::new(),
             pointers: Vec::new(),
+            keyboards: Vec::new(),
 
             pending_outputs: globals
                 .iter()
                 .filter(|g| g.is::<WlOutput>())
                 .map(|g| PendingOutput::bind(conn, g))
                 .collect(),
+            disabled_outputs: Vec::new(),
 
-            hidden: false,
+            hidden,
+            is_idle: false,
+            peek_deadline: None,
+            night: None,
             has_error: false,
             bars: Vec::new(),
+            status_blocks: Vec::new(),
+            status_blocks_received: Instant::now(),
 
             shared_state: SharedState {
                 shm: ShmAlloc::bind(conn, globals).unwrap(),
                 config,
-                status_cmd,
-                blocks_cache: BlocksCache::default(),
+                status_cmd: None,
+                blocks_cache,
+                blocks_persist,
+                blocks_server,
+                blocks_stale: false,
+                faded: false,
+                plugins,
+                sysinfo,
+                scripts,
+                profiler: args
+                    .profile
+                    .map(|secs| Profiler::new(Duration::from_secs(secs.max(1)))),
+                clipboard,
+                tooltip,
+                tray,
                 wm_info_provider,
+                subscribers: Subscribers::default(),
             },
+            config_path: args.config.clone(),
+            config_profile: args.config_profile.clone(),
+            bar_id: args.bar_id.clone(),
+            config_error: false,
 
             cursor_theme,
             default_cursor,
@@ -122,6 +334,147 @@ impl State {
         this
     }
 
+    /// Spawn the status command, or start replaying a recorded session.
+    ///
+    /// Called once the outputs are known, so that they can be passed to the command.
+    pub fn start_status_cmd(&mut self, conn: &mut Connection<Self>, args: &Cli) {
+        let config = &self.shared_state.config;
+        if !config.blocks_shown() {
+            info!("no bar shows the blocks, not starting the status command");
+            return;
+        }
+        let status_cmd = match &args.replay {
+            Some(path) => StatusCmd::replay(path, config),
+            None => match &config.command {
+                Some(cmd) => StatusCmd::new(cmd, config, &self.status_cmd_env()),
+                None => return,
+            },
+        };
+        let status_cmd = status_cmd.and_then(|mut cmd| {
+            if let Some(path) = &args.record {
+                cmd.record(path)?;
+            }
+            Ok(cmd)
+        });
+        match status_cmd {
+            Ok(cmd) => {
+                self.shared_state.status_cmd = Some(cmd);
+                self.update_status_paused();
+            }
+            Err(e) => self.set_error(conn, "status", e),
+        }
+    }
+
+    /// The environment variables describing the bar to the status command.
+    fn status_cmd_env(&self) -> Vec<(&'static str, String)> {
+        let config = &self.shared_state.config;
+        let outputs: Vec<_> = self
+            .bars
+            .iter()
+            .map(|bar| bar.output.name.as_str())
+            .collect();
+        let scale = self.bars.iter().map(Bar::scale).fold(1.0, f64::max);
+        vec![
+            ("I3BAR_RIVER_HEIGHT", config.height.to_string()),
+            ("I3BAR_RIVER_SCALE", scale.to_string()),
+            ("I3BAR_RIVER_OUTPUTS", outputs.join(",")),
+            ("I3BAR_RIVER_FONT", config.font.to_string()),
+        ]
+    }
+
+    pub fn set_status_blocks(&mut self, conn: &mut Connection<Self>, blocks: Vec<Block>) {
+        self.status_blocks = blocks;
+        self.status_blocks_received = Instant::now();
+        self.shared_state.blocks_stale = false;
+        self.update_blocks(conn);
+    }
+
+    /// Start a timer to show the tooltip of the hovered block, if it is not running already.
+    pub fn schedule_tooltip(&mut self, handle: &mut Handle) {
+        let Some(tooltip) = &mut self.shared_state.tooltip else {
+            return;
+        };
+        if let Some(delay) = tooltip.timer_needed() {
+            handle.add_timer(delay, |ctx| {
+                ctx.state.show_tooltip(ctx.conn);
+                // The pointer may have moved to another block in the meantime
+                ctx.state.schedule_tooltip(ctx.handle);
+                Ok(event_loop::Action::Unregister)
+            });
+        }
+    }
+
+    fn show_tooltip(&mut self, conn: &mut Connection<Self>) {
+        let Some(tooltip) = &mut self.shared_state.tooltip else {
+            return;
+        };
+        let Some((surface, block)) = tooltip.due() else {
+            return;
+        };
+        let Some(bar) = self.bars.iter().find(|bar| bar.surface == surface) else {
+            return;
+        };
+        if let Err(e) = bar.show_tooltip(conn, &mut self.shared_state, &block) {
+            self.set_error(conn, "status", e);
+        }
+    }
+
+    /// Dim the blocks if the status command has not sent new ones within `stale_timeout`.
+    pub fn check_stale(&mut self, conn: &mut Connection<Self>) {
+        let Some(timeout) = self.shared_state.config.stale_timeout else {
+            return;
+        };
+        let stale = self.shared_state.status_cmd.is_some()
+            && self.status_blocks_received.elapsed() > timeout.0;
+        if stale != self.shared_state.blocks_stale {
+            if stale {
+                info!(
+                    "the status command has not sent new blocks for {:?}",
+                    timeout.0
+                );
+            }
+            self.shared_state.blocks_stale = stale;
+            self.draw_all(conn);
+        }
+    }
+
+    /// Collapse the bars the pointer has been away from for `auto_hide`.
+    pub fn check_auto_hide(&mut self, conn: &mut Connection<Self>) {
+        for bar in &mut self.bars {
+            bar.check_auto_hide(conn, &mut self.shared_state);
+        }
+    }
+
+    pub fn update_plugin(&mut self, conn: &mut Connection<Self>, index: usize) {
+        match self.shared_state.plugins.update(index) {
+            Ok(true) => self.update_blocks(conn),
+            Ok(false) => (),
+            Err(e) => self.set_error(conn, "plugin", format!("{e:#}")),
+        }
+    }
+
+    pub fn update_blocks(&mut self, conn: &mut Connection<Self>) {
+        let mut blocks = self.status_blocks.clone();
+        if let Some(server) = &self.shared_state.blocks_server {
+            blocks.extend(server.blocks().cloned());
+        }
+        blocks.extend(self.shared_state.plugins.blocks().cloned());
+        if let Some(sysinfo) = &self.shared_state.sysinfo {
+            blocks.extend(sysinfo.block().cloned());
+        }
+        self.set_blocks(conn, blocks);
+    }
+
+    pub fn update_sysinfo(&mut self, conn: &mut Connection<Self>) {
+        let Some(sysinfo) = &mut self.shared_state.sysinfo else {
+            return;
+        };
+        match sysinfo.update() {
+            Ok(()) => self.update_blocks(conn),
+            Err(e) => self.set_error(conn, "sysinfo", e),
+        }
+    }
+
     pub fn set_blocks(&mut self, conn: &mut Connection<Self>, blocks: Vec<Block>) {
         if !self.has_error {
             self.shared_state
@@ -132,13 +485,33 @@ impl State {
     }
 
     pub fn set_error(&mut self, conn: &mut Connection<Self>, context: &str, error: impl Display) {
-        self.set_blocks(
-            conn,
-            vec![Block {
-                full_text: format!("{context}: {error}"),
-                ..Default::default()
-            }],
-        );
+        error!("{context}: {error}");
+        if !self.shared_state.subscribers.is_empty() {
+            let event = serde_json::json!({
+                "event": "error",
+                "context": context,
+                "message": error.to_string(),
+            });
+            self.shared_state.subscribers.send(&event);
+        }
+        let config = &self.shared_state.config;
+        // The context is a chip with inverted colors, joined with the message
+        let context = Block {
+            full_text: format!(" <small>{context}</small> ").into(),
+            color: Some(config.error_bg),
+            background: Some(config.error_fg),
+            separator: false,
+            separator_block_width: 0,
+            markup: Some("pango".into()),
+            ..Default::default()
+        };
+        let message = Block {
+            full_text: format!(" {error} ").into(),
+            color: Some(config.error_fg),
+            background: Some(config.error_bg),
+            ..Default::default()
+        };
+        self.set_blocks(conn, vec![context, message]);
         self.has_error = true;
     }
 
@@ -148,100 +521,545 @@ impl State {
         }
     }
 
+    /// Switch between the day and the night colors if the `[theme.schedule]` says so.
+    pub fn update_theme(&mut self, conn: &mut Connection<Self>) {
+        let Some(schedule) = self.shared_state.config.theme.schedule else {
+            return;
+        };
+        let night = schedule.is_night(TimeOfDay::now());
+        if self.night == Some(night) {
+            return;
+        }
+        debug!("night colors: {night}");
+        self.night = Some(night);
+        self.shared_state.config.apply_theme(night);
+        for bar in &mut self.bars {
+            bar.reset_tag_colors();
+            bar.frame(conn, &mut self.shared_state);
+        }
+    }
+
     pub fn status_cmd_fd(&self) -> Option<RawFd> {
-        self.shared_state
-            .status_cmd
-            .as_ref()
-            .map(|cmd| cmd.output.as_raw_fd())
+        self.shared_state.status_cmd.as_ref().map(|cmd| cmd.fd())
     }
 
     pub fn register_output(&mut self, conn: &mut Connection<Self>, output: Output) {
         if !self.shared_state.config.output_enabled(&output.name) {
+            debug!("output {} is disabled", output.name);
+            self.disabled_outputs.push(output);
             return;
         }
 
+        debug!("new output {} (scale {})", output.name, output.scale);
+
         self.shared_state.wm_info_provider.new_ouput(conn, &output);
 
+        if let Some(output_power) = &mut self.output_power {
+            output_power.output_added(conn, output.wl);
+        }
+
         let mut bar = Bar::new(conn, self, output);
 
         bar.set_tags(self.shared_state.wm_info_provider.get_tags(&bar.output));
+        bar.focused = self
+            .shared_state
+            .wm_info_provider
+            .is_output_focused(&bar.output);
 
-        if !self.hidden {
-            bar.show(conn, &self.shared_state);
+        if self.bars_shown() {
+            bar.show(conn, &mut self.shared_state);
         }
 
+        // Show the blocks saved for the first output until the status command sends new ones
+        let persisted = self
+            .shared_state
+            .blocks_persist
+            .as_mut()
+            .and_then(|persist| persist.load(&bar.output.name));
+
         self.bars.push(bar);
+        self.update_mirrors(conn);
+
+        if let Some(blocks) = persisted {
+            self.set_status_blocks(conn, blocks);
+        }
     }
 
     pub fn drop_bar(&mut self, conn: &mut Connection<Self>, bar_index: usize) {
+        debug!("output {} removed", self.bars[bar_index].output.name);
+        self.remove_bar(conn, bar_index).destroy(conn);
+    }
+
+    /// Destroy a bar, returning its output.
+    fn remove_bar(&mut self, conn: &mut Connection<Self>, bar_index: usize) -> Output {
         let bar = self.bars.swap_remove(bar_index);
         self.shared_state
             .wm_info_provider
             .output_removed(conn, &bar.output);
-        bar.destroy(conn);
+        if let Some(output_power) = &mut self.output_power {
+            output_power.output_removed(conn, bar.output.wl);
+        }
+        if let Some(tooltip) = &mut self.shared_state.tooltip {
+            tooltip.bar_gone(conn, bar.surface);
+        }
+        self.update_mirrors(conn);
+        bar.destroy(conn)
+    }
+
+    /// Point the bars with `mirror_of` at the outputs they mirror, if these have a bar. Mirroring
+    /// is not transitive: a bar mirroring a mirror shows the state of the output of the latter.
+    fn update_mirrors(&mut self, conn: &mut Connection<Self>) {
+        let mut changed = false;
+        for i in 0..self.bars.len() {
+            let mirrored = self
+                .shared_state
+                .config
+                .output_mirror_of(&self.bars[i].output.name)
+                .and_then(|name| self.bars.iter().find(|b| b.output.name == name))
+                .map(|b| b.output.clone());
+            let bar = &mut self.bars[i];
+            if bar.mirrored.as_ref().map(|o| o.wl) != mirrored.as_ref().map(|o| o.wl) {
+                debug!(
+                    "{}: mirroring {}",
+                    bar.output.name,
+                    mirrored.as_ref().map_or("nothing", |o| o.name.as_str())
+                );
+                changed = true;
+            }
+            bar.mirrored = mirrored;
+        }
+        if changed {
+            self.tags_updated(conn, None);
+            self.layout_name_updated(conn, None);
+            self.mode_name_updated(conn, None);
+            self.output_focus_updated(conn);
+        }
+    }
+
+    /// Add or remove the bar on the output `name`, overriding the config.
+    pub fn set_output_enabled(
+        &mut self,
+        conn: &mut Connection<Self>,
+        name: &str,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        let bar_index = self.bars.iter().position(|bar| bar.output.name == name);
+        let disabled_index = self.disabled_outputs.iter().position(|o| o.name == name);
+        ensure!(
+            bar_index.is_some() || disabled_index.is_some(),
+            "there is no output '{name}'"
+        );
+        debug!("output {name} enabled: {enabled}");
+        self.shared_state.config.set_output_enabled(name, enabled);
+        match (enabled, bar_index, disabled_index) {
+            (true, None, Some(i)) => {
+                let output = self.disabled_outputs.swap_remove(i);
+                self.register_output(conn, output);
+            }
+            (false, Some(i), None) => {
+                let output = self.remove_bar(conn, i);
+                self.disabled_outputs.push(output);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Kill the status command and exit.
+    pub fn quit(&mut self) -> ! {
+        self.flush_blocks();
+        if let Some(cmd) = &mut self.shared_state.status_cmd {
+            cmd.kill();
+        }
+        std::process::exit(0);
+    }
+
+    /// Save the blocks for every output with a bar, see `persist_blocks` in README.
+    pub fn flush_blocks(&mut self) {
+        let Some(persist) = &mut self.shared_state.blocks_persist else {
+            return;
+        };
+        let outputs = self.bars.iter().map(|bar| bar.output.name.as_str());
+        if let Err(e) = persist.flush(outputs) {
+            warn!("failed to save the blocks: {e}");
+        }
+    }
+
+    /// Read the config again and redraw the bars with it.
+    ///
+    /// The options used only at startup, such as the status command, the plugins, the signals and
+    /// the enabled outputs, keep their old values. If the new config is invalid, the old one is kept
+    /// and the error is shown.
+    pub fn reload_config(&mut self, conn: &mut Connection<Self>) -> anyhow::Result<()> {
+        info!("reloading the config");
+        let config = Config::new(self.config_path.as_deref(), self.config_profile.as_deref())
+            .and_then(|mut config| {
+                if let Some(bar_id) = &self.bar_id {
+                    swaybar::BarConfig::query(bar_id)?.apply(&mut config);
+                }
+                Ok(config)
+            });
+        let mut config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                self.set_error(conn, "config", format!("{e:#}"));
+                self.config_error = true;
+                return Err(e);
+            }
+        };
+
+        let was_shown = self.bars_shown();
+        let ss = &mut self.shared_state;
+        config.keep_startup_options(&mut ss.config);
+        ss.config = config;
+        ss.faded = self.is_idle && ss.config.idle.fade;
+
+        // The texts are measured with the old font, keep the current blocks until new ones arrive
+        let blocks = ss
+            .blocks_cache
+            .get_computed()
+            .iter()
+            .map(|computed| computed.block.clone())
+            .collect();
+        ss.blocks_cache = BlocksCaches::new(&ss.config);
+        ss.blocks_cache.process_new_blocks(&ss.config, blocks);
+
+        for bar in &mut self.bars {
+            bar.config_changed(conn, &ss.config);
+        }
+        self.update_mirrors(conn);
+        self.update_bars_shown(conn, was_shown);
+        self.update_status_paused();
+        self.night = None;
+        self.update_theme(conn);
+
+        if self.config_error {
+            self.config_error = false;
+            self.has_error = false;
+        }
+        if self.has_error {
+            self.draw_all(conn);
+        } else {
+            self.update_blocks(conn);
+        }
+        Ok(())
+    }
+
+    /// Check that the config has the profile `name`.
+    pub fn check_profile(&self, name: &str) -> anyhow::Result<()> {
+        Config::new(self.config_path.as_deref(), Some(name)).map(drop)
+    }
+
+    /// Replace the bar with a new instance which uses the profile `name`, with the same arguments
+    /// otherwise. Only returns if that fails.
+    pub fn restart_with_profile(&mut self, conn: &mut Connection<Self>, name: &str) {
+        let mut args: Vec<OsString> = Vec::new();
+        let mut old_args = std::env::args_os().skip(1);
+        while let Some(arg) = old_args.next() {
+            if arg == "--config-profile" {
+                old_args.next();
+            } else if !arg.to_string_lossy().starts_with("--config-profile=") {
+                args.push(arg);
+            }
+        }
+        args.push("--config-profile".into());
+        args.push(name.into());
+
+        info!("restarting with the profile {name}");
+        self.flush_blocks();
+        if let Some(cmd) = &mut self.shared_state.status_cmd {
+            cmd.kill();
+        }
+        // The fds are close-on-exec, so the lock and the socket are free for the new instance
+        let mut cmd = Command::new("/proc/self/exe");
+        if let Some(arg0) = std::env::args_os().next() {
+            cmd.arg0(arg0);
+        }
+        let error = cmd.args(args).exec();
+        self.set_error(conn, "profile", error);
+    }
+
+    /// The state reported by the `get-state` IPC command.
+    pub fn ipc_state(&self) -> serde_json::Value {
+        let outputs: Vec<_> = self.bars.iter().map(Bar::ipc_state).collect();
+        let blocks: Vec<_> = self
+            .shared_state
+            .blocks_cache
+            .get_computed()
+            .iter()
+            .map(|computed| {
+                serde_json::json!({
+                    "name": computed.block.name,
+                    "instance": computed.block.instance,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "hidden": self.hidden,
+            "bar_mode": self.shared_state.config.mode,
+            "outputs": outputs,
+            "blocks": blocks,
+        })
     }
 
     pub fn toggle_visibility(&mut self, conn: &mut Connection<Self>) {
-        self.hidden = !self.hidden;
+        if self.shared_state.config.mode != BarMode::Invisible {
+            self.set_hidden(conn, !self.hidden);
+        }
+    }
+
+    /// Show or hide the bars, unless the mode is invisible.
+    pub fn set_visible(&mut self, conn: &mut Connection<Self>, visible: bool) {
+        if self.shared_state.config.mode != BarMode::Invisible {
+            self.set_hidden(conn, !visible);
+        }
+    }
+
+    /// Switch to `mode`, like `swaymsg bar mode`.
+    pub fn set_mode(&mut self, conn: &mut Connection<Self>, mode: BarMode) {
+        debug!("bar mode: {mode:?}");
+        self.shared_state.config.mode = mode;
+        self.set_hidden(conn, mode != BarMode::Dock);
+    }
+
+    /// Show the bar while `revealed`, in the hide mode.
+    pub fn reveal(&mut self, conn: &mut Connection<Self>, revealed: bool) {
+        if self.shared_state.config.mode == BarMode::Hide {
+            self.set_hidden(conn, !revealed);
+        }
+    }
+
+    fn set_hidden(&mut self, conn: &mut Connection<Self>, hidden: bool) {
+        if self.hidden == hidden {
+            return;
+        }
+        let was_shown = self.bars_shown();
+        self.hidden = hidden;
+        self.peek_deadline = None;
+        debug!("hidden: {}", self.hidden);
+        self.update_status_paused();
+        if !self.shared_state.subscribers.is_empty() {
+            let event = serde_json::json!({ "event": "visibility", "hidden": self.hidden });
+            self.shared_state.subscribers.send(&event);
+        }
+        self.update_bars_shown(conn, was_shown);
+    }
+
+    /// Whether the bars are mapped: they are neither hidden nor hidden because the seats are idle.
+    fn bars_shown(&self) -> bool {
+        !self.hidden && !(self.is_idle && !self.shared_state.config.idle.fade)
+    }
+
+    /// Show or hide the bars if [`State::bars_shown`] has changed from `was_shown`.
+    fn update_bars_shown(&mut self, conn: &mut Connection<Self>, was_shown: bool) {
+        let shown = self.bars_shown();
+        if shown == was_shown {
+            return;
+        }
         for bar in &mut self.bars {
-            if self.hidden {
-                bar.hide(conn);
+            if shown {
+                bar.show(conn, &mut self.shared_state);
             } else {
-                bar.show(conn, &self.shared_state);
+                bar.hide(conn, &mut self.shared_state);
             }
         }
     }
 
+    /// Hide or fade the bars once every seat is idle, and restore them on activity.
+    pub fn idle_changed(&mut self, conn: &mut Connection<Self>) {
+        let idle = self.idle.as_ref().is_some_and(Idle::is_idle);
+        if idle == self.is_idle {
+            return;
+        }
+        debug!("idle: {idle}");
+        let was_shown = self.bars_shown();
+        self.is_idle = idle;
+        self.update_status_paused();
+        if self.shared_state.config.idle.fade {
+            self.shared_state.faded = idle;
+            self.draw_all(conn);
+        } else {
+            self.update_bars_shown(conn, was_shown);
+        }
+    }
+
+    /// Stop drawing the bar on `output` while it is powered off, and redraw it once it is on.
+    pub fn output_power_changed(&mut self, conn: &mut Connection<Self>, output: WlOutput) {
+        let on = self.output_power.as_ref().is_none_or(|p| p.is_on(output));
+        let Some(bar) = self.bars.iter_mut().find(|b| b.output.wl == output) else {
+            return;
+        };
+        if bar.powered_off != on {
+            return;
+        }
+        debug!(
+            "{}: powered {}",
+            bar.output.name,
+            if on { "on" } else { "off" }
+        );
+        bar.powered_off = !on;
+        if on {
+            bar.frame(conn, &mut self.shared_state);
+        }
+        self.update_status_paused();
+    }
+
+    /// Pause the status command while the bars are hidden, or while nobody can see them, see
+    /// `pause_when_hidden` and `pause_when_off` in README.
+    fn update_status_paused(&mut self) {
+        let config = &self.shared_state.config;
+        let powered_off = !self.bars.is_empty() && self.bars.iter().all(|b| b.powered_off);
+        let paused = config.pause_when_hidden && self.hidden
+            || config.pause_when_off && (self.is_idle || powered_off);
+        if let Some(cmd) = &mut self.shared_state.status_cmd {
+            cmd.set_paused(paused);
+        }
+    }
+
+    /// Show the hidden bar for `duration`, then hide it again, unless it is hovered in the
+    /// meantime. Peeking while a peek is in progress extends it.
+    pub fn peek(&mut self, conn: &mut Connection<Self>, handle: &mut Handle, duration: Duration) {
+        if !self.hidden && self.peek_deadline.is_none()
+            || self.shared_state.config.mode == BarMode::Invisible
+        {
+            return;
+        }
+        if self.hidden {
+            self.toggle_visibility(conn);
+        }
+        self.peek_deadline = Some(Instant::now() + duration);
+        handle.add_timer(duration, |ctx| {
+            // The deadline is moved by a later peek and cleared by a hover or a toggle
+            if ctx
+                .state
+                .peek_deadline
+                .is_some_and(|deadline| deadline <= Instant::now())
+            {
+                ctx.state.toggle_visibility(ctx.conn);
+            }
+            Ok(event_loop::Action::Unregister)
+        });
+    }
+
     fn for_each_bar<F: FnMut(&mut Bar, &mut SharedState)>(
         &mut self,
         output: Option<WlOutput>,
         mut f: F,
     ) {
-        match output {
-            Some(output) => f(
-                self.bars
-                    .iter_mut()
-                    .find(|b| b.output.wl == output)
-                    .unwrap(),
-                &mut self.shared_state,
-            ),
-            None => self
-                .bars
-                .iter_mut()
-                .for_each(|b| f(b, &mut self.shared_state)),
-        }
+        // Several bars show the state of an output which is mirrored
+        self.bars
+            .iter_mut()
+            .filter(|b| output.is_none_or(|output| b.wm_output().wl == output))
+            .for_each(|b| f(b, &mut self.shared_state));
     }
 
     pub fn tags_updated(&mut self, conn: &mut Connection<Self>, output: Option<WlOutput>) {
         self.for_each_bar(output, |bar, ss| {
-            bar.set_tags(ss.wm_info_provider.get_tags(&bar.output));
+            bar.set_tags(ss.wm_info_provider.get_tags(bar.wm_output()));
             bar.frame(conn, ss);
+            notify_output(ss, "tags", bar);
         });
     }
 
     pub fn layout_name_updated(&mut self, conn: &mut Connection<Self>, output: Option<WlOutput>) {
         self.for_each_bar(output, |bar, ss| {
-            bar.set_layout_name(ss.wm_info_provider.get_layout_name(&bar.output));
+            bar.set_layout_name(ss.wm_info_provider.get_layout_name(bar.wm_output()));
             bar.frame(conn, ss);
+            notify_output(ss, "layout", bar);
         });
     }
 
     pub fn mode_name_updated(&mut self, conn: &mut Connection<Self>, output: Option<WlOutput>) {
         self.for_each_bar(output, |bar, ss| {
-            bar.set_mode_name(ss.wm_info_provider.get_mode_name(&bar.output));
+            bar.set_mode_name(ss.wm_info_provider.get_mode_name(bar.wm_output()));
             bar.frame(conn, ss);
+            notify_output(ss, "mode", bar);
+        });
+    }
+
+    /// Re-render the bars of the outputs which have gained or lost the focus.
+    pub fn output_focus_updated(&mut self, conn: &mut Connection<Self>) {
+        self.for_each_bar(None, |bar, ss| {
+            let focused = ss.wm_info_provider.is_output_focused(bar.wm_output());
+            if bar.focused != focused {
+                bar.focused = focused;
+                bar.frame(conn, ss);
+                notify_output(ss, "focus", bar);
+            }
         });
     }
 }
 
+/// Tell the IPC subscribers about a change on the output of `bar`.
+fn notify_output(ss: &mut SharedState, event: &str, bar: &Bar) {
+    if !ss.subscribers.is_empty() {
+        let event = serde_json::json!({ "event": event, "output": bar.ipc_state() });
+        ss.subscribers.send(&event);
+    }
+}
+
 impl SeatHandler for State {
     fn get_seats(&mut self) -> &mut Seats {
         &mut self.seats
     }
 
+    fn seat_added(&mut self, conn: &mut Connection<Self>, seat: WlSeat) {
+        if let Some(idle) = &mut self.idle {
+            idle.seat_added(conn, seat);
+        }
+        if let Some(dnd) = &mut self.dnd {
+            dnd.seat_added(conn, seat);
+        }
+        self.shared_state.wm_info_provider.seat_added(conn, seat);
+    }
+
+    fn seat_name(&mut self, conn: &mut Connection<Self>, seat: WlSeat, name: CString) {
+        debug!("new seat {name:?}");
+        if !self
+            .shared_state
+            .config
+            .seat_enabled(&name.to_string_lossy())
+        {
+            debug!("ignoring seat {name:?}");
+            // The pointer may have been added before the name was received
+            self.pointer_removed(conn, seat);
+            self.keyboard_removed(conn, seat);
+            if let Some(idle) = &mut self.idle {
+                idle.seat_removed(conn, seat);
+            }
+            if let Some(dnd) = &mut self.dnd {
+                dnd.seat_removed(conn, seat);
+            }
+            self.shared_state.wm_info_provider.seat_removed(conn, seat);
+            self.ignored_seats.push(seat);
+        }
+    }
+
+    fn seat_removed(&mut self, conn: &mut Connection<Self>, seat: WlSeat) {
+        if !self.ignored_seats.contains(&seat) {
+            self.shared_state.wm_info_provider.seat_removed(conn, seat);
+        }
+        self.ignored_seats.retain(|s| *s != seat);
+        if let Some(idle) = &mut self.idle {
+            idle.seat_removed(conn, seat);
+        }
+        if let Some(dnd) = &mut self.dnd {
+            dnd.seat_removed(conn, seat);
+        }
+        if let Some(clipboard) = &mut self.shared_state.clipboard {
+            clipboard.seat_removed(conn, seat);
+        }
+    }
+
     fn pointer_added(&mut self, conn: &mut Connection<Self>, seat: WlSeat) {
+        if self.ignored_seats.contains(&seat) {
+            return;
+        }
         assert!(seat.version() >= 5);
         let pointer = seat.get_pointer_with_cb(conn, wl_pointer_cb);
+        let swipe = self
+            .pointer_gestures
+            .map(|mgr| mgr.get_swipe_gesture_with_cb(conn, pointer, swipe_gesture_cb));
         self.pointers.push(Pointer {
             seat,
             pointer,
@@ -250,15 +1068,50 @@ impl SeatHandler for State {
             x: 0.0,
             y: 0.0,
             pending_button: None,
+            pending_release: None,
             pending_scroll: 0.0,
             scroll_frame: ScrollFrame::default(),
+            swipe,
+            swipe_dx: None,
+        });
+    }
+
+    fn keyboard_added(&mut self, conn: &mut Connection<Self>, seat: WlSeat) {
+        if self.ignored_seats.contains(&seat) {
+            return;
+        }
+        self.keyboards.push(Keyboard {
+            seat,
+            keyboard: seat.get_keyboard_with_cb(conn, wl_keyboard_cb),
+            focus: None,
         });
     }
 
+    fn keyboard_removed(&mut self, conn: &mut Connection<Self>, seat: WlSeat) {
+        let Some(i) = self.keyboards.iter().position(|k| k.seat == seat) else {
+            return;
+        };
+        let keyboard = self.keyboards.swap_remove(i);
+        if let Some(bar) = self
+            .bars
+            .iter_mut()
+            .find(|b| Some(b.surface) == keyboard.focus)
+        {
+            bar.set_keyboard_focus(conn, &mut self.shared_state, false);
+        }
+        keyboard.keyboard.release(conn);
+    }
+
     fn pointer_removed(&mut self, conn: &mut Connection<Self>, seat: WlSeat) {
-        let pointer_i = self.pointers.iter().position(|p| p.seat == seat).unwrap();
+        // Pointers of ignored seats are never added
+        let Some(pointer_i) = self.pointers.iter().position(|p| p.seat == seat) else {
+            return;
+        };
         let pointer = self.pointers.swap_remove(pointer_i);
         pointer.themed_pointer.destroy(conn);
+        if let Some(swipe) = pointer.swipe {
+            swipe.destroy(conn);
+        }
         pointer.pointer.release(conn);
     }
 }
@@ -277,6 +1130,60 @@ fn wl_registry_cb(conn: &mut Connection<State>, state: &mut State, event: &wl_re
                 .position(|bar| bar.output.reg_name == *name)
             {
                 state.drop_bar(conn, bar_index);
+            } else if let Some(i) = state
+                .disabled_outputs
+                .iter()
+                .position(|o| o.reg_name == *name)
+            {
+                state.disabled_outputs.swap_remove(i).destroy(conn);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn wl_keyboard_cb(ctx: EventCtx<State, WlKeyboard>) {
+    let Some(keyboard) = ctx
+        .state
+        .keyboards
+        .iter_mut()
+        .find(|k| k.keyboard == ctx.proxy)
+    else {
+        return;
+    };
+
+    use wl_keyboard::Event;
+    match ctx.event {
+        Event::Enter(args) => {
+            let Some(bar) = ctx
+                .state
+                .bars
+                .iter_mut()
+                .find(|b| b.surface.id() == args.surface)
+            else {
+                return;
+            };
+            keyboard.focus = Some(bar.surface);
+            bar.set_keyboard_focus(ctx.conn, &mut ctx.state.shared_state, true);
+        }
+        Event::Leave(_) => {
+            let Some(surface) = keyboard.focus.take() else {
+                return;
+            };
+            if let Some(bar) = ctx.state.bars.iter_mut().find(|b| b.surface == surface) {
+                bar.set_keyboard_focus(ctx.conn, &mut ctx.state.shared_state, false);
+            }
+        }
+        Event::Key(args) if args.state == wl_keyboard::KeyState::Pressed => {
+            let seat = keyboard.seat;
+            let Some(surface) = keyboard.focus else {
+                return;
+            };
+            let Some(bar) = ctx.state.bars.iter_mut().find(|b| b.surface == surface) else {
+                return;
+            };
+            if let Err(e) = bar.key(ctx.conn, &mut ctx.state.shared_state, seat, args.key) {
+                ctx.state.set_error(ctx.conn, "keyboard", e);
             }
         }
         _ => (),
@@ -295,7 +1202,10 @@ fn wl_pointer_cb(ctx: EventCtx<State, WlPointer>) {
     match ctx.event {
         Event::Frame => {
             let btn = pointer.pending_button.take();
+            let release = pointer.pending_release.take();
             let scroll = pointer.scroll_frame.finalize();
+            // Reported once the bar and the pointer are no longer borrowed
+            let mut click_error = None;
             if let Some(surface) = pointer.current_surface {
                 let bar = ctx
                     .state
@@ -305,18 +1215,44 @@ fn wl_pointer_cb(ctx: EventCtx<State, WlPointer>) {
                     .unwrap();
 
                 if let Some(btn) = btn {
-                    bar.click(
+                    trace!(
+                        "{btn:?} at ({}, {}) on {}",
+                        pointer.x,
+                        pointer.y,
+                        bar.output.name
+                    );
+                    if let Err(e) = bar.click(
                         ctx.conn,
                         &mut ctx.state.shared_state,
                         btn,
                         pointer.seat,
                         pointer.x,
                         pointer.y,
-                    )
-                    .unwrap();
+                    ) {
+                        click_error = Some(e);
+                    }
+                }
+                if let Some(btn) = release {
+                    bar.release(
+                        ctx.conn,
+                        &mut ctx.state.shared_state,
+                        btn,
+                        pointer.seat,
+                        pointer.x,
+                        pointer.y,
+                    );
                 }
 
-                if scroll.is_finger && ctx.state.shared_state.config.invert_touchpad_scrolling {
+                let config = &ctx.state.shared_state.config;
+                let inversion =
+                    config.touchpad_scroll_inversion(bar.is_over_tags(pointer.x, pointer.y));
+                let invert = match inversion {
+                    ScrollInversion::Never => false,
+                    ScrollInversion::Always => true,
+                    ScrollInversion::Auto => !scroll.natural,
+                };
+                let is_finger = scroll.source == Some(wl_pointer::AxisSource::Finger);
+                if is_finger && invert {
                     pointer.pending_scroll -= scroll.absolute;
                 } else {
                     pointer.pending_scroll += scroll.absolute;
@@ -326,10 +1262,15 @@ fn wl_pointer_cb(ctx: EventCtx<State, WlPointer>) {
                     pointer.pending_scroll = 0.0;
                 }
 
-                let btn = if pointer.pending_scroll >= 15.0 {
+                let threshold = match scroll.source {
+                    Some(wl_pointer::AxisSource::Finger) => config.scroll_threshold.finger,
+                    Some(wl_pointer::AxisSource::Continuous) => config.scroll_threshold.continuous,
+                    _ => config.scroll_threshold.wheel,
+                };
+                let btn = if pointer.pending_scroll >= threshold {
                     pointer.pending_scroll = 0.0;
                     Some(PointerBtn::WheelDown)
-                } else if pointer.pending_scroll <= -15.0 {
+                } else if pointer.pending_scroll <= -threshold {
                     pointer.pending_scroll = 0.0;
                     Some(PointerBtn::WheelUp)
                 } else {
@@ -337,28 +1278,47 @@ fn wl_pointer_cb(ctx: EventCtx<State, WlPointer>) {
                 };
 
                 if let Some(btn) = btn {
-                    bar.click(
+                    if let Err(e) = bar.click(
                         ctx.conn,
                         &mut ctx.state.shared_state,
                         btn,
                         pointer.seat,
                         pointer.x,
                         pointer.y,
-                    )
-                    .unwrap();
+                    ) {
+                        click_error = Some(e);
+                    }
                 }
+
+                let swipe = &ctx.state.shared_state.config.swipe;
+                if swipe.enable && swipe.fingers == 2 && is_finger {
+                    let threshold = swipe.threshold;
+                    pointer.swipe_dx.get_or_insert(0.0);
+                    let btn = pointer.swipe(scroll.horizontal, threshold);
+                    if scroll.horizontal_stop {
+                        pointer.swipe_dx = None;
+                    }
+                    if let Some(btn) = btn {
+                        bar.swipe(ctx.conn, &mut ctx.state.shared_state, pointer.seat, btn);
+                    }
+                }
+            }
+            if let Some(e) = click_error {
+                ctx.state.set_error(ctx.conn, "click", format!("{e:#}"));
             }
         }
         Event::Enter(args) => {
             let bar = ctx
                 .state
                 .bars
-                .iter()
+                .iter_mut()
                 .find(|bar| bar.surface.id() == args.surface)
                 .unwrap();
             pointer.current_surface = Some(bar.surface);
             pointer.x = args.surface_x.as_f64();
             pointer.y = args.surface_y.as_f64();
+            // Hovering a peeking bar keeps it shown
+            ctx.state.peek_deadline = None;
             if let Some(default_cursor) = &ctx.state.default_cursor {
                 pointer.themed_pointer.set_cursor(
                     ctx.conn,
@@ -368,30 +1328,101 @@ fn wl_pointer_cb(ctx: EventCtx<State, WlPointer>) {
                     args.serial,
                 );
             }
+            bar.hover(
+                ctx.conn,
+                &mut ctx.state.shared_state,
+                Some((pointer.x, pointer.y)),
+            );
+        }
+        Event::Leave(_) => {
+            if let Some(bar) = ctx
+                .state
+                .bars
+                .iter_mut()
+                .find(|bar| Some(bar.surface) == pointer.current_surface)
+            {
+                bar.hover(ctx.conn, &mut ctx.state.shared_state, None);
+            }
+            pointer.current_surface = None;
         }
-        Event::Leave(_) => pointer.current_surface = None,
         Event::Motion(args) => {
             pointer.x = args.surface_x.as_f64();
             pointer.y = args.surface_y.as_f64();
+            if let Some(bar) = ctx
+                .state
+                .bars
+                .iter_mut()
+                .find(|bar| Some(bar.surface) == pointer.current_surface)
+            {
+                bar.hover(
+                    ctx.conn,
+                    &mut ctx.state.shared_state,
+                    Some((pointer.x, pointer.y)),
+                );
+            }
         }
         Event::Button(args) => {
             if args.state == wl_pointer::ButtonState::Pressed {
                 pointer.pending_button = Some(args.button.into());
+            } else {
+                pointer.pending_release = Some(args.button.into());
             }
         }
         Event::Axis(args) => {
             if args.axis == wl_pointer::Axis::VerticalScroll {
                 pointer.scroll_frame.absolute += args.value.as_f64();
+            } else {
+                pointer.scroll_frame.horizontal += args.value.as_f64();
             }
         }
         Event::AxisSource(source) => {
-            pointer.scroll_frame.is_finger = source == wl_pointer::AxisSource::Finger;
+            pointer.scroll_frame.source = Some(source);
         }
         Event::AxisStop(args) => {
             if args.axis == wl_pointer::Axis::VerticalScroll {
                 pointer.scroll_frame.stop = true;
+            } else {
+                pointer.scroll_frame.horizontal_stop = true;
+            }
+        }
+        Event::AxisRelativeDirection(args) => {
+            if args.axis == wl_pointer::Axis::VerticalScroll {
+                pointer.scroll_frame.natural =
+                    args.direction == wl_pointer::AxisRelativeDirection::Inverted;
+            }
+        }
+        _ => (),
+    }
+}
+
+fn swipe_gesture_cb(ctx: EventCtx<State, ZwpPointerGestureSwipeV1>) {
+    let pointer = ctx
+        .state
+        .pointers
+        .iter_mut()
+        .find(|p| p.swipe == Some(ctx.proxy))
+        .unwrap();
+    let swipe = &ctx.state.shared_state.config.swipe;
+
+    use zwp_pointer_gesture_swipe_v1::Event;
+    match ctx.event {
+        Event::Begin(args) => {
+            pointer.swipe_dx = (args.fingers == swipe.fingers).then_some(0.0);
+        }
+        Event::Update(args) => {
+            let Some(btn) = pointer.swipe(args.dx.as_f64(), swipe.threshold) else {
+                return;
+            };
+            if let Some(bar) = ctx
+                .state
+                .bars
+                .iter()
+                .find(|bar| Some(bar.surface) == pointer.current_surface)
+            {
+                bar.swipe(ctx.conn, &mut ctx.state.shared_state, pointer.seat, btn);
             }
         }
+        Event::End(_) => pointer.swipe_dx = None,
         _ => (),
     }
 }
@@ -400,7 +1431,11 @@ fn wl_pointer_cb(ctx: EventCtx<State, WlPointer>) {
 pub struct ScrollFrame {
     stop: bool,
     absolute: f64,
-    is_finger: bool,
+    horizontal_stop: bool,
+    horizontal: f64,
+    source: Option<wl_pointer::AxisSource>,
+    /// The compositor has inverted the scroll direction, i.e. natural scrolling is enabled.
+    natural: bool,
 }
 
 impl ScrollFrame {
@@ -1,46 +1,143 @@
 use std::io::{self, BufWriter, ErrorKind, Write};
-use std::os::unix::io::AsRawFd;
-use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
 
 use anyhow::Result;
 
+use crate::config::{Config, StatusProtocol};
 use crate::i3bar_protocol::{Block, Event, Protocol};
-use crate::utils::read_to_vec;
+use crate::recording::{self, Recorder};
+use crate::utils::{read_to_vec, spawn_command};
 
 #[derive(Debug)]
 pub struct StatusCmd {
-    pub child: Child,
-    pub output: ChildStdout,
-    input: BufWriter<ChildStdin>,
+    /// `None` when replaying a recorded session. The child leads its own process group, which
+    /// also has the processes it spawns, e.g. the other commands of a pipeline.
+    child: Option<Child>,
+    paused: bool,
+    output: OwnedFd,
+    input: Option<BufWriter<ChildStdin>>,
     protocol: Protocol,
+    plain_text_separator: Option<String>,
+    recorder: Option<Recorder>,
     buf: Vec<u8>,
 }
 
 impl StatusCmd {
-    pub fn new(cmd: &str) -> Result<Self> {
+    pub fn new(cmd: &str, config: &Config, env: &[(&str, String)]) -> Result<Self> {
+        debug!("spawning status command: {cmd}");
         let mut child = Command::new("sh")
             .args(["-c", &format!("exec {cmd}")])
+            .envs(env.iter().cloned())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
+            .process_group(0)
             .spawn()?;
-        let output = child.stdout.take().unwrap();
+        let output = child.stdout.take().unwrap().into();
         let input = BufWriter::new(child.stdin.take().unwrap());
+        Self::with_output(Some(child), output, Some(input), config)
+    }
+
+    /// Replay a session recorded with [`StatusCmd::record`] instead of running a command.
+    pub fn replay(path: &Path, config: &Config) -> Result<Self> {
+        debug!("replaying {}", path.display());
+        Self::with_output(None, recording::replay(path)?, None, config)
+    }
+
+    fn with_output(
+        child: Option<Child>,
+        output: OwnedFd,
+        input: Option<BufWriter<ChildStdin>>,
+        config: &Config,
+    ) -> Result<Self> {
         if unsafe { libc::fcntl(output.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) } == -1 {
             return Err(io::Error::last_os_error().into());
         }
         Ok(Self {
             child,
+            paused: false,
             output,
             input,
-            protocol: Protocol::Unknown,
+            protocol: match config.protocol {
+                StatusProtocol::Auto => Protocol::Unknown,
+                StatusProtocol::Lemonbar => Protocol::Lemonbar {
+                    pending_line: None,
+                    actions: Default::default(),
+                },
+            },
+            plain_text_separator: config.plain_text_separator.clone(),
+            recorder: None,
             buf: Vec::new(),
         })
     }
 
+    /// Record everything the command outputs to a file, see [`StatusCmd::replay`].
+    pub fn record(&mut self, path: &Path) -> Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.output.as_raw_fd()
+    }
+
+    /// Terminate the whole process group, so that no children are left behind.
+    pub fn kill(&mut self) {
+        // Stopped processes only handle SIGTERM once they continue
+        let _ = self.signal_group(libc::SIGTERM);
+        let _ = self.signal_group(libc::SIGCONT);
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+        }
+    }
+
+    /// Pause the process group while the bar is hidden or unseen, with the signals from the header
+    /// of the i3bar protocol.
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        let (stop, cont) = self.protocol.stop_cont_signals();
+        let signal = match (paused, stop, cont) {
+            (true, 0, _) => libc::SIGSTOP,
+            (true, stop, _) => stop,
+            (false, _, 0) => libc::SIGCONT,
+            (false, _, cont) => cont,
+        };
+        debug!(
+            "{} the status command",
+            if paused { "pausing" } else { "resuming" }
+        );
+        match self.signal_group(signal) {
+            Ok(()) => self.paused = paused,
+            Err(e) => warn!("failed to signal the status command: {e}"),
+        }
+    }
+
+    fn signal_group(&self, signal: libc::c_int) -> io::Result<()> {
+        let Some(child) = &self.child else {
+            return Ok(());
+        };
+        if unsafe { libc::kill(-(child.id() as libc::pid_t), signal) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
     pub fn receive_blocks(&mut self) -> Result<Option<Vec<Block>>> {
+        let old_len = self.buf.len();
         match read_to_vec(&self.output, &mut self.buf) {
             Ok(0) => bail!("status command exited"),
-            Ok(_n) => (),
+            Ok(_n) => {
+                if let Some(recorder) = &mut self.recorder {
+                    if let Err(e) = recorder.record(&self.buf[old_len..]) {
+                        warn!("failed to record status command output: {e}");
+                        self.recorder = None;
+                    }
+                }
+            }
             Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
             Err(e) => bail!(e),
         }
@@ -49,14 +146,47 @@ impl StatusCmd {
         let used = self.buf.len() - rem.len();
         self.buf.drain(..used);
 
-        Ok(self.protocol.get_blocks())
+        Ok(self
+            .protocol
+            .get_blocks(self.plain_text_separator.as_deref()))
+    }
+
+    /// Send `signal` to the command itself, but not to the processes it spawned.
+    pub fn send_signal(&self, signal: libc::c_int) -> io::Result<()> {
+        let Some(child) = &self.child else {
+            return Ok(());
+        };
+        if unsafe { libc::kill(child.id() as libc::pid_t, signal) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
     }
 
     pub fn send_click_event(&mut self, event: &Event) -> Result<()> {
-        if self.protocol.supports_clicks() {
-            serde_json::to_writer(&mut self.input, event)?;
-            self.input.write_all(b"\n")?;
-            self.input.flush()?;
+        if let Some(cmd) = event
+            .name
+            .and_then(|name| self.protocol.lemonbar_action(name, event.button))
+        {
+            debug!("running lemonbar command: {cmd}");
+            spawn_command(cmd)?;
+        } else if let Some(input) = &mut self.input {
+            if self.protocol.supports_clicks() {
+                serde_json::to_writer(&mut *input, event)?;
+                input.write_all(b"\n")?;
+                input.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a drop or a hover event. Unlike clicks, they never run lemonbar actions.
+    pub fn send_event(&mut self, event: &Event) -> Result<()> {
+        if let Some(input) = &mut self.input {
+            if self.protocol.supports_clicks() {
+                serde_json::to_writer(&mut *input, event)?;
+                input.write_all(b"\n")?;
+                input.flush()?;
+            }
         }
         Ok(())
     }
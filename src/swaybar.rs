@@ -0,0 +1,154 @@
+//! Swaybar compatibility: read the bar configuration from sway.
+//!
+//! When sway starts a bar with `swaybar_command i3bar-river`, it passes `-b <bar_id>`. The bar's
+//! settings are then requested over i3 IPC with `GET_BAR_CONFIG` and applied on top of the
+//! configuration file.
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::config::{BarMode, Config, Font, Layer, Position};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const GET_BAR_CONFIG: u32 = 6;
+
+#[derive(Debug, Deserialize)]
+pub struct BarConfig {
+    mode: Option<String>,
+    position: Option<Position>,
+    status_command: Option<String>,
+    font: Option<String>,
+    gaps: Option<Gaps>,
+    bar_height: Option<u32>,
+    workspace_buttons: Option<bool>,
+    binding_mode_indicator: Option<bool>,
+    outputs: Option<Vec<String>>,
+    #[serde(default)]
+    colors: Colors,
+}
+
+#[derive(Debug, Deserialize)]
+struct Gaps {
+    top: i32,
+    right: i32,
+    bottom: i32,
+    left: i32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Colors {
+    background: Option<Color>,
+    statusline: Option<Color>,
+    separator: Option<Color>,
+    focused_workspace_bg: Option<Color>,
+    focused_workspace_text: Option<Color>,
+    inactive_workspace_bg: Option<Color>,
+    inactive_workspace_text: Option<Color>,
+    urgent_workspace_bg: Option<Color>,
+    urgent_workspace_text: Option<Color>,
+}
+
+impl BarConfig {
+    /// Request the configuration of the bar `bar_id` from sway.
+    pub fn query(bar_id: &str) -> Result<Self> {
+        let reply: serde_json::Value = ipc_request(GET_BAR_CONFIG, bar_id)?;
+        if let Some(error) = reply.get("error").and_then(|e| e.as_str()) {
+            bail!("sway: {error}");
+        }
+        serde_json::from_value(reply).context("Failed to deserialize sway bar config")
+    }
+
+    pub fn apply(self, config: &mut Config) {
+        if self.status_command.is_some() {
+            config.command = self.status_command;
+        }
+        if let Some(position) = self.position {
+            config.position = position;
+        }
+        match self.mode.as_deref() {
+            Some("overlay") => {
+                config.layer = Layer::Overlay;
+                config.mode = BarMode::Dock;
+            }
+            Some(mode) => {
+                if let Ok(mode) = mode.parse() {
+                    config.mode = mode;
+                }
+            }
+            None => (),
+        }
+        if let Some(font) = self.font {
+            config.font = Font::new(font.strip_prefix("pango:").unwrap_or(&font));
+        }
+        if let Some(gaps) = self.gaps {
+            config.margin_top = gaps.top;
+            config.margin_right = gaps.right;
+            config.margin_bottom = gaps.bottom;
+            config.margin_left = gaps.left;
+        }
+        if let Some(height) = self.bar_height.filter(|&h| h > 0) {
+            config.height = height;
+        }
+        if let Some(show_tags) = self.workspace_buttons {
+            config.show_tags = show_tags;
+        }
+        if let Some(show_mode) = self.binding_mode_indicator {
+            config.show_mode = show_mode;
+        }
+        if let Some(outputs) = self.outputs {
+            if !outputs.is_empty() && !outputs.iter().any(|o| o == "*") {
+                config.only_outputs = Some(outputs);
+            }
+        }
+
+        let colors = self.colors;
+        let set = |dst: &mut Color, src: Option<Color>| {
+            if let Some(src) = src {
+                *dst = src;
+            }
+        };
+        set(&mut config.background, colors.background);
+        set(&mut config.color, colors.statusline);
+        set(&mut config.separator, colors.separator);
+        set(&mut config.tag_focused_bg, colors.focused_workspace_bg);
+        set(&mut config.tag_focused_fg, colors.focused_workspace_text);
+        set(&mut config.tag_bg, colors.inactive_workspace_bg);
+        set(&mut config.tag_fg, colors.inactive_workspace_text);
+        set(&mut config.tag_inactive_bg, colors.inactive_workspace_bg);
+        set(&mut config.tag_inactive_fg, colors.inactive_workspace_text);
+        set(&mut config.tag_urgent_bg, colors.urgent_workspace_bg);
+        set(&mut config.tag_urgent_fg, colors.urgent_workspace_text);
+    }
+}
+
+fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("SWAYSOCK")
+        .or_else(|| std::env::var_os("I3SOCK"))
+        .map(PathBuf::from)
+}
+
+fn ipc_request<T: serde::de::DeserializeOwned>(msg_type: u32, payload: &str) -> Result<T> {
+    let path = socket_path().context("SWAYSOCK is not set")?;
+    let mut sock = UnixStream::connect(path).context("Failed to connect to sway")?;
+
+    let mut msg = Vec::with_capacity(14 + payload.len());
+    msg.extend_from_slice(MAGIC);
+    msg.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(&msg_type.to_ne_bytes());
+    msg.extend_from_slice(payload.as_bytes());
+    sock.write_all(&msg)?;
+
+    let mut header = [0u8; 14];
+    sock.read_exact(&mut header)?;
+    ensure!(&header[..6] == MAGIC, "invalid i3 ipc reply");
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let mut reply = vec![0; len as usize];
+    sock.read_exact(&mut reply)?;
+
+    serde_json::from_slice(&reply).context("Invalid i3 ipc reply")
+}
@@ -0,0 +1,139 @@
+//! The built-in `[builtin.sysinfo]` block: the CPU load, the memory use and optionally a
+//! temperature, read from `/proc` and `/sys/class/hwmon`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::SysInfoConfig;
+use crate::i3bar_protocol::Block;
+
+pub struct SysInfo {
+    temperature: bool,
+    sensor: Option<String>,
+    /// The busy and the total CPU time at the previous update, in clock ticks.
+    prev_cpu: (u64, u64),
+    block: Option<Block>,
+}
+
+impl SysInfo {
+    pub fn new(config: &SysInfoConfig) -> Self {
+        Self {
+            temperature: config.temperature,
+            sensor: config.sensor.clone(),
+            prev_cpu: (0, 0),
+            block: None,
+        }
+    }
+
+    pub fn update(&mut self) -> io::Result<()> {
+        // The first update shows the average load since boot
+        let (busy, total) = cpu_times()?;
+        let cpu = percent(
+            busy.saturating_sub(self.prev_cpu.0),
+            total.saturating_sub(self.prev_cpu.1),
+        );
+        self.prev_cpu = (busy, total);
+
+        let mut text = format!("CPU {cpu:.0}% MEM {:.0}%", memory_use()?);
+        if self.temperature {
+            match temperature(self.sensor.as_deref()) {
+                Some(temp) => text.push_str(&format!(" {temp:.0}°C")),
+                None => text.push_str(" --°C"),
+            }
+        }
+
+        self.block = Some(Block {
+            full_text: text.into(),
+            name: Some("sysinfo".into()),
+            separator: true,
+            separator_block_width: 9,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    pub fn block(&self) -> Option<&Block> {
+        self.block.as_ref()
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 / total as f64 * 100.0
+}
+
+/// The busy and the total time of all the CPUs since boot, from the first line of `/proc/stat`.
+fn cpu_times() -> io::Result<(u64, u64)> {
+    let stat = fs::read_to_string("/proc/stat")?;
+    let times: Vec<u64> = stat
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("cpu "))
+        .map(|line| {
+            line.split_whitespace()
+                .filter_map(|t| t.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    // user nice system idle iowait irq softirq steal
+    if times.len() < 5 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected format of /proc/stat",
+        ));
+    }
+    let total: u64 = times.iter().take(8).sum();
+    let idle = times[3] + times[4];
+    Ok((total - idle, total))
+}
+
+/// The percentage of the memory which is not available, from `/proc/meminfo`.
+fn memory_use() -> io::Result<f64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| {
+                value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+    };
+    match (field("MemTotal"), field("MemAvailable")) {
+        (Some(total), Some(available)) => Ok(percent(total.saturating_sub(available), total)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected format of /proc/meminfo",
+        )),
+    }
+}
+
+/// The first temperature of the hwmon device named `sensor`, or of the first device which has one.
+fn temperature(sensor: Option<&str>) -> Option<f64> {
+    let mut devices: Vec<_> = fs::read_dir("/sys/class/hwmon")
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    devices.sort();
+    devices.iter().find_map(|device| {
+        if let Some(sensor) = sensor {
+            let name = fs::read_to_string(device.join("name")).ok()?;
+            if name.trim() != sensor {
+                return None;
+            }
+        }
+        read_millidegrees(&device.join("temp1_input"))
+    })
+}
+
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    let millidegrees: i64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some(millidegrees as f64 / 1000.0)
+}
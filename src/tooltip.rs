@@ -0,0 +1,277 @@
+//! Popups showing the `tooltip` of the blocks, or the full text of the blocks which are shortened
+//! or cut off, using xdg-shell.
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use pangocairo::cairo;
+use wayrs_client::global::{Globals, GlobalsExt};
+use wayrs_client::{Connection, EventCtx};
+use wayrs_utils::shm_alloc::{BufferSpec, ShmAlloc};
+
+use crate::color::Color;
+use crate::protocol::*;
+use crate::state::State;
+use crate::text::{ComputedText, RenderOptions};
+
+type BlockId = (Option<Rc<str>>, Option<Rc<str>>);
+
+pub struct Tooltip {
+    wm_base: XdgWmBase,
+    wl_compositor: WlCompositor,
+    delay: Duration,
+    /// The block under the pointer, the surface of its bar and since when it is hovered.
+    hovered: Option<(WlSurface, BlockId, Instant)>,
+    timer_armed: bool,
+    popup: Option<Popup>,
+}
+
+/// What the popup shows, in logical pixels.
+pub struct Content {
+    pub text: ComputedText,
+    pub height: f64,
+    pub fg: Color,
+    pub bg: Color,
+    pub scale: u32,
+}
+
+struct Popup {
+    parent: WlSurface,
+    surface: WlSurface,
+    xdg_surface: XdgSurface,
+    xdg_popup: XdgPopup,
+    content: Content,
+}
+
+impl Tooltip {
+    pub fn bind(
+        conn: &mut Connection<State>,
+        globals: &Globals,
+        wl_compositor: WlCompositor,
+        delay: Duration,
+    ) -> Option<Self> {
+        Some(Self {
+            wm_base: globals.bind_with_cb(conn, 1..=6, wm_base_cb).ok()?,
+            wl_compositor,
+            delay,
+            hovered: None,
+            timer_armed: false,
+            popup: None,
+        })
+    }
+
+    /// The pointer moved over `block` of the bar of `surface`, or off the truncated blocks.
+    pub fn hover(
+        &mut self,
+        conn: &mut Connection<State>,
+        surface: WlSurface,
+        block: Option<&BlockId>,
+    ) {
+        let current = self
+            .hovered
+            .as_ref()
+            .filter(|(s, _, _)| *s == surface)
+            .map(|(_, b, _)| b);
+        if current == block {
+            return;
+        }
+        self.close(conn);
+        self.hovered = block.map(|block| (surface, block.clone(), Instant::now()));
+    }
+
+    /// How long to wait before showing the popup, if a timer is needed.
+    pub fn timer_needed(&mut self) -> Option<Duration> {
+        let (_, _, since) = self.hovered.as_ref()?;
+        if self.timer_armed || self.popup.is_some() {
+            return None;
+        }
+        self.timer_armed = true;
+        Some(self.delay.saturating_sub(since.elapsed()))
+    }
+
+    /// The hovered block, once it has been hovered long enough and its popup is not shown yet.
+    pub fn due(&mut self) -> Option<(WlSurface, BlockId)> {
+        self.timer_armed = false;
+        let (surface, block, since) = self.hovered.as_ref()?;
+        (self.popup.is_none() && since.elapsed() >= self.delay).then(|| (*surface, block.clone()))
+    }
+
+    /// Show `content` under or above `anchor`, the `(x, y, width, height)` of the block on `parent`.
+    pub fn show(
+        &mut self,
+        conn: &mut Connection<State>,
+        parent: (WlSurface, ZwlrLayerSurfaceV1),
+        anchor: (f64, f64, f64, f64),
+        below: bool,
+        content: Content,
+    ) {
+        self.close(conn);
+
+        let surface = self.wl_compositor.create_surface(conn);
+        // The pointer goes through the popup, so that it does not steal the hover
+        let region = self.wl_compositor.create_region(conn);
+        surface.set_input_region(conn, Some(region));
+        region.destroy(conn);
+
+        let positioner = self.wm_base.create_positioner(conn);
+        positioner.set_size(
+            conn,
+            content.text.width.ceil() as i32,
+            content.height.ceil() as i32,
+        );
+        let (x, y, width, height) = anchor;
+        positioner.set_anchor_rect(
+            conn,
+            x.round() as i32,
+            y.round() as i32,
+            (width.round() as i32).max(1),
+            (height.round() as i32).max(1),
+        );
+        let (anchor, gravity) = if below {
+            (
+                xdg_positioner::Anchor::Bottom,
+                xdg_positioner::Gravity::Bottom,
+            )
+        } else {
+            (xdg_positioner::Anchor::Top, xdg_positioner::Gravity::Top)
+        };
+        positioner.set_anchor(conn, anchor);
+        positioner.set_gravity(conn, gravity);
+        positioner.set_constraint_adjustment(
+            conn,
+            xdg_positioner::ConstraintAdjustment::SlideX
+                | xdg_positioner::ConstraintAdjustment::FlipY,
+        );
+
+        let xdg_surface = self
+            .wm_base
+            .get_xdg_surface_with_cb(conn, surface, xdg_surface_cb);
+        let xdg_popup = xdg_surface.get_popup_with_cb(conn, None, positioner, xdg_popup_cb);
+        positioner.destroy(conn);
+        parent.1.get_popup(conn, xdg_popup);
+        surface.commit(conn);
+
+        self.popup = Some(Popup {
+            parent: parent.0,
+            surface,
+            xdg_surface,
+            xdg_popup,
+            content,
+        });
+    }
+
+    /// Close the popup, if any.
+    pub fn close(&mut self, conn: &mut Connection<State>) {
+        if let Some(popup) = self.popup.take() {
+            popup.xdg_popup.destroy(conn);
+            popup.xdg_surface.destroy(conn);
+            popup.surface.destroy(conn);
+        }
+    }
+
+    /// Forget the bar of `surface`, which is hidden or destroyed.
+    pub fn bar_gone(&mut self, conn: &mut Connection<State>, surface: WlSurface) {
+        if self.popup.as_ref().is_some_and(|p| p.parent == surface) {
+            self.close(conn);
+        }
+        if self.hovered.as_ref().is_some_and(|(s, _, _)| *s == surface) {
+            self.hovered = None;
+        }
+    }
+}
+
+impl Popup {
+    fn draw(&self, conn: &mut Connection<State>, shm: &mut ShmAlloc) {
+        let content = &self.content;
+        let width = content.text.width.ceil() as u32;
+        let height = content.height.ceil() as u32;
+        let (pix_width, pix_height) = (width * content.scale, height * content.scale);
+
+        let (buffer, canvas) = shm
+            .alloc_buffer(
+                conn,
+                BufferSpec {
+                    width: pix_width,
+                    height: pix_height,
+                    stride: pix_width * 4,
+                    format: wl_shm::Format::Argb8888,
+                },
+            )
+            .unwrap();
+
+        let cairo_surf = unsafe {
+            cairo::ImageSurface::create_for_data_unsafe(
+                canvas.as_mut_ptr(),
+                cairo::Format::ARgb32,
+                pix_width as i32,
+                pix_height as i32,
+                pix_width as i32 * 4,
+            )
+            .expect("cairo surface")
+        };
+        let cairo_ctx = cairo::Context::new(&cairo_surf).expect("cairo context");
+        cairo_ctx.scale(content.scale as f64, content.scale as f64);
+        cairo_ctx.set_operator(cairo::Operator::Source);
+        content.bg.apply(&cairo_ctx);
+        cairo_ctx.paint().unwrap();
+        cairo_ctx.set_operator(cairo::Operator::Over);
+        content.text.render(
+            &cairo_ctx,
+            RenderOptions {
+                x_offset: 0.0,
+                bar_height: content.height,
+                fg_color: content.fg,
+                bg_color: None,
+                r_left: 0.0,
+                r_right: 0.0,
+                overlap: 0.0,
+                progress: None,
+            },
+        );
+        drop(cairo_ctx);
+
+        self.surface.set_buffer_scale(conn, content.scale as i32);
+        self.surface
+            .attach(conn, Some(buffer.into_wl_buffer()), 0, 0);
+        self.surface.damage(conn, 0, 0, i32::MAX, i32::MAX);
+        self.surface.commit(conn);
+    }
+}
+
+fn wm_base_cb(ctx: EventCtx<State, XdgWmBase>) {
+    if let xdg_wm_base::Event::Ping(serial) = ctx.event {
+        ctx.proxy.pong(ctx.conn, serial);
+    }
+}
+
+fn xdg_surface_cb(ctx: EventCtx<State, XdgSurface>) {
+    let xdg_surface::Event::Configure(serial) = ctx.event else {
+        return;
+    };
+    ctx.proxy.ack_configure(ctx.conn, serial);
+    let ss = &mut ctx.state.shared_state;
+    if let Some(popup) = ss
+        .tooltip
+        .as_ref()
+        .and_then(|t| t.popup.as_ref())
+        .filter(|p| p.xdg_surface == ctx.proxy)
+    {
+        popup.draw(ctx.conn, &mut ss.shm);
+    }
+}
+
+fn xdg_popup_cb(ctx: EventCtx<State, XdgPopup>) {
+    if let xdg_popup::Event::PopupDone = ctx.event {
+        if let Some(tooltip) = &mut ctx.state.shared_state.tooltip {
+            if tooltip
+                .popup
+                .as_ref()
+                .is_some_and(|p| p.xdg_popup == ctx.proxy)
+            {
+                // Dismissed by the compositor, do not show it again for the same block
+                tooltip.close(ctx.conn);
+                tooltip.hovered = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,755 @@
+//! A StatusNotifierItem tray, configured with `tray`.
+//!
+//! The bar is a StatusNotifierHost. It owns `org.kde.StatusNotifierWatcher` if no other process
+//! does, otherwise it registers with the running watcher. The session bus is read from the event
+//! loop, and the requests to the items never wait for a reply.
+//!
+//! The icons come from the `IconPixmap` of the items, or from the files of their `IconName` in
+//! their `IconThemePath` or the icon themes, see src/icon_theme.rs. The icons are chosen, and SVG
+//! icons rasterized, for the size they are drawn at in pixels, so every output scale gets its own.
+//! Items without an icon are shown as the first letter of their title. Passive items are not
+//! shown, and menus (`com.canonical.dbusmenu`) are not supported: right click sends `ContextMenu`,
+//! which some items ignore.
+
+use std::os::fd::RawFd;
+
+use anyhow::Result;
+use pangocairo::cairo;
+
+use crate::button_manager::ButtonManager;
+use crate::color::Color;
+use crate::pointer_btn::PointerBtn;
+
+#[cfg(feature = "tray")]
+pub use imp::Tray;
+
+#[cfg(not(feature = "tray"))]
+pub struct Tray;
+
+#[cfg(not(feature = "tray"))]
+impl Tray {
+    pub fn connect(_icon_theme: Option<&str>) -> Result<Self> {
+        bail!("the tray is enabled, but i3bar-river was built without the tray feature")
+    }
+
+    pub fn fd(&self) -> RawFd {
+        unreachable!()
+    }
+
+    /// Returns whether the icons have changed.
+    pub fn dispatch(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    pub fn width(&self, _height: f64) -> f64 {
+        0.0
+    }
+
+    pub fn render(
+        &self,
+        _context: &cairo::Context,
+        _options: RenderOptions,
+        _btns: &mut ButtonManager<u32>,
+    ) {
+    }
+
+    pub fn click(&mut self, _id: u32, _btn: PointerBtn) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Where and how [`Tray::render`] draws the icons.
+#[cfg_attr(not(feature = "tray"), allow(dead_code))]
+pub struct RenderOptions<'a> {
+    /// The left edge of the tray.
+    pub x_offset: f64,
+    pub bar_height: f64,
+    /// The space around each icon.
+    pub padding: f64,
+    /// The scale of the buffer, the icons are chosen for their size in pixels.
+    pub scale: f64,
+    /// The font and the color of the letters shown for the items without an icon.
+    pub font: &'a pangocairo::pango::FontDescription,
+    pub fg_color: Color,
+}
+
+#[cfg(feature = "tray")]
+mod imp {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::path::{Path as FsPath, PathBuf};
+    use std::time::Duration;
+
+    use anyhow::Context;
+    use dbus::arg::{prop_cast, PropMap, RefArg, Variant};
+    use dbus::channel::{BusType, Channel};
+    use dbus::message::MessageType;
+    use dbus::strings::{BusName, ErrorName, Interface, Member};
+    use dbus::{Message, Path};
+
+    use super::*;
+    use crate::blocks_cache::load_svg;
+    use crate::icon_theme::IconThemes;
+    use crate::text::{Align, Attributes, ComputedText};
+
+    const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+    const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+    const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+    const ITEM_PATH: &str = "/StatusNotifierItem";
+    const DBUS_NAME: &str = "org.freedesktop.DBus";
+    const DBUS_PATH: &str = "/org/freedesktop/DBus";
+    const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+    const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+
+    /// What the bar implements of the watcher, for the clients which introspect it.
+    const WATCHER_INTROSPECTION: &str = r#"<node>
+  <interface name="org.kde.StatusNotifierWatcher">
+    <method name="RegisterStatusNotifierItem"><arg name="service" type="s" direction="in"/></method>
+    <method name="RegisterStatusNotifierHost"><arg name="service" type="s" direction="in"/></method>
+    <property name="RegisteredStatusNotifierItems" type="as" access="read"/>
+    <property name="IsStatusNotifierHostRegistered" type="b" access="read"/>
+    <property name="ProtocolVersion" type="i" access="read"/>
+    <signal name="StatusNotifierItemRegistered"><arg type="s"/></signal>
+    <signal name="StatusNotifierItemUnregistered"><arg type="s"/></signal>
+    <signal name="StatusNotifierHostRegistered"/>
+  </interface>
+</node>"#;
+
+    /// How long to wait for the bus during [`Tray::connect`].
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// The pixmaps of the items larger than this are ignored.
+    const MAX_PIXMAP_SIZE: i64 = 1024;
+
+    /// The size in pixels at which the icons named by the items are checked to exist.
+    const ICON_CHECK_SIZE: u32 = 24;
+
+    pub struct Tray {
+        channel: Channel,
+        /// Whether the bar owns the watcher name, rather than being registered with another watcher.
+        is_watcher: bool,
+        items: Vec<Item>,
+        next_id: u32,
+        /// The requests waiting for a reply, by serial.
+        pending: HashMap<u32, Request>,
+        themes: IconThemes,
+    }
+
+    enum Request {
+        /// `RegisteredStatusNotifierItems` of another watcher.
+        Items,
+        /// The properties of an item.
+        Properties(u32),
+        /// The unique name of the bus name of an item.
+        Owner(u32),
+    }
+
+    struct Item {
+        id: u32,
+        /// As listed by the watcher: a bus name, optionally followed by an object path.
+        service: String,
+        bus: BusName<'static>,
+        path: Path<'static>,
+        /// The unique name of `bus`, which sends the signals.
+        owner: Option<String>,
+        title: String,
+        passive: bool,
+        is_menu: bool,
+        icon: Option<Icon>,
+        /// `icon` for the sizes it was drawn at, in pixels.
+        rasterized: RefCell<HashMap<u32, Option<cairo::ImageSurface>>>,
+    }
+
+    enum Icon {
+        /// From `IconPixmap`, smallest first.
+        Pixmaps(Vec<cairo::ImageSurface>),
+        /// `IconName`, looked up in `IconThemePath` and the icon themes.
+        Named {
+            name: String,
+            theme_path: Option<PathBuf>,
+        },
+    }
+
+    impl Tray {
+        /// Connect to the session bus. The items name their icons in `icon_theme`, or hicolor.
+        pub fn connect(icon_theme: Option<&str>) -> Result<Self> {
+            let mut channel = Channel::get_private(BusType::Session)
+                .context("failed to connect to the session bus")?;
+            channel.set_watch_enabled(true);
+
+            let host_name = format!("org.kde.StatusNotifierHost-{}", std::process::id());
+            request_name(&channel, &host_name)?;
+            let is_watcher = request_name(&channel, WATCHER_NAME)?;
+
+            let mut this = Self {
+                channel,
+                is_watcher,
+                items: Vec::new(),
+                next_id: 0,
+                pending: HashMap::new(),
+                themes: IconThemes::new(icon_theme),
+            };
+            this.add_match("type='signal',sender='org.freedesktop.DBus',member='NameOwnerChanged'");
+            this.add_match("type='signal',interface='org.kde.StatusNotifierItem'");
+
+            if is_watcher {
+                debug!("tray: acting as the StatusNotifierWatcher");
+                this.emit("StatusNotifierHostRegistered", ());
+            } else {
+                debug!("tray: registering with the running StatusNotifierWatcher");
+                this.add_match(
+                    "type='signal',sender='org.kde.StatusNotifierWatcher',\
+                     interface='org.kde.StatusNotifierWatcher'",
+                );
+                let register = Message::call_with_args(
+                    WATCHER_NAME,
+                    WATCHER_PATH,
+                    WATCHER_NAME,
+                    "RegisterStatusNotifierHost",
+                    (host_name.as_str(),),
+                );
+                this.channel
+                    .send_with_reply_and_block(register, TIMEOUT)
+                    .context("failed to register with the StatusNotifierWatcher")?;
+                let get = Message::call_with_args(
+                    WATCHER_NAME,
+                    WATCHER_PATH,
+                    PROPERTIES_INTERFACE,
+                    "Get",
+                    (WATCHER_NAME, "RegisteredStatusNotifierItems"),
+                );
+                this.send(get, Some(Request::Items));
+            }
+            this.channel.flush();
+
+            Ok(this)
+        }
+
+        pub fn fd(&self) -> RawFd {
+            self.channel.watch().fd
+        }
+
+        /// Handle the messages received from the bus. Returns whether the icons have changed.
+        pub fn dispatch(&mut self) -> Result<bool> {
+            self.channel
+                .read_write(Some(Duration::ZERO))
+                .map_err(|()| anyhow!("disconnected from the session bus"))?;
+            let mut changed = false;
+            while let Some(msg) = self.channel.pop_message() {
+                changed |= match msg.msg_type() {
+                    MessageType::MethodCall => self.handle_call(&msg),
+                    MessageType::MethodReturn => self.handle_reply(&msg),
+                    MessageType::Signal => self.handle_signal(&msg),
+                    MessageType::Error => {
+                        if let Some(serial) = msg.get_reply_serial() {
+                            self.pending.remove(&serial);
+                        }
+                        debug!("tray: {:?}", msg.read1::<&str>().unwrap_or_default());
+                        false
+                    }
+                };
+            }
+            self.channel.flush();
+            Ok(changed)
+        }
+
+        fn shown(&self) -> impl Iterator<Item = &Item> {
+            self.items.iter().filter(|item| !item.passive)
+        }
+
+        pub fn width(&self, height: f64) -> f64 {
+            self.shown().count() as f64 * height
+        }
+
+        pub fn render(
+            &self,
+            context: &cairo::Context,
+            options: RenderOptions,
+            btns: &mut ButtonManager<u32>,
+        ) {
+            let size = options.bar_height - options.padding * 2.0;
+            let pixels = (size * options.scale).round();
+            let mut x = options.x_offset;
+            for item in self.shown() {
+                btns.push(x, options.bar_height, item.id);
+                let icon = if pixels >= 1.0 {
+                    item.icon_for(pixels as u32, &self.themes)
+                } else {
+                    None
+                };
+                match icon {
+                    Some(icon) => {
+                        let scale = size / icon.width().max(icon.height()) as f64;
+                        context.save().unwrap();
+                        context.translate(x + options.padding, options.padding);
+                        context.scale(scale, scale);
+                        context.set_source_surface(&icon, 0.0, 0.0).unwrap();
+                        context.source().set_filter(cairo::Filter::Good);
+                        context.paint().unwrap();
+                        context.restore().unwrap();
+                    }
+                    _ => {
+                        let letter: String = item.title.chars().take(1).collect();
+                        let text = ComputedText::new(
+                            &letter,
+                            Attributes {
+                                font: options.font,
+                                padding_left: 0.0,
+                                padding_right: 0.0,
+                                min_width: Some(options.bar_height),
+                                align: Align::Center,
+                                markup: false,
+                            },
+                        );
+                        text.render(
+                            context,
+                            crate::text::RenderOptions {
+                                x_offset: x,
+                                bar_height: options.bar_height,
+                                fg_color: options.fg_color,
+                                bg_color: None,
+                                r_left: 0.0,
+                                r_right: 0.0,
+                                overlap: 0.0,
+                                progress: None,
+                            },
+                        );
+                    }
+                }
+                x += options.bar_height;
+            }
+        }
+
+        /// Left click activates the item (or opens its menu), middle click sends
+        /// `SecondaryActivate`, right click `ContextMenu` and the wheel `Scroll`.
+        pub fn click(&mut self, id: u32, btn: PointerBtn) -> Result<()> {
+            let Some(item) = self.items.iter().find(|item| item.id == id) else {
+                return Ok(());
+            };
+            let call = |method: &str| {
+                Message::new_method_call(
+                    item.bus.clone(),
+                    item.path.clone(),
+                    ITEM_INTERFACE,
+                    method,
+                )
+                .unwrap()
+            };
+            // The position of the pointer on the screen is unknown
+            let mut msg = match btn {
+                PointerBtn::Left if item.is_menu => call("ContextMenu").append2(0i32, 0i32),
+                PointerBtn::Left => call("Activate").append2(0i32, 0i32),
+                PointerBtn::Middle => call("SecondaryActivate").append2(0i32, 0i32),
+                PointerBtn::Right => call("ContextMenu").append2(0i32, 0i32),
+                PointerBtn::WheelUp => call("Scroll").append2(-1i32, "vertical"),
+                PointerBtn::WheelDown => call("Scroll").append2(1i32, "vertical"),
+                _ => return Ok(()),
+            };
+            debug!("tray: {:?} of {}", msg.member(), item.service);
+            msg.set_no_reply(true);
+            self.send(msg, None);
+            self.channel.flush();
+            Ok(())
+        }
+
+        fn send(&mut self, msg: Message, request: Option<Request>) {
+            match self.channel.send(msg) {
+                Ok(serial) => {
+                    if let Some(request) = request {
+                        self.pending.insert(serial, request);
+                    }
+                }
+                Err(()) => warn!("tray: failed to send a message"),
+            }
+        }
+
+        fn add_match(&mut self, rule: &str) {
+            let msg = Message::call_with_args(DBUS_NAME, DBUS_PATH, DBUS_NAME, "AddMatch", (rule,));
+            self.send(msg, None);
+        }
+
+        /// Emit a signal of the watcher.
+        fn emit(&mut self, name: &'static str, args: impl dbus::arg::AppendAll) {
+            let mut msg = Message::signal(
+                &Path::from(WATCHER_PATH),
+                &Interface::from(WATCHER_NAME),
+                &Member::from(name),
+            );
+            args.append(&mut dbus::arg::IterAppend::new(&mut msg));
+            self.send(msg, None);
+        }
+
+        /// Track the item `service`. `sender` is the bus name of the item if it registered itself
+        /// with just an object path.
+        fn add_item(&mut self, service: &str, sender: Option<&str>) -> Option<String> {
+            let (bus, path, service) = match service.find('/') {
+                Some(0) => (sender?, service, format!("{}{service}", sender?)),
+                Some(i) => (&service[..i], &service[i..], service.to_owned()),
+                None => (service, ITEM_PATH, service.to_owned()),
+            };
+            if self.items.iter().any(|item| item.service == service) {
+                return None;
+            }
+            let (Ok(bus), Ok(path)) = (BusName::new(bus.to_owned()), Path::new(path.to_owned()))
+            else {
+                debug!("tray: invalid item {service:?}");
+                return None;
+            };
+            debug!("tray: new item {service}");
+
+            let id = self.next_id;
+            self.next_id += 1;
+            let owner = bus.starts_with(':').then(|| String::from(&*bus));
+            if owner.is_none() {
+                let msg = Message::call_with_args(
+                    DBUS_NAME,
+                    DBUS_PATH,
+                    DBUS_NAME,
+                    "GetNameOwner",
+                    (&*bus,),
+                );
+                self.send(msg, Some(Request::Owner(id)));
+            }
+            self.items.push(Item {
+                id,
+                service: service.clone(),
+                bus,
+                path,
+                owner,
+                title: String::new(),
+                passive: true,
+                is_menu: false,
+                icon: None,
+                rasterized: RefCell::default(),
+            });
+            self.request_properties(id);
+            Some(service)
+        }
+
+        /// Forget the items which match `f`. Returns whether any item was removed.
+        fn remove_items(&mut self, f: impl Fn(&Item) -> bool) -> bool {
+            let (removed, kept): (Vec<Item>, Vec<Item>) =
+                std::mem::take(&mut self.items).into_iter().partition(f);
+            self.items = kept;
+            for item in &removed {
+                debug!("tray: item {} removed", item.service);
+                if self.is_watcher {
+                    self.emit("StatusNotifierItemUnregistered", (item.service.as_str(),));
+                }
+            }
+            !removed.is_empty()
+        }
+
+        fn request_properties(&mut self, id: u32) {
+            let Some(item) = self.items.iter().find(|item| item.id == id) else {
+                return;
+            };
+            let msg = Message::new_method_call(
+                item.bus.clone(),
+                item.path.clone(),
+                PROPERTIES_INTERFACE,
+                "GetAll",
+            )
+            .unwrap()
+            .append1(ITEM_INTERFACE);
+            self.send(msg, Some(Request::Properties(id)));
+        }
+
+        fn handle_reply(&mut self, msg: &Message) -> bool {
+            let Some(request) = msg
+                .get_reply_serial()
+                .and_then(|serial| self.pending.remove(&serial))
+            else {
+                return false;
+            };
+            match request {
+                Request::Items => {
+                    let Ok(Variant(services)) = msg.read1::<Variant<Vec<String>>>() else {
+                        return false;
+                    };
+                    for service in services {
+                        self.add_item(&service, None);
+                    }
+                    false
+                }
+                Request::Owner(id) => {
+                    if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+                        item.owner = msg.read1::<String>().ok();
+                    }
+                    false
+                }
+                Request::Properties(id) => {
+                    let Some(item) = self.items.iter_mut().find(|item| item.id == id) else {
+                        return false;
+                    };
+                    match msg.read1::<PropMap>() {
+                        Ok(properties) => {
+                            item.update(&properties, &self.themes);
+                            true
+                        }
+                        Err(e) => {
+                            debug!("tray: invalid properties of {}: {e}", item.service);
+                            false
+                        }
+                    }
+                }
+            }
+        }
+
+        fn handle_signal(&mut self, msg: &Message) -> bool {
+            let interface = msg.interface();
+            let member = msg.member();
+            match (interface.as_deref(), member.as_deref()) {
+                (Some(DBUS_NAME), Some("NameOwnerChanged")) => {
+                    let Ok((name, _, new_owner)) = msg.read3::<&str, &str, &str>() else {
+                        return false;
+                    };
+                    if !new_owner.is_empty() {
+                        return false;
+                    }
+                    if name == WATCHER_NAME && !self.is_watcher {
+                        warn!("tray: the StatusNotifierWatcher has exited");
+                    }
+                    self.remove_items(|item| {
+                        &*item.bus == name || item.owner.as_deref() == Some(name)
+                    })
+                }
+                (Some(WATCHER_NAME), Some("StatusNotifierItemRegistered")) if !self.is_watcher => {
+                    if let Ok(service) = msg.read1::<&str>() {
+                        self.add_item(service, None);
+                    }
+                    false
+                }
+                (Some(WATCHER_NAME), Some("StatusNotifierItemUnregistered"))
+                    if !self.is_watcher =>
+                {
+                    let Ok(service) = msg.read1::<&str>() else {
+                        return false;
+                    };
+                    self.remove_items(|item| item.service == service)
+                }
+                (Some(ITEM_INTERFACE), Some(_)) => {
+                    // NewIcon, NewStatus, NewTitle and so on: fetch all the properties again
+                    let sender = msg.sender();
+                    let path = msg.path();
+                    let ids: Vec<u32> = self
+                        .items
+                        .iter()
+                        .filter(|item| item.owner.as_deref() == sender.as_deref())
+                        .filter(|item| Some(&item.path) == path.as_ref())
+                        .map(|item| item.id)
+                        .collect();
+                    for id in ids {
+                        self.request_properties(id);
+                    }
+                    false
+                }
+                _ => false,
+            }
+        }
+
+        /// Implement the watcher, if the bar owns its name.
+        fn handle_call(&mut self, msg: &Message) -> bool {
+            let interface = msg.interface();
+            let member = msg.member();
+            let reply = match (interface.as_deref(), member.as_deref()) {
+                _ if !self.is_watcher || msg.path().as_deref() != Some(WATCHER_PATH) => None,
+                (Some(WATCHER_NAME), Some("RegisterStatusNotifierItem")) => {
+                    let sender = msg.sender();
+                    if let Ok(service) = msg.read1::<&str>() {
+                        if let Some(service) = self.add_item(service, sender.as_deref()) {
+                            self.emit("StatusNotifierItemRegistered", (service.as_str(),));
+                        }
+                    }
+                    Some(msg.method_return())
+                }
+                (Some(WATCHER_NAME), Some("RegisterStatusNotifierHost")) => {
+                    Some(msg.method_return())
+                }
+                (Some(PROPERTIES_INTERFACE), Some("Get")) => msg
+                    .read2::<&str, &str>()
+                    .ok()
+                    .filter(|(interface, _)| *interface == WATCHER_NAME)
+                    .and_then(|(_, name)| self.watcher_property(name))
+                    .map(|value| msg.method_return().append1(Variant(value))),
+                (Some(PROPERTIES_INTERFACE), Some("GetAll")) => {
+                    let properties: PropMap = [
+                        "RegisteredStatusNotifierItems",
+                        "IsStatusNotifierHostRegistered",
+                        "ProtocolVersion",
+                    ]
+                    .into_iter()
+                    .filter_map(|name| {
+                        Some((name.to_owned(), Variant(self.watcher_property(name)?)))
+                    })
+                    .collect();
+                    Some(msg.method_return().append1(properties))
+                }
+                (Some(INTROSPECTABLE_INTERFACE), Some("Introspect")) => {
+                    Some(msg.method_return().append1(WATCHER_INTROSPECTION))
+                }
+                _ => None,
+            };
+            let reply = reply.unwrap_or_else(|| {
+                msg.error(
+                    &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+                    c"Unknown method",
+                )
+            });
+            if !msg.get_no_reply() {
+                self.send(reply, None);
+            }
+            false
+        }
+
+        fn watcher_property(&self, name: &str) -> Option<Box<dyn RefArg>> {
+            match name {
+                "RegisteredStatusNotifierItems" => {
+                    let services: Vec<String> =
+                        self.items.iter().map(|item| item.service.clone()).collect();
+                    Some(Box::new(services))
+                }
+                "IsStatusNotifierHostRegistered" => Some(Box::new(true)),
+                "ProtocolVersion" => Some(Box::new(0i32)),
+                _ => None,
+            }
+        }
+    }
+
+    impl Item {
+        fn update(&mut self, properties: &PropMap, themes: &IconThemes) {
+            let string =
+                |name: &str| prop_cast::<String>(properties, name).filter(|s| !s.is_empty());
+            self.title = string("Title")
+                .or_else(|| string("Id"))
+                .cloned()
+                .unwrap_or_default();
+            let status = string("Status").map_or("Active", String::as_str);
+            self.passive = status == "Passive";
+            self.is_menu = prop_cast::<bool>(properties, "ItemIsMenu").is_some_and(|&b| b);
+
+            let theme_path = string("IconThemePath").map(PathBuf::from);
+            let icon = |prefix: &str| {
+                let pixmaps = properties
+                    .get(&format!("{prefix}IconPixmap"))
+                    .map(|pixmaps| convert_pixmaps(&*pixmaps.0))
+                    .unwrap_or_default();
+                if !pixmaps.is_empty() {
+                    return Some(Icon::Pixmaps(pixmaps));
+                }
+                let name = string(&format!("{prefix}IconName"))?;
+                themes.find(name, ICON_CHECK_SIZE, theme_path.as_deref())?;
+                Some(Icon::Named {
+                    name: name.clone(),
+                    theme_path: theme_path.clone(),
+                })
+            };
+            self.icon = if status == "NeedsAttention" {
+                icon("Attention").or_else(|| icon(""))
+            } else {
+                icon("")
+            };
+            self.rasterized = RefCell::default();
+            if self.icon.is_none() {
+                debug!("tray: no icon for {}", self.service);
+            }
+        }
+
+        /// The icon to draw at `size` pixels: the smallest pixmap at least that large, or the
+        /// file of the closest size, rasterized at that size if it is an SVG file.
+        fn icon_for(&self, size: u32, themes: &IconThemes) -> Option<cairo::ImageSurface> {
+            self.rasterized
+                .borrow_mut()
+                .entry(size)
+                .or_insert_with(|| match self.icon.as_ref()? {
+                    Icon::Pixmaps(pixmaps) => pixmaps
+                        .iter()
+                        .find(|p| p.width().max(p.height()) as u32 >= size)
+                        .or(pixmaps.last())
+                        .cloned(),
+                    Icon::Named { name, theme_path } => {
+                        let path = themes.find(name, size, theme_path.as_deref())?;
+                        load_icon(&path, size)
+                    }
+                })
+                .clone()
+        }
+    }
+
+    /// Ask for `name` without queueing. Returns whether the bar is its owner.
+    fn request_name(channel: &Channel, name: &str) -> Result<bool> {
+        // DBUS_NAME_FLAG_DO_NOT_QUEUE
+        let msg =
+            Message::call_with_args(DBUS_NAME, DBUS_PATH, DBUS_NAME, "RequestName", (name, 4u32));
+        let reply = channel
+            .send_with_reply_and_block(msg, TIMEOUT)
+            .with_context(|| format!("failed to request the name {name}"))?;
+        // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+        Ok(reply.read1::<u32>()? == 1)
+    }
+
+    /// The valid `a(iiay)` pixmaps, converted from ARGB in network byte order, smallest first.
+    fn convert_pixmaps(pixmaps: &dyn RefArg) -> Vec<cairo::ImageSurface> {
+        let Some(pixmaps) = pixmaps.as_iter() else {
+            return Vec::new();
+        };
+        let mut surfaces: Vec<_> = pixmaps.filter_map(convert_pixmap).collect();
+        surfaces.sort_by_key(|s| s.width().max(s.height()));
+        surfaces
+    }
+
+    fn convert_pixmap(pixmap: &dyn RefArg) -> Option<cairo::ImageSurface> {
+        let mut fields = pixmap.as_iter()?;
+        let width = fields.next()?.as_i64()?;
+        let height = fields.next()?.as_i64()?;
+        if !(1..=MAX_PIXMAP_SIZE).contains(&width) || !(1..=MAX_PIXMAP_SIZE).contains(&height) {
+            return None;
+        }
+        let data: Vec<u8> = fields
+            .next()?
+            .as_iter()?
+            .filter_map(|byte| byte.as_u64())
+            .map(|byte| byte as u8)
+            .collect();
+        if (data.len() as i64) < width * height * 4 {
+            return None;
+        }
+
+        let mut surface =
+            cairo::ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32).ok()?;
+        let stride = surface.stride() as usize;
+        let mut dest = surface.data().ok()?;
+        for (i, pixel) in data
+            .chunks_exact(4)
+            .take((width * height) as usize)
+            .enumerate()
+        {
+            let (x, y) = (i % width as usize, i / width as usize);
+            let [a, r, g, b] = [pixel[0], pixel[1], pixel[2], pixel[3]].map(u32::from);
+            // Cairo expects premultiplied alpha
+            let premultiply = |c: u32| c * a / 255;
+            let value = a << 24 | premultiply(r) << 16 | premultiply(g) << 8 | premultiply(b);
+            let offset = y * stride + x * 4;
+            dest[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+        }
+        drop(dest);
+        Some(surface)
+    }
+
+    /// Load the PNG or SVG icon at `path`. SVG icons are rasterized at `size` pixels.
+    fn load_icon(path: &FsPath, size: u32) -> Option<cairo::ImageSurface> {
+        let loaded = if path.extension().is_some_and(|ext| ext == "svg") {
+            load_svg(&path.to_string_lossy(), size)
+        } else {
+            File::open(path)
+                .map_err(|e| e.to_string())
+                .and_then(|mut file| {
+                    cairo::ImageSurface::create_from_png(&mut file).map_err(|e| e.to_string())
+                })
+        };
+        loaded
+            .map_err(|e| debug!("tray: failed to load {}: {e}", path.display()))
+            .ok()
+    }
+}
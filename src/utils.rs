@@ -1,10 +1,10 @@
 //! Some usefull functions
 
-use std::io;
-use std::os::fd::{AsFd, AsRawFd};
-
-use serde::Deserialize;
-use serde_json::{Deserializer, Error as JsonError};
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
 
 /// Read from a raw file descriptor to the vector.
 ///
@@ -30,103 +30,61 @@ pub fn read_to_vec(fd: impl AsFd, buf: &mut Vec<u8>) -> io::Result<usize> {
     Ok(read)
 }
 
-/// Retuns (`last_line`, `remaining`). See tests for examples.
-pub fn last_line(s: &[u8]) -> Option<(&[u8], &[u8])> {
-    let mut it = memchr::memrchr_iter(b'\n', s);
-    let last = it.next()?;
-    let rem = &s[(last + 1)..];
-    if let Some(pre_last) = it.next() {
-        Some((&s[(pre_last + 1)..last], rem))
-    } else {
-        Some((&s[..last], rem))
+/// Create a pipe with the given flags. Returns the read and the write ends.
+pub fn pipe(flags: libc::c_int) -> io::Result<[OwnedFd; 2]> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), flags) } == -1 {
+        return Err(io::Error::last_os_error());
     }
+    Ok(fds.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }))
 }
 
-/// Deserialize the last complete object. Returns (`object`, `remaining`). See tests for examples.
-pub fn de_last_json<'a, T: Deserialize<'a>>(
-    mut s: &'a [u8],
-) -> Result<(Option<T>, &'a [u8]), JsonError> {
-    let mut last = None;
-    let mut tmp;
-    loop {
-        (tmp, s) = de_first_json(s)?;
-        last = match tmp {
-            Some(obj) => Some(obj),
-            None => return Ok((last, s)),
-        };
+/// Try to take an exclusive lock. Returns `false` if the lock is held by someone else.
+pub fn try_lock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() == ErrorKind::WouldBlock {
+            return Ok(false);
+        }
+        return Err(err);
     }
+    Ok(true)
 }
 
-/// Deserialize the first complete object. Returns (`object`, `remaining`). See tests for examples.
-pub fn de_first_json<'a, T: Deserialize<'a>>(
-    mut s: &'a [u8],
-) -> Result<(Option<T>, &'a [u8]), JsonError> {
-    while s
-        .first()
-        .is_some_and(|&x| x == b' ' || x == b',' || x == b'\n')
-    {
-        s = &s[1..];
-    }
-    let mut de = Deserializer::from_slice(s).into_iter();
-    match de.next() {
-        Some(Ok(obj)) => Ok((Some(obj), &s[de.byte_offset()..])),
-        Some(Err(e)) if e.is_eof() => Ok((None, &s[de.byte_offset()..])),
-        Some(Err(e)) => Err(e),
-        None => Ok((None, &s[de.byte_offset()..])),
+/// The name of the Wayland display, suitable for use in file names.
+pub fn wayland_display_name() -> String {
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".into());
+    // WAYLAND_DISPLAY may be an absolute path
+    match display.rsplit_once('/') {
+        Some((_, name)) => name.to_owned(),
+        None => display,
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    macro_rules! str {
-        ($str:expr) => {
-            &$str.as_bytes()[..]
-        };
-    }
-
-    #[test]
-    fn streaming_json() {
-        let s = b",[2]\n, [3], [4, 3],[32][3] ";
-        assert_eq!(
-            de_first_json::<Vec<u8>>(s).unwrap(),
-            (Some(vec![2]), str!("\n, [3], [4, 3],[32][3] "))
-        );
-        assert_eq!(
-            de_last_json::<Vec<u8>>(s).unwrap(),
-            (Some(vec![3]), str!(""))
-        );
-
-        let s = b",[2]\n, [3], [4, 3],[32][3] [2, 4";
-        assert_eq!(
-            de_last_json::<Vec<u8>>(s).unwrap(),
-            (Some(vec![3]), str!("[2, 4"))
-        );
+/// The commands spawned by [`spawn_command`] which have not exited yet.
+static SPAWNED: Mutex<Vec<Child>> = Mutex::new(Vec::new());
 
-        let s = b",[2]\n, [3], [4, 3],[32] invalid";
-        assert_eq!(
-            de_first_json::<Vec<u8>>(s).unwrap(),
-            (Some(vec![2]), str!("\n, [3], [4, 3],[32] invalid"))
-        );
-        assert!(de_last_json::<Vec<u8>>(s).is_err());
-    }
-
-    #[test]
-    fn test_last_line() {
-        let s = b"hello";
-        assert_eq!(last_line(s), None);
-
-        let s = b"hello\n";
-        assert_eq!(last_line(s), Some((str!("hello"), str!(""))));
-
-        let s = b"hello\nworld";
-        assert_eq!(last_line(s), Some((str!("hello"), str!("world"))));
+/// Run a shell command in the background. It is reaped by [`reap_children`] once it exits.
+pub fn spawn_command(cmd: &str) -> io::Result<()> {
+    spawn_command_with_env(cmd, &[])
+}
 
-        let s = b"hello\nworld\n";
-        assert_eq!(last_line(s), Some((str!("world"), str!(""))));
+/// Like [`spawn_command`], with additional environment variables.
+pub fn spawn_command_with_env(cmd: &str, env: &[(&str, &str)]) -> io::Result<()> {
+    let child = Command::new("sh")
+        .args(["-c", cmd])
+        .envs(env.iter().copied())
+        .stdin(Stdio::null())
+        .spawn()?;
+    SPAWNED.lock().unwrap().push(child);
+    Ok(())
+}
 
-        let s = b"hello\nworld\n...";
-        assert_eq!(last_line(s), Some((str!("world"), str!("..."))));
-    }
+/// Reap the commands spawned by [`spawn_command`] which have exited, so that they do not become
+/// zombies. Called on `SIGCHLD`.
+pub fn reap_children() {
+    SPAWNED
+        .lock()
+        .unwrap()
+        .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
 }
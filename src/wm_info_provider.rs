@@ -3,13 +3,15 @@ use std::any::Any;
 use wayrs_client::global::*;
 use wayrs_client::Connection;
 
-use crate::config::WmConfig;
+use crate::config::Config;
 use crate::event_loop::EventLoop;
 use crate::output::Output;
 use crate::pointer_btn::PointerBtn;
 use crate::protocol::*;
 use crate::state::State;
 
+pub use i3bar_river_core::tags::Tag;
+
 mod dummy;
 pub use dummy::*;
 
@@ -22,12 +24,18 @@ pub use hyprland::*;
 mod niri;
 pub use niri::*;
 
+mod ext_workspace;
+pub use ext_workspace::*;
+
 pub trait WmInfoProvider {
     fn register(&self, _: &mut EventLoop) {}
 
     fn new_ouput(&mut self, _: &mut Connection<State>, _: &Output) {}
     fn output_removed(&mut self, _: &mut Connection<State>, _: &Output) {}
 
+    fn seat_added(&mut self, _: &mut Connection<State>, _: WlSeat) {}
+    fn seat_removed(&mut self, _: &mut Connection<State>, _: WlSeat) {}
+
     fn get_tags(&self, _: &Output) -> Vec<Tag> {
         Vec::new()
     }
@@ -37,6 +45,11 @@ pub trait WmInfoProvider {
     fn get_mode_name(&self, _: &Output) -> Option<String> {
         None
     }
+    /// Whether `output` has the keyboard focus. Every output is focused as far as we know if the
+    /// window manager does not tell.
+    fn is_output_focused(&self, _: &Output) -> bool {
+        true
+    }
 
     fn click_on_tag(
         &mut self,
@@ -48,35 +61,97 @@ pub trait WmInfoProvider {
     ) {
     }
 
+    /// Called when tag `from` is dragged onto tag `to` with the left button.
+    fn drag_tag(
+        &mut self,
+        _conn: &mut Connection<State>,
+        _output: &Output,
+        _seat: WlSeat,
+        _from: u32,
+        _to: u32,
+    ) {
+    }
+
+    /// Called when the mode indicator is clicked.
+    fn click_on_mode(
+        &mut self,
+        _conn: &mut Connection<State>,
+        _output: &Output,
+        _seat: WlSeat,
+        _btn: PointerBtn,
+    ) {
+    }
+
     // TODO: remove once RFC3324 (dyn upcasting coercion) is stabilized
     fn as_any(&mut self) -> &mut dyn Any;
 }
 
+/// How the wheel scrolls through the tags, see `tags_scroll_wrap` in README.
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollConfig {
+    pub wrap: bool,
+    pub occupied_only: bool,
+}
+
+impl ScrollConfig {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            wrap: config.tags_scroll_wrap,
+            occupied_only: config.tags_scroll_occupied_only,
+        }
+    }
+
+    /// The index of the tag to switch to when scrolling from `current` among `len` tags, skipping
+    /// the unoccupied ones if configured so.
+    pub fn next_index(
+        self,
+        len: usize,
+        current: usize,
+        forward: bool,
+        is_occupied: impl Fn(usize) -> bool,
+    ) -> Option<usize> {
+        let mut i = current;
+        for _ in 1..len {
+            i = match (forward, i) {
+                (true, i) if i + 1 < len => i + 1,
+                (true, _) if self.wrap => 0,
+                (false, 1..) => i - 1,
+                (false, 0) if self.wrap => len - 1,
+                _ => return None,
+            };
+            if !self.occupied_only || is_occupied(i) {
+                return Some(i);
+            }
+        }
+        None
+    }
+}
+
 pub fn bind(
     conn: &mut Connection<State>,
     globals: &Globals,
-    config: &WmConfig,
+    config: &Config,
 ) -> Box<dyn WmInfoProvider> {
     if let Some(river) = RiverInfoProvider::bind(conn, globals, config) {
+        info!("using river info provider");
         return Box::new(river);
     }
 
-    if let Some(hyprland) = HyprlandInfoProvider::new() {
+    if let Some(hyprland) = HyprlandInfoProvider::new(config) {
+        info!("using hyprland info provider");
         return Box::new(hyprland);
     }
 
-    if let Some(niri) = NiriInfoProvider::new() {
+    if let Some(niri) = NiriInfoProvider::new(config) {
+        info!("using niri info provider");
         return Box::new(niri);
     }
 
-    Box::new(DummyInfoProvider)
-}
+    if let Some(ext_workspace) = ExtWorkspaceInfoProvider::bind(conn, globals, config) {
+        info!("using ext-workspace info provider");
+        return Box::new(ext_workspace);
+    }
 
-#[derive(Debug)]
-pub struct Tag {
-    pub id: u32,
-    pub name: String,
-    pub is_focused: bool,
-    pub is_active: bool,
-    pub is_urgent: bool,
+    info!("no supported window manager found");
+    Box::new(DummyInfoProvider)
 }
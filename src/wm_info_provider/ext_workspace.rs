@@ -0,0 +1,245 @@
+use wayrs_client::global::*;
+use wayrs_client::object::ObjectId;
+use wayrs_client::proxy::Proxy;
+use wayrs_client::EventCtx;
+
+use super::*;
+
+/// The workspaces of any compositor which implements ext-workspace-v1.
+pub struct ExtWorkspaceInfoProvider {
+    manager: ExtWorkspaceManagerV1,
+    groups: Vec<Group>,
+    workspaces: Vec<Workspace>,
+    /// The tag id of the next workspace.
+    next_id: u32,
+    scroll: ScrollConfig,
+}
+
+struct Group {
+    handle: ExtWorkspaceGroupHandleV1,
+    outputs: Vec<ObjectId>,
+}
+
+struct Workspace {
+    handle: ExtWorkspaceHandleV1,
+    id: u32,
+    group: Option<ObjectId>,
+    name: String,
+    coordinates: Vec<u32>,
+    state: ext_workspace_handle_v1::State,
+    capabilities: ext_workspace_handle_v1::WorkspaceCapabilities,
+}
+
+impl ExtWorkspaceInfoProvider {
+    pub fn bind(conn: &mut Connection<State>, globals: &Globals, config: &Config) -> Option<Self> {
+        Some(Self {
+            manager: globals.bind_with_cb(conn, 1, manager_cb).ok()?,
+            groups: Vec::new(),
+            workspaces: Vec::new(),
+            next_id: 1,
+            scroll: ScrollConfig::new(config),
+        })
+    }
+
+    /// The workspaces shown on `output`, in the order of their coordinates, then of their names.
+    fn output_workspaces(&self, output: &Output) -> Vec<&Workspace> {
+        let output_groups: Vec<ObjectId> = self
+            .groups
+            .iter()
+            .filter(|g| g.outputs.contains(&output.wl.id()))
+            .map(|g| g.handle.id())
+            .collect();
+        let mut workspaces: Vec<_> = self
+            .workspaces
+            .iter()
+            .filter(|ws| ws.group.is_some_and(|g| output_groups.contains(&g)))
+            .filter(|ws| !ws.state.contains(ext_workspace_handle_v1::State::Hidden))
+            .collect();
+        workspaces.sort_by(|a, b| {
+            a.coordinates
+                .cmp(&b.coordinates)
+                .then_with(|| a.name.cmp(&b.name))
+                .then(a.id.cmp(&b.id))
+        });
+        workspaces
+    }
+
+    fn activate(&self, conn: &mut Connection<State>, workspace: &Workspace) {
+        use ext_workspace_handle_v1::WorkspaceCapabilities;
+        if !workspace
+            .capabilities
+            .contains(WorkspaceCapabilities::Activate)
+        {
+            debug!("workspace {:?} cannot be activated", workspace.name);
+            return;
+        }
+        debug!("activating workspace {:?}", workspace.name);
+        workspace.handle.activate(conn);
+        self.manager.commit(conn);
+    }
+}
+
+impl WmInfoProvider for ExtWorkspaceInfoProvider {
+    fn get_tags(&self, output: &Output) -> Vec<Tag> {
+        use ext_workspace_handle_v1::State as WsState;
+        self.output_workspaces(output)
+            .into_iter()
+            .map(|ws| Tag {
+                id: ws.id,
+                name: ws.name.clone(),
+                is_focused: ws.state.contains(WsState::Active),
+                // The protocol does not tell which workspaces have windows
+                is_active: true,
+                is_urgent: ws.state.contains(WsState::Urgent),
+                always_shown: false,
+                windows: None,
+            })
+            .collect()
+    }
+
+    fn click_on_tag(
+        &mut self,
+        conn: &mut Connection<State>,
+        output: &Output,
+        _: WlSeat,
+        tag_id: Option<u32>,
+        btn: PointerBtn,
+    ) {
+        let workspaces = self.output_workspaces(output);
+        match btn {
+            PointerBtn::Left => {
+                if let Some(ws) = workspaces.iter().find(|ws| Some(ws.id) == tag_id) {
+                    self.activate(conn, ws);
+                }
+            }
+            PointerBtn::WheelUp | PointerBtn::WheelDown => {
+                let active = workspaces
+                    .iter()
+                    .position(|ws| ws.state.contains(ext_workspace_handle_v1::State::Active));
+                if let Some(active) = active {
+                    let next = self.scroll.next_index(
+                        workspaces.len(),
+                        active,
+                        btn == PointerBtn::WheelDown,
+                        |_| true,
+                    );
+                    if let Some(next) = next {
+                        self.activate(conn, workspaces[next]);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+fn manager_cb(ctx: EventCtx<State, ExtWorkspaceManagerV1>) {
+    use ext_workspace_manager_v1::Event;
+
+    let provider = ctx.state.shared_state.get_ext_workspace().unwrap();
+    match ctx.event {
+        Event::WorkspaceGroup(handle) => {
+            ctx.conn.set_callback_for(handle, group_cb);
+            provider.groups.push(Group {
+                handle,
+                outputs: Vec::new(),
+            });
+        }
+        Event::Workspace(handle) => {
+            ctx.conn.set_callback_for(handle, workspace_cb);
+            provider.workspaces.push(Workspace {
+                handle,
+                id: provider.next_id,
+                group: None,
+                name: String::new(),
+                coordinates: Vec::new(),
+                state: ext_workspace_handle_v1::State::empty(),
+                capabilities: ext_workspace_handle_v1::WorkspaceCapabilities::empty(),
+            });
+            provider.next_id += 1;
+        }
+        Event::Done => ctx.state.tags_updated(ctx.conn, None),
+        Event::Finished => {
+            warn!("the compositor stopped sending the workspaces");
+        }
+        _ => (),
+    }
+}
+
+fn group_cb(ctx: EventCtx<State, ExtWorkspaceGroupHandleV1>) {
+    use ext_workspace_group_handle_v1::Event;
+
+    let provider = ctx.state.shared_state.get_ext_workspace().unwrap();
+    let Some(index) = provider.groups.iter().position(|g| g.handle == ctx.proxy) else {
+        return;
+    };
+    let group = &mut provider.groups[index];
+    match ctx.event {
+        Event::OutputEnter(output) => group.outputs.push(output),
+        Event::OutputLeave(output) => group.outputs.retain(|o| *o != output),
+        Event::WorkspaceEnter(workspace) => {
+            if let Some(ws) = provider
+                .workspaces
+                .iter_mut()
+                .find(|ws| ws.handle.id() == workspace)
+            {
+                ws.group = Some(ctx.proxy.id());
+            }
+        }
+        Event::WorkspaceLeave(workspace) => {
+            if let Some(ws) = provider
+                .workspaces
+                .iter_mut()
+                .find(|ws| ws.handle.id() == workspace && ws.group == Some(ctx.proxy.id()))
+            {
+                ws.group = None;
+            }
+        }
+        Event::Removed => {
+            for ws in &mut provider.workspaces {
+                if ws.group == Some(ctx.proxy.id()) {
+                    ws.group = None;
+                }
+            }
+            provider.groups.swap_remove(index).handle.destroy(ctx.conn);
+        }
+        _ => (),
+    }
+}
+
+fn workspace_cb(ctx: EventCtx<State, ExtWorkspaceHandleV1>) {
+    use ext_workspace_handle_v1::Event;
+
+    let provider = ctx.state.shared_state.get_ext_workspace().unwrap();
+    let Some(index) = provider
+        .workspaces
+        .iter()
+        .position(|ws| ws.handle == ctx.proxy)
+    else {
+        return;
+    };
+    let ws = &mut provider.workspaces[index];
+    match ctx.event {
+        Event::Name(name) => ws.name = name.to_string_lossy().into_owned(),
+        Event::Coordinates(coordinates) => {
+            ws.coordinates = coordinates
+                .chunks_exact(4)
+                .map(|c| u32::from_ne_bytes(c.try_into().unwrap()))
+                .collect();
+        }
+        Event::State(state) => ws.state = state,
+        Event::Capabilities(capabilities) => ws.capabilities = capabilities,
+        Event::Removed => {
+            provider
+                .workspaces
+                .swap_remove(index)
+                .handle
+                .destroy(ctx.conn);
+        }
+        _ => (),
+    }
+}
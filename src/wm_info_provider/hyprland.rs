@@ -1,5 +1,3 @@
-#![allow(clippy::collapsible_else_if)]
-
 use std::io::{self, Write};
 use std::os::fd::AsRawFd;
 use std::os::unix::net::UnixStream;
@@ -15,10 +13,18 @@ pub struct HyprlandInfoProvider {
     ipc: Ipc,
     workspaces: Vec<IpcWorkspace>,
     active_name: String,
+    focused_monitor: Option<String>,
+    submap: Option<String>,
+    /// The urgent windows, by address without the "0x" prefix, and the ids of their workspaces.
+    urgent_windows: Vec<(String, i32)>,
+    always_show_workspaces: u32,
+    scroll_skip_special: bool,
+    tags_all_outputs: bool,
+    scroll: ScrollConfig,
 }
 
 impl HyprlandInfoProvider {
-    pub fn new() -> Option<Self> {
+    pub fn new(config: &Config) -> Option<Self> {
         let his = std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
         let ipc = Ipc::new(&his)?;
         Some(Self {
@@ -27,12 +33,61 @@ impl HyprlandInfoProvider {
                 .query_json::<IpcWorkspace>("j/activeworkspace")
                 .ok()?
                 .name,
+            focused_monitor: ipc
+                .query_json::<Vec<IpcMonitor>>("j/monitors")
+                .ok()?
+                .into_iter()
+                .find(|monitor| monitor.focused)
+                .map(|monitor| monitor.name),
+            submap: None,
+            urgent_windows: Vec::new(),
+            always_show_workspaces: config.wm.hyprland.always_show_workspaces,
+            scroll_skip_special: config.wm.hyprland.scroll_skip_special,
+            tags_all_outputs: config.tags_all_outputs,
+            scroll: ScrollConfig {
+                occupied_only: config.tags_scroll_occupied_only
+                    || config.wm.hyprland.scroll_skip_empty,
+                ..ScrollConfig::new(config)
+            },
             ipc,
         })
     }
 
+    fn is_urgent(&self, workspace_id: i32) -> bool {
+        self.urgent_windows
+            .iter()
+            .any(|(_, id)| *id == workspace_id)
+    }
+
+    /// Forget that the window at `address` is urgent. Returns whether it was.
+    fn clear_urgent(&mut self, address: &str) -> bool {
+        let len = self.urgent_windows.len();
+        self.urgent_windows.retain(|(a, _)| a != address);
+        self.urgent_windows.len() != len
+    }
+
     fn set_workspace(&self, id: u32) {
-        let _ = self.ipc.exec(&format!("/dispatch workspace {id}"));
+        debug!("switching to workspace {id}");
+        if let Err(e) = self.ipc.exec(&format!("/dispatch workspace {id}")) {
+            warn!("failed to switch workspace: {e}");
+        }
+    }
+
+    fn move_window_to_workspace(&self, id: u32) {
+        debug!("moving the active window to workspace {id}");
+        if let Err(e) = self.ipc.exec(&format!("/dispatch movetoworkspace {id}")) {
+            warn!("failed to move the window: {e}");
+        }
+    }
+
+    fn toggle_special_workspace(&self, name: &str) {
+        debug!("toggling special workspace {name}");
+        if let Err(e) = self
+            .ipc
+            .exec(&format!("/dispatch togglespecialworkspace {name}"))
+        {
+            warn!("failed to toggle special workspace: {e}");
+        }
     }
 }
 
@@ -50,17 +105,47 @@ impl WmInfoProvider for HyprlandInfoProvider {
     }
 
     fn get_tags(&self, output: &Output) -> Vec<Tag> {
-        self.workspaces
+        let mut tags: Vec<_> = self
+            .workspaces
             .iter()
-            .filter(|ws| ws.monitor == output.name)
+            .filter(|ws| !ws.is_special())
+            .filter(|ws| self.tags_all_outputs || ws.monitor == output.name)
             .map(|ws| Tag {
-                id: ws.id,
-                name: ws.name.clone(),
+                id: ws.id as u32,
+                name: if ws.monitor == output.name {
+                    ws.name.clone()
+                } else {
+                    format!("{}:{}", ws.monitor, ws.name)
+                },
                 is_focused: ws.name == self.active_name,
                 is_active: true,
-                is_urgent: false,
+                is_urgent: self.is_urgent(ws.id),
+                always_shown: false,
+                windows: Some(ws.windows),
             })
-            .collect()
+            .collect();
+
+        // Workspaces which do not exist yet are shown on every output, like Hyprland's persistent
+        // workspaces, until they are created on one of them.
+        tags.extend(
+            (1..=self.always_show_workspaces)
+                .filter(|&id| !self.workspaces.iter().any(|ws| ws.id == id as i32))
+                .map(|id| Tag {
+                    id,
+                    name: id.to_string(),
+                    is_focused: false,
+                    is_active: false,
+                    is_urgent: false,
+                    always_shown: true,
+                    windows: Some(0),
+                }),
+        );
+        tags.sort_by_key(|tag| tag.id);
+        tags
+    }
+
+    fn drag_tag(&mut self, _: &mut Connection<State>, _: &Output, _: WlSeat, _from: u32, to: u32) {
+        self.move_window_to_workspace(to);
     }
 
     fn click_on_tag(
@@ -78,25 +163,29 @@ impl WmInfoProvider for HyprlandInfoProvider {
                 }
             }
             PointerBtn::WheelUp | PointerBtn::WheelDown => {
-                if let Some(active_i) = self
+                let output_workspaces: Vec<_> = self
                     .workspaces
                     .iter()
-                    .position(|ws| ws.monitor == output.name && self.active_name == ws.name)
+                    .filter(|ws| ws.monitor == output.name)
+                    .filter(|ws| !self.scroll_skip_special || !ws.is_special())
+                    .collect();
+                if let Some(active_i) = output_workspaces
+                    .iter()
+                    .position(|ws| self.active_name == ws.name)
                 {
-                    if btn == PointerBtn::WheelUp {
-                        if let Some(prev) = self.workspaces[..active_i]
-                            .iter()
-                            .rfind(|ws| ws.monitor == output.name)
-                        {
-                            self.set_workspace(prev.id);
-                        }
-                    } else {
-                        if let Some(next) = self.workspaces[active_i..]
-                            .iter()
-                            .skip(1)
-                            .find(|ws| ws.monitor == output.name)
-                        {
-                            self.set_workspace(next.id);
+                    if let Some(next) = self.scroll.next_index(
+                        output_workspaces.len(),
+                        active_i,
+                        btn == PointerBtn::WheelDown,
+                        |i| output_workspaces[i].windows > 0,
+                    ) {
+                        let ws = output_workspaces[next];
+                        if ws.is_special() {
+                            // "special" is the unnamed special workspace
+                            let name = ws.name.strip_prefix("special:").unwrap_or_default();
+                            self.toggle_special_workspace(name);
+                        } else {
+                            self.set_workspace(ws.id as u32);
                         }
                     }
                 }
@@ -105,6 +194,25 @@ impl WmInfoProvider for HyprlandInfoProvider {
         }
     }
 
+    fn get_mode_name(&self, _output: &Output) -> Option<String> {
+        self.submap.clone()
+    }
+
+    fn is_output_focused(&self, output: &Output) -> bool {
+        self.focused_monitor
+            .as_ref()
+            .is_none_or(|monitor| *monitor == output.name)
+    }
+
+    fn click_on_mode(&mut self, _: &mut Connection<State>, _: &Output, _: WlSeat, btn: PointerBtn) {
+        if btn == PointerBtn::Left {
+            debug!("resetting submap");
+            if let Err(e) = self.ipc.exec("/dispatch submap reset") {
+                warn!("failed to reset submap: {e}");
+            }
+        }
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -113,19 +221,64 @@ impl WmInfoProvider for HyprlandInfoProvider {
 fn hyprland_cb(conn: &mut Connection<State>, state: &mut State) -> io::Result<()> {
     let hyprland = state.shared_state.get_hyprland().unwrap();
     let mut updated = false;
+    let mut submap_updated = false;
+    let mut focus_updated = false;
     loop {
         match hyprland.ipc.next_event() {
             Ok(event) => {
+                trace!("event: {event}");
                 if let Some(active_ws) = event.strip_prefix("workspace>>") {
                     hyprland.active_name = active_ws.to_owned();
                     updated = true;
                 } else if let Some(data) = event.strip_prefix("focusedmon>>") {
-                    let (_monitor, active_ws) = data.split_once(',').ok_or_else(|| {
+                    let (monitor, active_ws) = data.split_once(',').ok_or_else(|| {
                         io::Error::new(io::ErrorKind::InvalidData, "Too few fields in data")
                     })?;
                     hyprland.active_name = active_ws.to_owned();
+                    hyprland.focused_monitor = Some(monitor.to_owned());
                     updated = true;
-                } else if event.contains("workspace>>") {
+                    focus_updated = true;
+                } else if let Some(submap) = event.strip_prefix("submap>>") {
+                    hyprland.submap = (!submap.is_empty()).then(|| submap.to_owned());
+                    submap_updated = true;
+                } else if let Some(address) = event.strip_prefix("urgent>>") {
+                    let workspace = hyprland
+                        .ipc
+                        .query_json::<Vec<IpcClient>>("j/clients")?
+                        .into_iter()
+                        .find(|client| client.address.trim_start_matches("0x") == address)
+                        .map(|client| client.workspace.id);
+                    if let Some(workspace) = workspace {
+                        hyprland.clear_urgent(address);
+                        hyprland
+                            .urgent_windows
+                            .push((address.to_owned(), workspace));
+                        updated = true;
+                    }
+                } else if let Some(address) = event.strip_prefix("activewindowv2>>") {
+                    updated |= hyprland.clear_urgent(address);
+                } else if let Some(data) = event.strip_prefix("movewindowv2>>") {
+                    // The window moved, the workspaces are updated by the "movewindow" event
+                    let mut fields = data.split(',');
+                    let (Some(address), Some(Ok(workspace))) =
+                        (fields.next(), fields.next().map(str::parse))
+                    else {
+                        continue;
+                    };
+                    for (a, id) in &mut hyprland.urgent_windows {
+                        if a == address {
+                            *id = workspace;
+                            updated = true;
+                        }
+                    }
+                } else if event.contains("workspace>>")
+                    || event.starts_with("openwindow>>")
+                    || event.starts_with("closewindow>>")
+                    || event.starts_with("movewindow>>")
+                {
+                    if let Some(address) = event.strip_prefix("closewindow>>") {
+                        hyprland.clear_urgent(address);
+                    }
                     hyprland.workspaces = hyprland.ipc.query_sorted_workspaces()?;
                     updated = true;
                 }
@@ -137,6 +290,12 @@ fn hyprland_cb(conn: &mut Connection<State>, state: &mut State) -> io::Result<()
     if updated {
         state.tags_updated(conn, None);
     }
+    if submap_updated {
+        state.mode_name_updated(conn, None);
+    }
+    if focus_updated {
+        state.output_focus_updated(conn);
+    }
     Ok(())
 }
 
@@ -204,7 +363,31 @@ impl Ipc {
 
 #[derive(Debug, serde::Deserialize)]
 struct IpcWorkspace {
-    id: u32,
+    id: i32, // Special workspaces have negative ids.
     name: String,
     monitor: String,
+    windows: u32,
+}
+
+impl IpcWorkspace {
+    fn is_special(&self) -> bool {
+        self.id < 0
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct IpcClient {
+    address: String,
+    workspace: IpcClientWorkspace,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct IpcClientWorkspace {
+    id: i32,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct IpcMonitor {
+    name: String,
+    focused: bool,
 }
@@ -1,5 +1,3 @@
-#![allow(clippy::collapsible_else_if)]
-
 use std::io::{self, Write};
 use std::os::fd::AsRawFd;
 use std::os::unix::net::UnixStream;
@@ -14,22 +12,114 @@ use crate::utils::read_to_vec;
 pub struct NiriInfoProvider {
     ipc: Ipc,
     workspaces: Vec<IpcWorkspace>,
+    windows: Vec<IpcWindow>,
+    tags_all_outputs: bool,
+    always_show_workspaces: u32,
+    scroll: ScrollConfig,
 }
 
 impl NiriInfoProvider {
-    pub fn new() -> Option<Self> {
+    pub fn new(config: &Config) -> Option<Self> {
         let ns = std::env::var("NIRI_SOCKET").ok()?;
         let ipc = Ipc::new(&ns)?;
         Some(Self {
             workspaces: Vec::new(),
+            windows: Vec::new(),
+            tags_all_outputs: config.tags_all_outputs,
+            always_show_workspaces: config.wm.niri.always_show_workspaces,
+            scroll: ScrollConfig::new(config),
             ipc,
         })
     }
 
-    fn set_workspace(&self, idx: u32) {
-        let _ = self.ipc.exec(&format!(
-            r#"{{"Action":{{"FocusWorkspace":{{"reference":{{"Index":{idx}}}}}}}}}"#
-        ));
+    /// The reference to the workspace `id` in actions: its name if it has one, since named
+    /// workspaces are the ones configured in niri, or its id.
+    fn workspace_reference(&self, id: u32) -> String {
+        match self
+            .workspaces
+            .iter()
+            .find(|ws| ws.id == id)
+            .and_then(|ws| ws.name.as_ref())
+        {
+            Some(name) => format!(r#"{{"Name":{}}}"#, serde_json::Value::from(name.as_str())),
+            None => format!(r#"{{"Id":{id}}}"#),
+        }
+    }
+
+    fn set_workspace(&self, id: u32) {
+        let reference = self.workspace_reference(id);
+        debug!("switching to workspace {reference}");
+        if let Err(e) = self.ipc.exec(&format!(
+            r#"{{"Action":{{"FocusWorkspace":{{"reference":{reference}}}}}}}"#
+        )) {
+            warn!("failed to switch workspace: {e}");
+        }
+    }
+
+    fn move_workspace_to_index(&self, id: u32, idx: u32) {
+        let reference = self.workspace_reference(id);
+        debug!("moving workspace {reference} to index {idx}");
+        if let Err(e) = self.ipc.exec(&format!(
+            r#"{{"Action":{{"MoveWorkspaceToIndex":{{"index":{idx},"reference":{reference}}}}}}}"#
+        )) {
+            warn!("failed to move workspace: {e}");
+        }
+    }
+
+    fn move_window_to_workspace(&self, id: u32) {
+        let reference = self.workspace_reference(id);
+        debug!("moving the focused window to workspace {reference}");
+        if let Err(e) = self.ipc.exec(&format!(
+            r#"{{"Action":{{"MoveWindowToWorkspace":{{"window_id":null,"reference":{reference},"focus":false}}}}}}"#
+        )) {
+            warn!("failed to move the window: {e}");
+        }
+    }
+
+    /// The workspaces of `output`, with their labels prefixed with the output name if `prefixed`.
+    fn output_tags(&self, output: &str, prefixed: bool) -> Vec<Tag> {
+        // Niri always generates an empty workspace rather than having an explicit workspace
+        // creation command, so we make the last workspace active only if the user is looking at
+        // it. This makes the behavior of `hide_inactive_tags` useful for Niri. Because we're
+        // looking for the last element, we have to create an intermediate vector to get the
+        // length.
+        let output_workspaces: Vec<_> = self
+            .workspaces
+            .iter()
+            .filter(|ws| ws.output == output)
+            .collect();
+        output_workspaces
+            .iter()
+            .enumerate()
+            .map(|(i, ws)| {
+                let name = ws.name.clone().map_or_else(
+                    || ws.idx.to_string(),
+                    |name| format!("{0} / {1}", ws.idx, name),
+                );
+                Tag {
+                    id: ws.id,
+                    name: if prefixed {
+                        format!("{output}:{name}")
+                    } else {
+                        name
+                    },
+                    is_focused: ws.is_active,
+                    is_active: i < output_workspaces.len() - 1 || ws.is_focused,
+                    is_urgent: ws.is_urgent
+                        || self
+                            .windows
+                            .iter()
+                            .any(|w| w.is_urgent && w.workspace_id == Some(ws.id)),
+                    always_shown: ws.idx <= self.always_show_workspaces,
+                    windows: Some(
+                        self.windows
+                            .iter()
+                            .filter(|w| w.workspace_id == Some(ws.id))
+                            .count() as u32,
+                    ),
+                }
+            })
+            .collect()
     }
 }
 
@@ -47,32 +137,38 @@ impl WmInfoProvider for NiriInfoProvider {
     }
 
     fn get_tags(&self, output: &Output) -> Vec<Tag> {
-        // Niri always generates an empty workspace rather than having an explicit workspace
-        // creation command, so we make the last workspace active only if the user is looking at
-        // it. This makes the behavior of `hide_inactive_tags` useful for Niri. Because we're
-        // looking for the last element, we have to create an intermediate vector to get the
-        // length.
-        let output_workspaces: Vec<_> = self
+        if !self.tags_all_outputs {
+            return self.output_tags(&output.name, false);
+        }
+        // The workspaces of this output come first, then the other outputs in order
+        let mut outputs: Vec<&str> = self
             .workspaces
             .iter()
-            .filter(|ws| ws.output == output.name)
+            .map(|ws| ws.output.as_str())
             .collect();
-        output_workspaces
-            .iter()
-            .enumerate()
-            .map(|(i, ws)| Tag {
-                id: ws.idx,
-                name: ws.name.clone().map_or_else(
-                    || ws.idx.to_string(),
-                    |name| format!("{0} / {1}", ws.idx, name),
-                ),
-                is_focused: ws.is_active,
-                is_active: i < output_workspaces.len() - 1 || ws.is_focused,
-                is_urgent: false,
-            })
+        outputs.sort_unstable();
+        outputs.dedup();
+        outputs.sort_by_key(|&o| o != output.name);
+        outputs
+            .into_iter()
+            .flat_map(|o| self.output_tags(o, o != output.name))
             .collect()
     }
 
+    fn is_output_focused(&self, output: &Output) -> bool {
+        // The focused workspace is on the focused output
+        self.workspaces
+            .iter()
+            .find(|ws| ws.is_focused)
+            .is_none_or(|ws| ws.output == output.name)
+    }
+
+    fn drag_tag(&mut self, _: &mut Connection<State>, _: &Output, _: WlSeat, from: u32, to: u32) {
+        if let Some(target) = self.workspaces.iter().find(|ws| ws.id == to) {
+            self.move_workspace_to_index(from, target.idx);
+        }
+    }
+
     fn click_on_tag(
         &mut self,
         _: &mut Connection<State>,
@@ -87,27 +183,28 @@ impl WmInfoProvider for NiriInfoProvider {
                     self.set_workspace(tag_id);
                 }
             }
+            PointerBtn::Middle => {
+                if let Some(tag_id) = tag_id {
+                    self.move_window_to_workspace(tag_id);
+                }
+            }
             PointerBtn::WheelUp | PointerBtn::WheelDown => {
-                if let Some(active_i) = self
+                let output_workspaces: Vec<_> = self
                     .workspaces
                     .iter()
-                    .position(|ws| ws.output == output.name && ws.is_focused)
-                {
-                    if btn == PointerBtn::WheelUp {
-                        if let Some(prev) = self.workspaces[..active_i]
-                            .iter()
-                            .rfind(|ws| ws.output == output.name)
-                        {
-                            self.set_workspace(prev.idx);
-                        }
-                    } else {
-                        if let Some(next) = self.workspaces[active_i..]
-                            .iter()
-                            .skip(1)
-                            .find(|ws| ws.output == output.name)
-                        {
-                            self.set_workspace(next.idx);
-                        }
+                    .filter(|ws| ws.output == output.name)
+                    .collect();
+                if let Some(active_i) = output_workspaces.iter().position(|ws| ws.is_focused) {
+                    if let Some(next) = self.scroll.next_index(
+                        output_workspaces.len(),
+                        active_i,
+                        btn == PointerBtn::WheelDown,
+                        |i| {
+                            let id = output_workspaces[i].id;
+                            self.windows.iter().any(|w| w.workspace_id == Some(id))
+                        },
+                    ) {
+                        self.set_workspace(output_workspaces[next].id);
                     }
                 }
             }
@@ -151,6 +248,33 @@ fn niri_cb(conn: &mut Connection<State>, state: &mut State) -> io::Result<()> {
                     }
                 }
             }
+            Ok(IpcEvent::WindowsChanged { windows }) => {
+                niri.windows = windows;
+                updated = true;
+            }
+            Ok(IpcEvent::WindowOpenedOrChanged { window }) => {
+                match niri.windows.iter_mut().find(|w| w.id == window.id) {
+                    Some(w) => *w = window,
+                    None => niri.windows.push(window),
+                }
+                updated = true;
+            }
+            Ok(IpcEvent::WindowClosed { id }) => {
+                niri.windows.retain(|w| w.id != id);
+                updated = true;
+            }
+            Ok(IpcEvent::WorkspaceUrgencyChanged { id, urgent }) => {
+                if let Some(ws) = niri.workspaces.iter_mut().find(|ws| ws.id == id) {
+                    ws.is_urgent = urgent;
+                    updated = true;
+                }
+            }
+            Ok(IpcEvent::WindowUrgencyChanged { id, urgent }) => {
+                if let Some(w) = niri.windows.iter_mut().find(|w| w.id == id) {
+                    w.is_urgent = urgent;
+                    updated = true;
+                }
+            }
             Ok(IpcEvent::Ok(_)) => continue,
             Ok(IpcEvent::Ignored(_)) => continue,
             Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
@@ -159,6 +283,7 @@ fn niri_cb(conn: &mut Connection<State>, state: &mut State) -> io::Result<()> {
     }
     if updated {
         state.tags_updated(conn, None);
+        state.output_focus_updated(conn);
     }
     Ok(())
 }
@@ -195,6 +320,7 @@ impl Ipc {
             if let Some(i) = memchr::memchr(b'\n', &self.sock_buf) {
                 let event = String::from_utf8_lossy(&self.sock_buf[..i]).into_owned();
                 self.sock_buf.drain(..=i);
+                trace!("event: {event}");
                 return Ok(serde_json::from_str(&event)?);
             }
             if read_to_vec(&self.sock, &mut self.sock_buf)? == 0 {
@@ -209,12 +335,22 @@ impl Ipc {
 
 #[derive(Debug, serde::Deserialize)]
 struct IpcWorkspace {
-    id: u32,  // Niri's internal id is monotonic, only used for comparison.
+    id: u32,  // Niri's internal id is monotonic, unique across outputs.
     idx: u32, // idx is the user-facing workspace number.
     name: Option<String>,
     output: String,
     is_focused: bool,
     is_active: bool, // Niri's is_active means the workspace is visible on a display.
+    #[serde(default)] // Since niri 25.05
+    is_urgent: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct IpcWindow {
+    id: u64,
+    workspace_id: Option<u32>,
+    #[serde(default)]
+    is_urgent: bool,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -227,6 +363,23 @@ enum IpcEvent {
         id: u32,
         focused: bool,
     },
+    WindowsChanged {
+        windows: Vec<IpcWindow>,
+    },
+    WindowOpenedOrChanged {
+        window: IpcWindow,
+    },
+    WindowClosed {
+        id: u64,
+    },
+    WorkspaceUrgencyChanged {
+        id: u32,
+        urgent: bool,
+    },
+    WindowUrgencyChanged {
+        id: u64,
+        urgent: bool,
+    },
     #[serde(untagged)]
     Ignored(IgnoredAny),
 }
@@ -1,17 +1,33 @@
+use std::collections::HashMap;
 use std::ffi::CString;
 
 use wayrs_client::global::*;
-use wayrs_client::proxy::Proxy;
 use wayrs_client::EventCtx;
 
 use super::*;
 
+/// The id of the tag which shows the combination of the focused tags.
+const COMBINATION_TAG_ID: u32 = 0;
+
 pub struct RiverInfoProvider {
     status_manager: ZriverStatusManagerV1,
     control: ZriverControlV1,
     output_statuses: Vec<OutputStatus>,
     max_tag: u8,
-    seat_status: SeatStatus,
+    /// The `max_tag` of the outputs with overrides.
+    output_max_tag: HashMap<String, u8>,
+    tag_names: Vec<String>,
+    /// The `tag_names` of the outputs with overrides.
+    output_tag_names: HashMap<String, Vec<String>>,
+    always_show_tags: u8,
+    show_focused_combination: bool,
+    back_and_forth: bool,
+    /// The river commands run by clicks on the tags, instead of the default ones.
+    on_left_click: Option<String>,
+    on_middle_click: Option<String>,
+    on_right_click: Option<String>,
+    scroll: ScrollConfig,
+    seat_statuses: Vec<SeatStatus>,
 }
 
 struct OutputStatus {
@@ -20,40 +36,72 @@ struct OutputStatus {
     focused_tags: u32,
     urgent_tags: u32,
     active_tags: u32,
+    /// The number of views on each tag.
+    window_counts: [u32; 32],
     layout_name: Option<String>,
 }
 
 struct SeatStatus {
-    _status: ZriverSeatStatusV1,
+    seat: WlSeat,
+    status: ZriverSeatStatusV1,
     mode: Option<String>,
+    focused_output: Option<WlOutput>,
 }
 
 impl RiverInfoProvider {
-    pub fn bind(
-        conn: &mut Connection<State>,
-        globals: &Globals,
-        config: &WmConfig,
-    ) -> Option<Self> {
+    pub fn bind(conn: &mut Connection<State>, globals: &Globals, config: &Config) -> Option<Self> {
         let status_manager: ZriverStatusManagerV1 = globals.bind(conn, 1..=4).ok()?;
-        let wl_seat: WlSeat = globals.bind(conn, ..=5).ok()?; // river supports just one seat
-        let seat_status =
-            status_manager.get_river_seat_status_with_cb(conn, wl_seat, seat_status_cb);
-        if wl_seat.version() >= 5 {
-            wl_seat.release(conn);
-        }
         Some(Self {
             status_manager,
             control: globals.bind(conn, 1).ok()?,
             output_statuses: Vec::new(),
-            max_tag: config.river.max_tag,
-            seat_status: SeatStatus {
-                _status: seat_status,
-                mode: None,
+            max_tag: config.wm.river.max_tag,
+            output_max_tag: config
+                .output
+                .keys()
+                .map(|name| (name.clone(), config.river_max_tag(name)))
+                .collect(),
+            tag_names: config.wm.river.tag_names.clone(),
+            output_tag_names: config
+                .output
+                .keys()
+                .map(|name| (name.clone(), config.river_tag_names(name).to_vec()))
+                .collect(),
+            always_show_tags: config.wm.river.always_show_tags,
+            show_focused_combination: config.wm.river.show_focused_combination,
+            back_and_forth: config.wm.river.back_and_forth,
+            on_left_click: config.wm.river.on_left_click.clone(),
+            on_middle_click: config.wm.river.on_middle_click.clone(),
+            on_right_click: config.wm.river.on_right_click.clone(),
+            scroll: ScrollConfig {
+                occupied_only: config.tags_scroll_occupied_only
+                    || config.wm.river.scroll_occupied_only,
+                ..ScrollConfig::new(config)
             },
+            seat_statuses: Vec::new(),
         })
     }
 
+    /// The number of tags shown on `output`, at most 32.
+    fn max_tag(&self, output: &Output) -> u32 {
+        let max_tag = self.output_max_tag.get(&output.name).copied();
+        u32::min(max_tag.unwrap_or(self.max_tag) as u32, 32)
+    }
+
+    /// The name of `tag` on `output`, its number unless `tag_names` has one.
+    fn tag_name(&self, output: &Output, tag: u32) -> String {
+        let names = self
+            .output_tag_names
+            .get(&output.name)
+            .unwrap_or(&self.tag_names);
+        match names.get(tag as usize - 1) {
+            Some(name) => name.clone(),
+            None => tag.to_string(),
+        }
+    }
+
     fn set_focused_tags(&self, seat: WlSeat, conn: &mut Connection<State>, tags: u32) {
+        debug!("set-focused-tags {tags}");
         self.control
             .add_argument(conn, c"set-focused-tags".to_owned());
         self.control
@@ -61,6 +109,24 @@ impl RiverInfoProvider {
         self.control
             .run_command_with_cb(conn, seat, river_command_cb);
     }
+
+    pub fn run_command(&self, conn: &mut Connection<State>, seat: WlSeat, args: &[String]) {
+        debug!("river command: {args:?}");
+        // river queues the arguments until the next command, so none is sent unless all are valid
+        let Ok(args) = args
+            .iter()
+            .map(|arg| CString::new(arg.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            warn!("river command argument contains a nul byte: {args:?}");
+            return;
+        };
+        for arg in args {
+            self.control.add_argument(conn, arg);
+        }
+        self.control
+            .run_command_with_cb(conn, seat, river_command_cb);
+    }
 }
 
 impl WmInfoProvider for RiverInfoProvider {
@@ -74,10 +140,29 @@ impl WmInfoProvider for RiverInfoProvider {
             focused_tags: 0,
             urgent_tags: 0,
             active_tags: 0,
+            window_counts: [0; 32],
             layout_name: None,
         });
     }
 
+    fn seat_added(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        let status = self
+            .status_manager
+            .get_river_seat_status_with_cb(conn, seat, seat_status_cb);
+        self.seat_statuses.push(SeatStatus {
+            seat,
+            status,
+            mode: None,
+            focused_output: None,
+        });
+    }
+
+    fn seat_removed(&mut self, conn: &mut Connection<State>, seat: WlSeat) {
+        if let Some(i) = self.seat_statuses.iter().position(|s| s.seat == seat) {
+            self.seat_statuses.swap_remove(i).status.destroy(conn);
+        }
+    }
+
     fn output_removed(&mut self, conn: &mut Connection<State>, output: &Output) {
         let index = self
             .output_statuses
@@ -92,15 +177,33 @@ impl WmInfoProvider for RiverInfoProvider {
         let Some(status) = self.output_statuses.iter().find(|s| s.output == output.wl) else {
             return Vec::new();
         };
-        (1..=u8::min(self.max_tag, 32))
+        let mut tags: Vec<_> = (1..=self.max_tag(output))
             .map(|tag| Tag {
-                id: tag as u32,
-                name: tag.to_string(),
+                id: tag,
+                name: self.tag_name(output, tag),
                 is_focused: status.focused_tags & (1 << (tag - 1)) != 0,
                 is_active: status.active_tags & (1 << (tag - 1)) != 0,
                 is_urgent: status.urgent_tags & (1 << (tag - 1)) != 0,
+                always_shown: tag <= self.always_show_tags as u32,
+                windows: Some(status.window_counts[tag as usize - 1]),
             })
-            .collect()
+            .collect();
+        if self.show_focused_combination && status.focused_tags.count_ones() > 1 {
+            let focused: Vec<_> = (1..=32)
+                .filter(|tag| status.focused_tags & (1 << (tag - 1)) != 0)
+                .map(|tag| self.tag_name(output, tag))
+                .collect();
+            tags.push(Tag {
+                id: COMBINATION_TAG_ID,
+                name: focused.join("+"),
+                is_focused: true,
+                is_active: true,
+                is_urgent: false,
+                always_shown: true,
+                windows: None,
+            });
+        }
+        tags
     }
 
     fn get_layout_name(&self, output: &Output) -> Option<String> {
@@ -111,8 +214,46 @@ impl WmInfoProvider for RiverInfoProvider {
         status.layout_name.clone()
     }
 
-    fn get_mode_name(&self, _output: &Output) -> Option<String> {
-        self.seat_status.mode.clone()
+    /// The mode of the seat which focuses `output`, or of the first seat.
+    fn get_mode_name(&self, output: &Output) -> Option<String> {
+        self.seat_statuses
+            .iter()
+            .find(|s| s.focused_output == Some(output.wl))
+            .or(self.seat_statuses.first())?
+            .mode
+            .clone()
+    }
+
+    /// Whether any seat focuses `output`.
+    fn is_output_focused(&self, output: &Output) -> bool {
+        self.seat_statuses
+            .iter()
+            .all(|s| s.focused_output.is_none())
+            || self
+                .seat_statuses
+                .iter()
+                .any(|s| s.focused_output == Some(output.wl))
+    }
+
+    fn drag_tag(
+        &mut self,
+        conn: &mut Connection<State>,
+        _: &Output,
+        seat: WlSeat,
+        _from: u32,
+        to: u32,
+    ) {
+        // The press on the first tag has focused it, so this sends one of its views
+        if to == COMBINATION_TAG_ID {
+            return;
+        }
+        let tags = 1u32 << (to - 1);
+        debug!("set-view-tags {tags}");
+        self.control.add_argument(conn, c"set-view-tags".to_owned());
+        self.control
+            .add_argument(conn, CString::new(tags.to_string()).unwrap());
+        self.control
+            .run_command_with_cb(conn, seat, river_command_cb);
     }
 
     fn click_on_tag(
@@ -123,10 +264,48 @@ impl WmInfoProvider for RiverInfoProvider {
         tag_id: Option<u32>,
         btn: PointerBtn,
     ) {
+        if tag_id == Some(COMBINATION_TAG_ID) {
+            if btn == PointerBtn::Left {
+                if let Some(status) = self.output_statuses.iter().find(|s| s.output == output.wl) {
+                    self.set_focused_tags(seat, conn, status.focused_tags);
+                }
+            }
+            return;
+        }
+
+        let command = match btn {
+            PointerBtn::Left => self.on_left_click.as_deref(),
+            PointerBtn::Middle => self.on_middle_click.as_deref(),
+            PointerBtn::Right => self.on_right_click.as_deref(),
+            _ => None,
+        };
+        if let (Some(command), Some(tag_id)) = (command, tag_id) {
+            let tags = (1u32 << (tag_id - 1)).to_string();
+            let args: Vec<String> = command
+                .split_whitespace()
+                .map(|arg| arg.replace("%d", &tags))
+                .collect();
+            self.run_command(conn, seat, &args);
+            return;
+        }
+
         match btn {
             PointerBtn::Left => {
                 if let Some(tag_id) = tag_id {
-                    self.set_focused_tags(seat, conn, 1u32 << (tag_id - 1));
+                    let tags = 1u32 << (tag_id - 1);
+                    let already_focused = self
+                        .output_statuses
+                        .iter()
+                        .any(|s| s.output == output.wl && s.focused_tags == tags);
+                    if self.back_and_forth && already_focused {
+                        debug!("focus-previous-tags");
+                        self.control
+                            .add_argument(conn, c"focus-previous-tags".to_owned());
+                        self.control
+                            .run_command_with_cb(conn, seat, river_command_cb);
+                    } else {
+                        self.set_focused_tags(seat, conn, tags);
+                    }
                 }
             }
             PointerBtn::Right => {
@@ -142,22 +321,61 @@ impl WmInfoProvider for RiverInfoProvider {
                 }
             }
             PointerBtn::WheelUp | PointerBtn::WheelDown => {
-                if let Some(status) = self.output_statuses.iter().find(|s| s.output == output.wl) {
+                let Some(status) = self.output_statuses.iter().find(|s| s.output == output.wl)
+                else {
+                    return;
+                };
+                let focused = status.focused_tags;
+                let new_tags = if self.scroll.wrap || self.scroll.occupied_only {
+                    // Move from the first or the last focused tag to a single tag
+                    let len = self.max_tag(output);
+                    if len == 0 {
+                        return;
+                    }
+                    let forward = btn == PointerBtn::WheelDown;
+                    let current = if forward {
+                        focused.checked_ilog2().unwrap_or(0)
+                    } else {
+                        focused.trailing_zeros()
+                    };
+                    let Some(next) = self.scroll.next_index(
+                        len as usize,
+                        u32::min(current, len - 1) as usize,
+                        forward,
+                        |i| status.active_tags & (1 << i) != 0,
+                    ) else {
+                        return;
+                    };
+                    1 << next
+                } else {
                     let mut new_tags = if btn == PointerBtn::WheelUp {
-                        status.focused_tags >> 1
+                        focused >> 1
                     } else {
-                        status.focused_tags << 1
+                        focused << 1
                     };
                     if new_tags == 0 {
-                        new_tags |= status.focused_tags & 0x8000_0001;
+                        new_tags |= focused & 0x8000_0001;
                     }
-                    self.set_focused_tags(seat, conn, new_tags);
-                }
+                    new_tags
+                };
+                self.set_focused_tags(seat, conn, new_tags);
             }
             _ => (),
         }
     }
 
+    fn click_on_mode(
+        &mut self,
+        conn: &mut Connection<State>,
+        _output: &Output,
+        seat: WlSeat,
+        btn: PointerBtn,
+    ) {
+        if btn == PointerBtn::Left {
+            self.run_command(conn, seat, &["enter-mode".into(), "normal".into()]);
+        }
+    }
+
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
@@ -173,16 +391,22 @@ fn output_status_cb(ctx: EventCtx<State, ZriverOutputStatusV1>) {
     let output = status.output;
 
     use zriver_output_status_v1::Event;
+    trace!("output status: {:?}", ctx.event);
     match ctx.event {
         Event::FocusedTags(tags) => {
             status.focused_tags = tags;
             ctx.state.tags_updated(ctx.conn, Some(output));
         }
         Event::ViewTags(tags) => {
-            status.active_tags = tags
-                .chunks_exact(4)
-                .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
-                .fold(0, |a, b| a | b);
+            status.active_tags = 0;
+            status.window_counts = [0; 32];
+            for bytes in tags.chunks_exact(4) {
+                let view_tags = u32::from_ne_bytes(bytes.try_into().unwrap());
+                status.active_tags |= view_tags;
+                for (i, count) in status.window_counts.iter_mut().enumerate() {
+                    *count += (view_tags >> i) & 1;
+                }
+            }
             ctx.state.tags_updated(ctx.conn, Some(output));
         }
         Event::UrgentTags(tags) => {
@@ -201,11 +425,42 @@ fn output_status_cb(ctx: EventCtx<State, ZriverOutputStatusV1>) {
 }
 
 fn seat_status_cb(ctx: EventCtx<State, ZriverSeatStatusV1>) {
-    if let zriver_seat_status_v1::Event::Mode(mode) = ctx.event {
-        let river = ctx.state.shared_state.get_river().unwrap();
-        let mode = mode.to_string_lossy().into_owned();
-        river.seat_status.mode = (mode != "normal").then_some(mode);
-        ctx.state.mode_name_updated(ctx.conn, None);
+    use zriver_seat_status_v1::Event;
+
+    let river = ctx.state.shared_state.get_river().unwrap();
+    // The mode shown on an output depends on the seat which focuses it
+    let multi_seat = river.seat_statuses.len() > 1;
+    let Some(seat_status) = river
+        .seat_statuses
+        .iter_mut()
+        .find(|s| s.status == ctx.proxy)
+    else {
+        return;
+    };
+    match ctx.event {
+        Event::Mode(mode) => {
+            let mode = mode.to_string_lossy().into_owned();
+            seat_status.mode = (mode != "normal").then_some(mode);
+            ctx.state.mode_name_updated(ctx.conn, None);
+        }
+        Event::FocusedOutput(output) => {
+            seat_status.focused_output = Some(output);
+            if multi_seat {
+                ctx.state.mode_name_updated(ctx.conn, None);
+            }
+            ctx.state.output_focus_updated(ctx.conn);
+        }
+        Event::UnfocusedOutput(output) => {
+            // Only unset if another output has not been focused already
+            if seat_status.focused_output == Some(output) {
+                seat_status.focused_output = None;
+                if multi_seat {
+                    ctx.state.mode_name_updated(ctx.conn, None);
+                }
+                ctx.state.output_focus_updated(ctx.conn);
+            }
+        }
+        _ => (),
     }
 }
 
@@ -0,0 +1,94 @@
+//! End-to-end tests which run the bar against a mock compositor.
+
+mod mock_compositor;
+
+use std::fs;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use mock_compositor::MockCompositor;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Kills the bar when dropped.
+struct Bar(Child);
+
+impl Drop for Bar {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start the bar and wait until it has rendered a frame of the given size.
+fn start_bar(mock: &mut MockCompositor, config: &str, width: u32) -> Bar {
+    let config_path = mock.dir().join("config.toml");
+    fs::write(&config_path, config).unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_i3bar-river"))
+        .arg("--config")
+        .arg(&config_path)
+        .env("XDG_RUNTIME_DIR", mock.dir())
+        .env("WAYLAND_DISPLAY", mock_compositor::SOCKET_NAME)
+        .env_remove("WAYLAND_SOCKET")
+        .env_remove("HYPRLAND_INSTANCE_SIGNATURE")
+        .env_remove("NIRI_SOCKET")
+        .spawn()
+        .unwrap();
+    let bar = Bar(child);
+
+    mock.accept();
+    assert!(mock.wait_until(TIMEOUT, |m| m.layer_surface.is_some()
+        && m.pointer.is_some()));
+    mock.configure(width, 0);
+    assert!(mock.wait_until(TIMEOUT, |m| m.buffer_commits > 0));
+
+    bar
+}
+
+#[test]
+fn click_on_tags() {
+    let mut mock = MockCompositor::new("tags");
+    mock.focused_tags = 0b11;
+    let _bar = start_bar(&mut mock, "tags_padding = 100\n", 1000);
+
+    // Each tag is at least 200 pixels wide
+    mock.click(5.0, 10.0);
+    assert!(mock.wait_until(TIMEOUT, |m| m.river_commands.len() == 1));
+    assert_eq!(mock.river_commands[0], ["set-focused-tags", "1"]);
+
+    mock.click(250.0, 10.0);
+    assert!(mock.wait_until(TIMEOUT, |m| m.river_commands.len() == 2));
+    assert_eq!(mock.river_commands[1], ["set-focused-tags", "2"]);
+}
+
+#[test]
+fn click_on_blocks() {
+    let mut mock = MockCompositor::new("blocks");
+    let clicks_path = mock.dir().join("clicks");
+    let script = format!(
+        r#"echo '{{"version":1,"click_events":true}}'; echo '['; echo '[{{"full_text":"A","name":"a"}},{{"full_text":"B","name":"b"}}],'; while read -r line; do echo "$line" >> '{}'; done"#,
+        clicks_path.display()
+    );
+    let config = format!("command = {}\n", toml_string(&script));
+    let _bar = start_bar(&mut mock, &config, 1000);
+
+    // The blocks may arrive after the first frame, so retry until the click reaches the command
+    let mut clicks = String::new();
+    for _ in 0..20 {
+        mock.click(999.0, 10.0);
+        mock.dispatch_for(Duration::from_millis(100));
+        clicks = fs::read_to_string(&clicks_path).unwrap_or_default();
+        if !clicks.is_empty() {
+            break;
+        }
+    }
+
+    let first_click = clicks.lines().next().expect("no click events");
+    assert!(first_click.contains(r#""name":"b""#), "{first_click}");
+    assert!(first_click.contains(r#""button":1"#), "{first_click}");
+}
+
+fn toml_string(s: &str) -> String {
+    format!("'''{s}'''")
+}
@@ -0,0 +1,419 @@
+//! A minimal Wayland compositor for end-to-end tests.
+//!
+//! Only the subset of the wire protocol and of the globals used by the bar is implemented: layer
+//! shell, shm, a seat with a pointer, a single output and river status/control. Requests to
+//! objects the mock does not care about are silently ignored.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const SOCKET_NAME: &str = "wayland-mock";
+pub const OUTPUT_NAME: &str = "MOCK-1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interface {
+    Display,
+    Registry,
+    Compositor,
+    Surface,
+    Shm,
+    ShmPool,
+    Seat,
+    Output,
+    LayerShell,
+    LayerSurface,
+    Viewporter,
+    CursorShapeManager,
+    RiverStatusManager,
+    RiverControl,
+}
+
+const GLOBALS: &[(&str, u32, Interface)] = &[
+    ("wl_compositor", 4, Interface::Compositor),
+    ("wl_shm", 1, Interface::Shm),
+    ("wl_seat", 7, Interface::Seat),
+    ("wl_output", 4, Interface::Output),
+    ("zwlr_layer_shell_v1", 4, Interface::LayerShell),
+    ("wp_viewporter", 1, Interface::Viewporter),
+    (
+        "wp_cursor_shape_manager_v1",
+        1,
+        Interface::CursorShapeManager,
+    ),
+    ("zriver_status_manager_v1", 4, Interface::RiverStatusManager),
+    ("zriver_control_v1", 1, Interface::RiverControl),
+];
+
+const BTN_LEFT: u32 = 0x110;
+
+pub struct MockCompositor {
+    dir: PathBuf,
+    listener: UnixListener,
+    client: Option<UnixStream>,
+    buf: Vec<u8>,
+    objects: HashMap<u32, Interface>,
+    serial: u32,
+    pending_buffer: u32,
+    frame_callbacks: Vec<u32>,
+
+    /// The tags reported as focused on the output.
+    pub focused_tags: u32,
+    /// The layer surface and its `wl_surface`.
+    pub layer_surface: Option<(u32, u32)>,
+    pub pointer: Option<u32>,
+    /// The number of commits with a buffer attached.
+    pub buffer_commits: usize,
+    /// Commands executed via river control.
+    pub river_commands: Vec<Vec<String>>,
+    pending_args: Vec<String>,
+}
+
+impl MockCompositor {
+    /// Create a compositor listening in a new temporary directory.
+    pub fn new(test_name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "i3bar-river-test-{}-{test_name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let listener = UnixListener::bind(dir.join(SOCKET_NAME)).unwrap();
+        Self {
+            dir,
+            listener,
+            client: None,
+            buf: Vec::new(),
+            objects: HashMap::from([(1, Interface::Display)]),
+            serial: 0,
+            pending_buffer: 0,
+            frame_callbacks: Vec::new(),
+            focused_tags: 1,
+            layer_surface: None,
+            pointer: None,
+            buffer_commits: 0,
+            river_commands: Vec::new(),
+            pending_args: Vec::new(),
+        }
+    }
+
+    /// The directory to be used as `XDG_RUNTIME_DIR`.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn accept(&mut self) {
+        let (stream, _) = self.listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        self.client = Some(stream);
+    }
+
+    /// Process requests until `cond` returns `true`. Returns `false` on timeout.
+    pub fn wait_until(&mut self, timeout: Duration, cond: impl Fn(&Self) -> bool) -> bool {
+        let start = Instant::now();
+        while !cond(self) {
+            if start.elapsed() > timeout {
+                return false;
+            }
+            self.dispatch();
+        }
+        true
+    }
+
+    /// Process the requests for a while.
+    pub fn dispatch_for(&mut self, duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            self.dispatch();
+        }
+    }
+
+    pub fn configure(&mut self, width: u32, height: u32) {
+        let (layer_surface, _) = self.layer_surface.expect("no layer surface");
+        let serial = self.next_serial();
+        self.send(
+            Msg::new(layer_surface, 0)
+                .uint(serial)
+                .uint(width)
+                .uint(height),
+        );
+    }
+
+    /// Click with the left button at the given surface-local position.
+    pub fn click(&mut self, x: f64, y: f64) {
+        let pointer = self.pointer.expect("no pointer");
+        let (_, surface) = self.layer_surface.expect("no layer surface");
+        let serial = self.next_serial();
+        self.send(
+            Msg::new(pointer, 0)
+                .uint(serial)
+                .uint(surface)
+                .fixed(x)
+                .fixed(y),
+        );
+        self.send(Msg::new(pointer, 5));
+        for state in [1, 0] {
+            let serial = self.next_serial();
+            self.send(
+                Msg::new(pointer, 3)
+                    .uint(serial)
+                    .uint(0)
+                    .uint(BTN_LEFT)
+                    .uint(state),
+            );
+            self.send(Msg::new(pointer, 5));
+        }
+        let serial = self.next_serial();
+        self.send(Msg::new(pointer, 1).uint(serial).uint(surface));
+        self.send(Msg::new(pointer, 5));
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        self.serial += 1;
+        self.serial
+    }
+
+    fn send(&mut self, msg: Msg) {
+        let client = self.client.as_mut().expect("no client");
+        // The client may have already exited
+        let _ = client.write_all(&msg.finish());
+    }
+
+    fn dispatch(&mut self) {
+        let Some(client) = &mut self.client else {
+            std::thread::sleep(Duration::from_millis(10));
+            return;
+        };
+
+        let mut chunk = [0; 4096];
+        match client.read(&mut chunk) {
+            Ok(0) => {
+                self.client = None;
+                return;
+            }
+            Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return,
+            Err(e) => panic!("{e}"),
+        }
+
+        while self.buf.len() >= 8 {
+            let obj = u32::from_ne_bytes(self.buf[0..4].try_into().unwrap());
+            let word = u32::from_ne_bytes(self.buf[4..8].try_into().unwrap());
+            let size = (word >> 16) as usize;
+            let opcode = (word & 0xffff) as u16;
+            if self.buf.len() < size {
+                break;
+            }
+            let payload = self.buf[8..size].to_vec();
+            self.buf.drain(..size);
+            self.handle_request(obj, opcode, Args(&payload));
+        }
+    }
+
+    fn handle_request(&mut self, obj: u32, opcode: u16, mut args: Args) {
+        let Some(&interface) = self.objects.get(&obj) else {
+            return;
+        };
+
+        match (interface, opcode) {
+            // wl_display.sync
+            (Interface::Display, 0) => {
+                let callback = args.uint();
+                self.send(Msg::new(callback, 0).uint(0));
+                self.send(Msg::new(1, 1).uint(callback));
+            }
+            // wl_display.get_registry
+            (Interface::Display, 1) => {
+                let registry = args.uint();
+                self.objects.insert(registry, Interface::Registry);
+                for (i, (name, version, _)) in GLOBALS.iter().enumerate() {
+                    self.send(
+                        Msg::new(registry, 0)
+                            .uint(i as u32 + 1)
+                            .string(name)
+                            .uint(*version),
+                    );
+                }
+            }
+            // wl_registry.bind
+            (Interface::Registry, 0) => {
+                let name = args.uint();
+                let _interface = args.string();
+                let _version = args.uint();
+                let id = args.uint();
+                let interface = GLOBALS[name as usize - 1].2;
+                self.objects.insert(id, interface);
+                self.on_bind(id, interface);
+            }
+            // wl_compositor.create_surface
+            (Interface::Compositor, 0) => {
+                self.objects.insert(args.uint(), Interface::Surface);
+            }
+            // wl_surface.attach
+            (Interface::Surface, 1) => {
+                self.pending_buffer = args.uint();
+            }
+            // wl_surface.frame
+            (Interface::Surface, 3) => {
+                self.frame_callbacks.push(args.uint());
+            }
+            // wl_surface.commit
+            (Interface::Surface, 6) => {
+                if self.pending_buffer != 0 {
+                    self.buffer_commits += 1;
+                    // wl_buffer.release
+                    self.send(Msg::new(self.pending_buffer, 0));
+                    self.pending_buffer = 0;
+                }
+                for callback in std::mem::take(&mut self.frame_callbacks) {
+                    self.send(Msg::new(callback, 0).uint(0));
+                    self.send(Msg::new(1, 1).uint(callback));
+                }
+            }
+            // wl_shm.create_pool
+            (Interface::Shm, 0) => {
+                self.objects.insert(args.uint(), Interface::ShmPool);
+            }
+            // wl_seat.get_pointer
+            (Interface::Seat, 0) => {
+                self.pointer = Some(args.uint());
+            }
+            // zwlr_layer_shell_v1.get_layer_surface
+            (Interface::LayerShell, 0) => {
+                let id = args.uint();
+                let surface = args.uint();
+                self.objects.insert(id, Interface::LayerSurface);
+                self.layer_surface = Some((id, surface));
+            }
+            // zriver_status_manager_v1.get_river_output_status
+            (Interface::RiverStatusManager, 1) => {
+                let id = args.uint();
+                self.send(Msg::new(id, 0).uint(self.focused_tags));
+                self.send(Msg::new(id, 1).array(&self.focused_tags.to_ne_bytes()));
+            }
+            // zriver_control_v1.add_argument
+            (Interface::RiverControl, 1) => {
+                let arg = args.string();
+                self.pending_args.push(arg);
+            }
+            // zriver_control_v1.run_command
+            (Interface::RiverControl, 2) => {
+                let _seat = args.uint();
+                let callback = args.uint();
+                let cmd = std::mem::take(&mut self.pending_args);
+                self.river_commands.push(cmd);
+                self.send(Msg::new(callback, 0).string(""));
+                self.send(Msg::new(1, 1).uint(callback));
+            }
+            _ => (),
+        }
+    }
+
+    fn on_bind(&mut self, id: u32, interface: Interface) {
+        match interface {
+            Interface::Shm => {
+                // argb8888 and xrgb8888
+                self.send(Msg::new(id, 0).uint(0));
+                self.send(Msg::new(id, 0).uint(1));
+            }
+            Interface::Seat => {
+                // pointer capability
+                self.send(Msg::new(id, 0).uint(1));
+                self.send(Msg::new(id, 1).string("seat0"));
+            }
+            Interface::Output => {
+                self.send(Msg::new(id, 3).int(1));
+                self.send(Msg::new(id, 4).string(OUTPUT_NAME));
+                self.send(Msg::new(id, 2));
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Drop for MockCompositor {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// An outgoing event.
+struct Msg {
+    obj: u32,
+    opcode: u16,
+    args: Vec<u8>,
+}
+
+impl Msg {
+    fn new(obj: u32, opcode: u16) -> Self {
+        Self {
+            obj,
+            opcode,
+            args: Vec::new(),
+        }
+    }
+
+    fn uint(mut self, value: u32) -> Self {
+        self.args.extend_from_slice(&value.to_ne_bytes());
+        self
+    }
+
+    fn int(mut self, value: i32) -> Self {
+        self.args.extend_from_slice(&value.to_ne_bytes());
+        self
+    }
+
+    fn fixed(self, value: f64) -> Self {
+        self.int((value * 256.0) as i32)
+    }
+
+    fn string(self, value: &str) -> Self {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.array(&bytes)
+    }
+
+    fn array(mut self, value: &[u8]) -> Self {
+        self.args
+            .extend_from_slice(&(value.len() as u32).to_ne_bytes());
+        self.args.extend_from_slice(value);
+        while self.args.len() % 4 != 0 {
+            self.args.push(0);
+        }
+        self
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let size = 8 + self.args.len() as u32;
+        let mut buf = Vec::with_capacity(size as usize);
+        buf.extend_from_slice(&self.obj.to_ne_bytes());
+        buf.extend_from_slice(&((size << 16) | self.opcode as u32).to_ne_bytes());
+        buf.extend_from_slice(&self.args);
+        buf
+    }
+}
+
+/// Arguments of an incoming request.
+struct Args<'a>(&'a [u8]);
+
+impl Args<'_> {
+    fn uint(&mut self) -> u32 {
+        let value = u32::from_ne_bytes(self.0[..4].try_into().unwrap());
+        self.0 = &self.0[4..];
+        value
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.uint() as usize;
+        let padded = (len + 3) & !3;
+        let value = String::from_utf8_lossy(&self.0[..len.saturating_sub(1)]).into_owned();
+        self.0 = &self.0[padded..];
+        value
+    }
+}